@@ -86,6 +86,9 @@ fn print_file(filename: &str) -> Result<()> {
     if let Some(v) = tags.id3v2 {
         avail_tags.push(format!("ID3v{}", v.header().version));
     }
+    if let Some(v) = tags.ape {
+        avail_tags.push(format!("APEv{}", v.version()));
+    }
     if tags.vcomment.is_some() {
         avail_tags.push("Vorbis Comment".into());
     }
@@ -98,8 +101,17 @@ fn print_file(filename: &str) -> Result<()> {
     print_opt_line("Date", tags.date());
 
     match format {
+        FormatRef::Aiff(_) => {},
         FormatRef::Flac(v) => {},
+        FormatRef::Mac(_) => {},
+        FormatRef::Mp4(_) => {},
         FormatRef::Mpeg(v) => print_mpeg(&v),
+        FormatRef::OggFlac(_) => {},
+        FormatRef::OggOpus(_) => {},
+        FormatRef::OggSpeex(_) => {},
+        FormatRef::OggVorbis(_) => {},
+        FormatRef::Wav(_) => {},
+        FormatRef::WavPack(_) => {},
         FormatRef::__Nonexhaustive => unreachable!(),
     }
 
@@ -0,0 +1,373 @@
+use byteorder::{ByteOrder, BE};
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use crate::error::*;
+use crate::fourcc::FourCC;
+use crate::id3::v2::Id3v2;
+use crate::tags::TagsRef;
+use crate::util::*;
+use crate::util::chunk::{Chunk, decode_str};
+
+const FORM: FourCC = FourCC(*b"FORM");
+const AIFF: FourCC = FourCC(*b"AIFF");
+const AIFC: FourCC = FourCC(*b"AIFC");
+const COMM: FourCC = FourCC(*b"COMM");
+const SSND: FourCC = FourCC(*b"SSND");
+const NAME: FourCC = FourCC(*b"NAME");
+const AUTH: FourCC = FourCC(*b"AUTH");
+const ANNO: FourCC = FourCC(*b"ANNO");
+const COPYRIGHT: FourCC = FourCC(*b"(c) ");
+const ID3: FourCC = FourCC(*b"ID3 ");
+const ID3_LOWER: FourCC = FourCC(*b"id3 ");
+
+/// The `COMM` chunk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Comm {
+    pub channel_count: u16,
+    pub len_frames: u32,
+    pub bits_per_sample: u16,
+    pub samples_per_sec: u32,
+
+    /// These are set for AIFF-C only.
+    pub compression_type: Option<FourCC>,
+    pub compression_name: Option<String>,
+}
+
+impl Comm {
+    /// Compression types that store uncompressed PCM.
+    const PCM_TYPES: [FourCC; 4] = [
+        FourCC(*b"NONE"),
+        FourCC(*b"sowt"),
+        FourCC(*b"twos"),
+        FourCC(*b"raw "),
+    ];
+
+    pub fn is_pcm(&self) -> bool {
+        self.compression_type.map(|v| Self::PCM_TYPES.contains(&v)).unwrap_or(true)
+    }
+
+    fn decode(buf: &[u8], aifc: bool) -> Result<Self> {
+        if buf.len() < 18 {
+            return Err(Error("COMM chunk is truncated"));
+        }
+        let channel_count = BE::read_u16(&buf[0..2]);
+        let samples_per_sec = decode_extended(&buf[8..18])
+            .filter(|&v| v >= 1.0 && v <= u32::MAX as f64)
+            .ok_or(Error("invalid sample rate"))?
+            .round() as u32;
+        if channel_count == 0 {
+            return Err(Error("bad COMM chunk"));
+        }
+        let (compression_type, compression_name) = if aifc {
+            if buf.len() < 23 {
+                return Err(Error("AIFC COMM chunk is truncated"));
+            }
+            let name_len = buf[22] as usize;
+            let name = buf.get(23..23 + name_len).ok_or(Error("AIFC COMM chunk is truncated"))?;
+            (Some(FourCC([buf[18], buf[19], buf[20], buf[21]])), Some(decode_str(name)))
+        } else {
+            (None, None)
+        };
+        Ok(Self {
+            channel_count,
+            len_frames: BE::read_u32(&buf[2..6]),
+            bits_per_sample: BE::read_u16(&buf[6..8]),
+            samples_per_sec,
+            compression_type,
+            compression_name,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Aiff {
+    comm: Comm,
+    sound_len: u64,
+    name: Option<String>,
+    author: Option<String>,
+    copyright: Option<String>,
+    annotations: Vec<String>,
+    id3v2: Option<Id3v2>,
+}
+
+impl Aiff {
+    pub fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
+        let file_len = rd.seek(SeekFrom::End(0))?;
+        rd.seek(SeekFrom::Start(0))?;
+
+        let mut hdr = [0; 12];
+        rd.read_exact(&mut hdr)?;
+        let kind = FourCC([hdr[8], hdr[9], hdr[10], hdr[11]]);
+        if hdr[..4] != FORM.0 || (kind != AIFF && kind != AIFC) {
+            return Err(Error("not an AIFF file").into_invalid_data_err());
+        }
+        let end = cmp::min(file_len, 8 + BE::read_u32(&hdr[4..8]) as u64);
+
+        let mut comm = None;
+        let mut sound_len = None;
+        let mut name = None;
+        let mut author = None;
+        let mut copyright = None;
+        let mut annotations = Vec::new();
+        let mut id3v2 = None;
+
+        let mut pos = 12;
+        while let Some(chunk) = Chunk::read::<BE>(&mut rd, pos, end)? {
+            let read_str = |rd: &mut _| chunk.read_data(rd).map(|v| decode_str(&v));
+
+            // Only `COMM` is required, the optional chunks that can't be decoded are ignored.
+            match chunk.id {
+                COMM if comm.is_none() => {
+                    comm = Some(Comm::decode(&chunk.read_data(&mut rd)?, kind == AIFC)
+                        .map_err(|e| e.into_invalid_data_err())?);
+                }
+                SSND if sound_len.is_none() => {
+                    // Streamed files may have the len exceeding the file.
+                    let len = cmp::min(chunk.len, chunk.avail);
+                    sound_len = Some(if len >= 8 {
+                        let mut buf = [0; 8];
+                        rd.read_exact(&mut buf)?;
+                        len.saturating_sub(8 + BE::read_u32(&buf[..4]) as u64)
+                    } else {
+                        0
+                    });
+                }
+                NAME if name.is_none() => name = read_str(&mut rd).into_opt()?,
+                AUTH if author.is_none() => author = read_str(&mut rd).into_opt()?,
+                COPYRIGHT if copyright.is_none() => copyright = read_str(&mut rd).into_opt()?,
+                ANNO => annotations.extend(read_str(&mut rd).into_opt()?),
+                ID3 | ID3_LOWER if id3v2.is_none() => {
+                    id3v2 = Id3v2::read(&mut rd, Some(cmp::min(chunk.len, chunk.avail)))
+                        .into_opt()?
+                        .map(|(v, _)| v);
+                }
+                _ => {}
+            }
+
+            pos = chunk.next_pos();
+        }
+
+        let comm = comm.ok_or_else(|| Error("missing COMM chunk").into_invalid_data_err())?;
+
+        Ok(Self {
+            comm,
+            sound_len: sound_len.unwrap_or(0),
+            name,
+            author,
+            copyright,
+            annotations,
+            id3v2,
+        })
+    }
+
+    pub fn comm(&self) -> &Comm {
+        &self.comm
+    }
+
+    /// Len of the sound data in bytes.
+    pub fn sound_len(&self) -> u64 {
+        self.sound_len
+    }
+
+    pub fn duration(&self) -> Duration {
+        duration(self.comm.len_frames as u64, self.comm.samples_per_sec)
+    }
+
+    pub fn bits_per_sec(&self) -> Option<u32> {
+        if self.comm.is_pcm() {
+            self.comm.samples_per_sec.checked_mul(self.comm.channel_count as u32)
+                .and_then(|v| v.checked_mul(self.comm.bits_per_sample as u32))
+        } else {
+            bits_per_sec(self.sound_len, self.duration())
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    pub fn copyright(&self) -> Option<&str> {
+        self.copyright.as_deref()
+    }
+
+    pub fn annotations(&self) -> impl Iterator<Item=&str> {
+        self.annotations.iter().map(|v| v.as_str())
+    }
+
+    pub fn id3v2(&self) -> Option<&Id3v2> {
+        self.id3v2.as_ref()
+    }
+
+    pub fn tags(&self) -> TagsRef<'_> {
+        TagsRef {
+            id3v2: self.id3v2.as_ref(),
+            aiff: Some(self),
+            .. Default::default()
+        }
+    }
+}
+
+/// Decodes 80-bit IEEE 754 extended precision number.
+fn decode_extended(buf: &[u8]) -> Option<f64> {
+    let exp = BE::read_u16(&buf[..2]);
+    let mantissa = BE::read_u64(&buf[2..10]);
+    let sign = if exp & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (exp & 0x7fff) as i32;
+    if exp == 0x7fff {
+        return None;
+    }
+    Some(sign * mantissa as f64 * 2f64.powi(exp - 16383 - 63))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    const RATE_44100: &[u8] = b"\x40\x0e\xac\x44\0\0\0\0\0\0";
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut r = id.to_vec();
+        r.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        r.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            r.push(0);
+        }
+        r
+    }
+
+    fn file(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let data = chunks.concat();
+        let mut r = b"FORM".to_vec();
+        r.extend_from_slice(&(4 + data.len() as u32).to_be_bytes());
+        r.extend_from_slice(kind);
+        r.extend_from_slice(&data);
+        r
+    }
+
+    fn comm(len_frames: u32, compression: &[u8]) -> Vec<u8> {
+        let mut r = 2u16.to_be_bytes().to_vec();
+        r.extend_from_slice(&len_frames.to_be_bytes());
+        r.extend_from_slice(&16u16.to_be_bytes());
+        r.extend_from_slice(RATE_44100);
+        r.extend_from_slice(compression);
+        r
+    }
+
+    fn ssnd(len: usize) -> Vec<u8> {
+        let mut r = vec![0; 8];
+        r.resize(8 + len, 0);
+        r
+    }
+
+    #[test]
+    fn decode_extended_float() {
+        assert_eq!(decode_extended(RATE_44100), Some(44100.0));
+        assert_eq!(decode_extended(b"\x40\x0c\xfa\0\0\0\0\0\0\0"), Some(16000.0));
+        assert_eq!(decode_extended(b"\x7f\xff\0\0\0\0\0\0\0\0"), None);
+    }
+
+    #[test]
+    fn read() {
+        let mut id3 = crate::id3::v2::Id3v2::new(crate::id3::Version::V2_3);
+        id3.frames_mut().set_text(crate::id3::frame::FrameId::TITLE, vec!["title"]);
+        let id3 = id3.encode(&Default::default()).unwrap();
+
+        let bytes = file(b"AIFF", &[
+            chunk(b"COMM", &comm(88200, b"")),
+            chunk(b"NAME", b"name"),
+            chunk(b"AUTH", b"auth\xe9"),
+            chunk(b"ANNO", b"a1"),
+            chunk(b"ANNO", b"a2"),
+            chunk(b"(c) ", b"2020"),
+            chunk(b"SSND", &ssnd(88200 * 4)),
+            chunk(b"ID3 ", &id3),
+        ]);
+        let aiff = Aiff::read(Cursor::new(&bytes)).unwrap();
+        assert_eq!(aiff.comm(), &Comm {
+            channel_count: 2,
+            len_frames: 88200,
+            bits_per_sample: 16,
+            samples_per_sec: 44100,
+            compression_type: None,
+            compression_name: None,
+        });
+        assert_eq!(aiff.sound_len(), 88200 * 4);
+        assert_eq!(aiff.duration(), Duration::from_secs(2));
+        assert_eq!(aiff.bits_per_sec(), Some(44100 * 32));
+        assert_eq!(aiff.name(), Some("name"));
+        assert_eq!(aiff.author(), Some("auth\u{e9}"));
+        assert_eq!(aiff.copyright(), Some("2020"));
+        assert_eq!(aiff.annotations().collect::<Vec<_>>(), vec!["a1", "a2"]);
+        assert_eq!(aiff.tags().title(), Some("title".into()));
+    }
+
+    #[test]
+    fn read_aifc() {
+        let bytes = file(b"AIFC", &[
+            chunk(b"FVER", b"\xa2\x80\x51\x40"),
+            chunk(b"COMM", &comm(44100, b"ima4\x0dIMA 4:1 ADPCM")),
+            chunk(b"SSND", &ssnd(1000)),
+        ]);
+        let aiff = Aiff::read(Cursor::new(&bytes)).unwrap();
+        assert_eq!(aiff.comm().compression_type, Some(FourCC(*b"ima4")));
+        assert_eq!(aiff.comm().compression_name.as_deref(), Some("IMA 4:1 ADPCM"));
+        assert!(!aiff.comm().is_pcm());
+        assert_eq!(aiff.duration(), Duration::from_secs(1));
+        assert_eq!(aiff.bits_per_sec(), Some(8000));
+        assert_eq!(aiff.tags().title(), None);
+    }
+
+    #[test]
+    fn tags_fallback_to_name_auth() {
+        let bytes = file(b"AIFF", &[
+            chunk(b"COMM", &comm(0, b"")),
+            chunk(b"NAME", b"name"),
+            chunk(b"AUTH", b"auth"),
+        ]);
+        let aiff = Aiff::read(Cursor::new(&bytes)).unwrap();
+        assert_eq!(aiff.tags().title(), Some("name".into()));
+        assert_eq!(aiff.tags().artist(), Some("auth".into()));
+    }
+
+    #[test]
+    fn read_bad_form() {
+        assert_eq!(Aiff::read(Cursor::new(&b"FORM\0\0\0\x04AIFF"[..])).unwrap_err().kind(),
+            io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_bad_optional_chunks() {
+        let mut auth = b"AUTH".to_vec();
+        auth.extend_from_slice(&100u32.to_be_bytes());
+        auth.extend_from_slice(b"auth");
+        let bytes = file(b"AIFF", &[
+            chunk(b"COMM", &comm(1, b"")),
+            chunk(b"NAME", b"name"),
+            chunk(b"SSND", &[0; 4]),
+            auth,
+        ]);
+        let aiff = Aiff::read(Cursor::new(&bytes)).unwrap();
+        assert_eq!(aiff.sound_len(), 0);
+        assert_eq!(aiff.name(), Some("name"));
+        assert_eq!(aiff.author(), None);
+    }
+
+    #[test]
+    fn bits_per_sec_overflow() {
+        let mut comm = comm(1, b"");
+        // 4294967294 Hz.
+        comm[8..18].copy_from_slice(b"\x40\x1e\xff\xff\xff\xfe\0\0\0\0");
+        let aiff = Aiff::read(Cursor::new(file(b"AIFF", &[chunk(b"COMM", &comm)]))).unwrap();
+        assert_eq!(aiff.comm().samples_per_sec, 4294967294);
+        assert_eq!(aiff.bits_per_sec(), None);
+    }
+}
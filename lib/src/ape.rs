@@ -0,0 +1,552 @@
+use bit_field::BitField;
+use byteorder::{ByteOrder, LE};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use crate::error::*;
+use crate::id3::v1::Id3v1;
+use crate::lyrics3::Lyrics3;
+use crate::timestamp::Timestamp;
+use crate::util::*;
+use crate::util::file::{copy_range, rewrite_file};
+
+const MAGIC: &[u8] = b"APETAGEX";
+const HEADER_LEN: usize = 32;
+
+/// Max tag len, larger tags are considered corrupted.
+const MAX_LEN: u64 = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Version {
+    V1,
+    V2,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Version::V1 => "1",
+            Version::V2 => "2",
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+    /// UTF-8 text, multiple values are separated by null chars.
+    Text(String),
+    Binary(Vec<u8>),
+    /// UTF-8 link to the external data.
+    Locator(String),
+}
+
+impl Value {
+    pub fn text(s: impl Into<String>) -> Self {
+        Value::Text(s.into())
+    }
+
+    /// Joins the `values` with null chars.
+    pub fn texts_from<'a>(values: impl IntoIterator<Item=&'a str>) -> Self {
+        Value::Text(values.into_iter().collect::<Vec<_>>().join("\0"))
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        if let Value::Text(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the null-separated text values.
+    pub fn texts(&self) -> impl Iterator<Item=&str> {
+        self.as_text().into_iter().flat_map(|v| v.split('\0'))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Item {
+    pub key: String,
+    pub value: Value,
+    pub read_only: bool,
+}
+
+impl Item {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<()> {
+        let key = self.key.as_bytes();
+        if !(2..=255).contains(&key.len())
+            || key.iter().any(|&c| !(0x20..=0x7e).contains(&c))
+            || ["ID3", "TAG", "OggS", "MP+"].iter().any(|v| self.key.eq_ignore_ascii_case(v))
+        {
+            return Err(Error("invalid APE item key"));
+        }
+        let (kind, value) = match &self.value {
+            Value::Text(v) => (0, v.as_bytes()),
+            Value::Binary(v) => (1, &v[..]),
+            Value::Locator(v) => (2, v.as_bytes()),
+        };
+        if value.len() as u64 > MAX_LEN {
+            return Err(Error("APE item value is too long"));
+        }
+        let mut flags = 0u32;
+        flags.set_bit(0, self.read_only);
+        flags.set_bits(1..3, kind);
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(key);
+        out.push(0);
+        out.extend_from_slice(value);
+        Ok(())
+    }
+}
+
+/// The flags and len of the tag as stored in the header or footer.
+struct Header {
+    version: Version,
+    /// Len of the items and the footer.
+    len: u32,
+    item_count: u32,
+    flags: u32,
+}
+
+impl Header {
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if !buf.starts_with(MAGIC) {
+            return Err(Error("bad APE tag magic"));
+        }
+        let version = match LE::read_u32(&buf[8..12]) {
+            1000 => Version::V1,
+            2000 => Version::V2,
+            _ => return Err(Error("unsupported APE tag version")),
+        };
+        let len = LE::read_u32(&buf[12..16]);
+        if (len as usize) < HEADER_LEN {
+            return Err(Error("bad APE tag len"));
+        }
+        Ok(Self {
+            version,
+            len,
+            item_count: LE::read_u32(&buf[16..20]),
+            flags: if version == Version::V1 { 0 } else { LE::read_u32(&buf[20..24]) },
+        })
+    }
+
+    fn has_header(&self) -> bool {
+        self.flags.get_bit(31)
+    }
+
+    fn is_header(&self) -> bool {
+        self.flags.get_bit(29)
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&match self.version {
+            Version::V1 => 1000u32,
+            Version::V2 => 2000,
+        }.to_le_bytes());
+        out.extend_from_slice(&self.len.to_le_bytes());
+        out.extend_from_slice(&self.item_count.to_le_bytes());
+        out.extend_from_slice(&self.flags.to_le_bytes());
+        out.extend_from_slice(&[0; 8]);
+    }
+}
+
+/// APEv1 or APEv2 tag.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ape {
+    version: Version,
+    read_only: bool,
+    items: Vec<Item>,
+}
+
+impl Default for Ape {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ape {
+    pub const TITLE: &'static str = "Title";
+    pub const ARTIST: &'static str = "Artist";
+    pub const ALBUM: &'static str = "Album";
+    pub const ALBUM_ARTIST: &'static str = "Album Artist";
+    pub const COMMENT: &'static str = "Comment";
+    pub const GENRE: &'static str = "Genre";
+    pub const TRACK: &'static str = "Track";
+    pub const YEAR: &'static str = "Year";
+    pub const REPLAYGAIN_TRACK_GAIN: &'static str = "REPLAYGAIN_TRACK_GAIN";
+    pub const REPLAYGAIN_TRACK_PEAK: &'static str = "REPLAYGAIN_TRACK_PEAK";
+    pub const REPLAYGAIN_ALBUM_GAIN: &'static str = "REPLAYGAIN_ALBUM_GAIN";
+    pub const REPLAYGAIN_ALBUM_PEAK: &'static str = "REPLAYGAIN_ALBUM_PEAK";
+    pub const MP3GAIN_MINMAX: &'static str = "MP3GAIN_MINMAX";
+    pub const MP3GAIN_ALBUM_MINMAX: &'static str = "MP3GAIN_ALBUM_MINMAX";
+    pub const MP3GAIN_UNDO: &'static str = "MP3GAIN_UNDO";
+
+    /// Creates an empty APEv2 tag.
+    pub fn new() -> Self {
+        Self {
+            version: Version::V2,
+            read_only: false,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn set_read_only(&mut self, v: bool) {
+        self.read_only = v;
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Sets the item value replacing the existing item with the same key, the position of
+    /// the existing item is kept.
+    pub fn set(&mut self, key: impl Into<String>, value: Value) {
+        let key = key.into();
+        if let Some(item) = self.items.iter_mut().find(|v| v.key.eq_ignore_ascii_case(&key)) {
+            item.key = key;
+            item.value = value;
+        } else {
+            self.items.push(Item {
+                key,
+                value,
+                read_only: false,
+            });
+        }
+    }
+
+    pub fn set_text(&mut self, key: impl Into<String>, s: impl Into<String>) {
+        self.set(key, Value::text(s));
+    }
+
+    /// Removes the item with `key`.
+    pub fn remove(&mut self, key: &str) -> Option<Item> {
+        let i = self.items.iter().position(|v| v.key.eq_ignore_ascii_case(key))?;
+        Some(self.items.remove(i))
+    }
+
+    /// Returns the item by its key, the keys are case-insensitive.
+    pub fn get(&self, key: &str) -> Option<&Item> {
+        self.items.iter().find(|v| v.key.eq_ignore_ascii_case(key))
+    }
+
+    /// Returns the first text value of the item.
+    pub fn get_text(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|v| v.value.texts().next())
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.get_text(Self::TITLE)
+    }
+
+    pub fn artist(&self) -> Option<&str> {
+        self.get_text(Self::ARTIST)
+    }
+
+    pub fn album(&self) -> Option<&str> {
+        self.get_text(Self::ALBUM)
+    }
+
+    pub fn genre(&self) -> Option<&str> {
+        self.get_text(Self::GENRE)
+    }
+
+    pub fn date(&self) -> Option<Timestamp> {
+        self.get_text(Self::YEAR).and_then(|s| s.trim().parse().ok())
+    }
+
+    /// Encodes the tag as APEv2 with both header and footer.
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        self.encode0().map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))
+    }
+
+    fn encode0(&self) -> Result<Vec<u8>> {
+        let mut items = Vec::new();
+        for item in &self.items {
+            item.encode(&mut items)?;
+        }
+        let len = items.len() as u64 + HEADER_LEN as u64;
+        if len > MAX_LEN {
+            return Err(Error("APE tag is too long"));
+        }
+        let mut header = Header {
+            version: Version::V2,
+            len: len as u32,
+            item_count: self.items.len() as u32,
+            flags: 0,
+        };
+        header.flags.set_bit(0, self.read_only);
+        header.flags.set_bit(31, true);
+
+        let mut r = Vec::with_capacity(len as usize + HEADER_LEN);
+        header.flags.set_bit(29, true);
+        header.encode(&mut r);
+        r.extend_from_slice(&items);
+        header.flags.set_bit(29, false);
+        header.encode(&mut r);
+        Ok(r)
+    }
+
+    /// Writes the tag to the end of the file at `path` replacing the existing APE tag.
+    /// The tag is placed before the trailing Lyrics3 and ID3v1 tags if there are any.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        Self::write_file(path.as_ref(), &self.encode()?).map(|_| ())
+    }
+
+    /// Removes the APE tag from the file at `path` keeping the trailing Lyrics3 and ID3v1
+    /// tags. Returns `false` if there was no tag.
+    pub fn strip(path: impl AsRef<Path>) -> io::Result<bool> {
+        Self::write_file(path.as_ref(), &[])
+    }
+
+    fn write_file(path: &Path, tag: &[u8]) -> io::Result<bool> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let file_len = file.seek(SeekFrom::End(0))?;
+
+        let end = file_len - Id3v1::read(&mut file).into_opt()?.map(|v| v.len()).unwrap_or(0) as u64;
+        let end = Lyrics3::read_at_end(&mut file, end).into_opt()?.map(|(_, pos)| pos).unwrap_or(end);
+        let (found, start) = match Self::read_at_end(&mut file, end).into_opt()? {
+            Some((_, start)) => (true, start),
+            None => (false, end),
+        };
+
+        if !found && tag.is_empty() {
+            return Ok(false);
+        }
+
+        // The tail is buffered and written after the tag in place, the file is truncated only
+        // after the whole write succeeds. The whole file is rewritten if the tail is too large
+        // to buffer.
+        let tail_len = file_len - end;
+        if tail_len > MAX_LEN {
+            rewrite_file(path, &mut file, |src, dst| {
+                copy_range(src, dst, 0..start)?;
+                dst.write_all(tag)?;
+                copy_range(src, dst, end..file_len)
+            })?;
+            return Ok(found);
+        }
+        let mut buf = Vec::with_capacity(tag.len() + tail_len as usize);
+        buf.extend_from_slice(tag);
+        file.seek(SeekFrom::Start(end))?;
+        file.read_to_end(&mut buf)?;
+
+        file.seek(SeekFrom::Start(start))?;
+        file.write_all(&buf)?;
+        file.set_len(start + buf.len() as u64)?;
+        file.sync_all()?;
+        Ok(found)
+    }
+
+    /// Reads the APE and ID3v1 tags at the end of the file, the APE tag may precede ID3v1.
+    /// Returns the tags and the position where they start.
+    pub(crate) fn read_trailing(rd: &mut (impl Read + Seek))
+        -> io::Result<(Option<Self>, Option<Id3v1>, u64)>
+    {
+        let file_len = rd.seek(SeekFrom::End(0))?;
+        let id3v1 = Id3v1::read(&mut *rd).into_opt()?;
+        let end = file_len - id3v1.as_ref().map(|v| v.len()).unwrap_or(0) as u64;
+        Ok(match Self::read_at_end(rd, end).into_opt()? {
+            Some((ape, start)) => (Some(ape), id3v1, start),
+            None => (None, id3v1, end),
+        })
+    }
+
+    /// Reads the tag whose footer ends at `end`. Returns the tag and its start position
+    /// including the header.
+    pub(crate) fn read_at_end(rd: &mut (impl Read + Seek), end: u64) -> io::Result<(Self, u64)> {
+        if end < HEADER_LEN as u64 {
+            return Err(Error("couldn't find APE tag footer").into_invalid_data_err());
+        }
+        rd.seek(SeekFrom::Start(end - HEADER_LEN as u64))?;
+        let mut buf = [0; HEADER_LEN];
+        rd.read_exact(&mut buf)?;
+        let footer = Header::decode(&buf).map_err(|e| e.into_invalid_data_err())?;
+        if footer.is_header() {
+            return Err(Error("APE tag footer has header flag").into_invalid_data_err());
+        }
+
+        let len = footer.len as u64;
+        let full_len = len + if footer.has_header() { HEADER_LEN as u64 } else { 0 };
+        if full_len > end || len > MAX_LEN {
+            return Err(Error("bad APE tag len").into_invalid_data_err());
+        }
+        let items_len = len - HEADER_LEN as u64;
+        rd.seek(SeekFrom::Start(end - len))?;
+        let buf = read_vec_limited(&mut Limited::new(rd, items_len), items_len as usize,
+            "APE tag is truncated")?;
+
+        let tag = Self::decode(&footer, &buf).map_err(|e| e.into_invalid_data_err())?;
+        Ok((tag, end - full_len))
+    }
+
+    fn decode(footer: &Header, mut buf: &[u8]) -> Result<Self> {
+        let truncated = || Error("APE tag item is truncated");
+        let mut items = Vec::new();
+        for _ in 0..footer.item_count {
+            if buf.len() < 8 {
+                return Err(truncated());
+            }
+            let len = LE::read_u32(&buf[..4]) as usize;
+            let flags = LE::read_u32(&buf[4..8]);
+            buf = &buf[8..];
+
+            let key_len = buf.iter().position(|&c| c == 0).ok_or_else(truncated)?;
+            let key = buf[..key_len].iter().map(|&c| c as char).collect();
+            buf = &buf[key_len + 1..];
+
+            let value = buf.get(..len).ok_or_else(truncated)?;
+            buf = &buf[len..];
+            let text = || String::from_utf8_lossy(value).into_owned();
+            let value = match (footer.version, flags.get_bits(1..3)) {
+                (Version::V1, _) | (_, 0) | (_, 3) => Value::Text(text()),
+                (_, 1) => Value::Binary(value.to_vec()),
+                (_, 2) => Value::Locator(text()),
+                _ => unreachable!(),
+            };
+
+            items.push(Item {
+                key,
+                value,
+                read_only: flags.get_bit(0),
+            });
+        }
+        Ok(Self {
+            version: footer.version,
+            read_only: footer.flags.get_bit(0),
+            items,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+    use crate::util::file::test::TempFile;
+
+    pub(crate) fn header(version: u32, len: usize, item_count: usize, flags: u32) -> Vec<u8> {
+        let mut r = MAGIC.to_vec();
+        r.extend_from_slice(&version.to_le_bytes());
+        r.extend_from_slice(&(len as u32).to_le_bytes());
+        r.extend_from_slice(&(item_count as u32).to_le_bytes());
+        r.extend_from_slice(&flags.to_le_bytes());
+        r.extend_from_slice(&[0; 8]);
+        r
+    }
+
+    pub(crate) fn item(key: &str, value: &[u8], flags: u32) -> Vec<u8> {
+        let mut r = (value.len() as u32).to_le_bytes().to_vec();
+        r.extend_from_slice(&flags.to_le_bytes());
+        r.extend_from_slice(key.as_bytes());
+        r.push(0);
+        r.extend_from_slice(value);
+        r
+    }
+
+    /// Builds APEv2 tag with header and footer.
+    pub(crate) fn tag(items: &[Vec<u8>]) -> Vec<u8> {
+        let data = items.concat();
+        let len = data.len() + HEADER_LEN;
+        let mut r = header(2000, len, items.len(), 0xa000_0000);
+        r.extend_from_slice(&data);
+        r.extend_from_slice(&header(2000, len, items.len(), 0x8000_0000));
+        r
+    }
+
+    #[test]
+    fn read() {
+        let mut bytes = b"audio".to_vec();
+        bytes.extend_from_slice(&tag(&[
+            item("TITLE", b"title", 0),
+            item("Artist", "a1\0\u{e9}".as_bytes(), 1),
+            item("Year", b"2001", 0),
+            item("Cover Art (Front)", b"cover.jpg\0\xff\xd8", 0b010),
+            item("Related", b"http://x", 0b100),
+        ]));
+        let (ape, start) = Ape::read_at_end(&mut Cursor::new(&bytes), bytes.len() as u64).unwrap();
+        assert_eq!(start, 5);
+        assert_eq!(ape.version(), Version::V2);
+        assert_eq!(ape.title(), Some("title"));
+        assert_eq!(ape.artist(), Some("a1"));
+        assert_eq!(ape.get("artist").unwrap().value.texts().collect::<Vec<_>>(), vec!["a1", "\u{e9}"]);
+        assert!(ape.get("ARTIST").unwrap().read_only);
+        assert_eq!(ape.date().unwrap().year(), 2001);
+        assert_eq!(ape.get("cover art (front)").unwrap().value,
+            Value::Binary(b"cover.jpg\0\xff\xd8".to_vec()));
+        assert_eq!(ape.get("Related").unwrap().value, Value::Locator("http://x".into()));
+
+        // APEv1 has no header and its items are always text.
+        let data = item("Title", b"v1", 0b010);
+        let mut bytes = data.clone();
+        bytes.extend_from_slice(&header(1000, data.len() + HEADER_LEN, 1, 0xffff_ffff));
+        let (ape, start) = Ape::read_at_end(&mut Cursor::new(&bytes), bytes.len() as u64).unwrap();
+        assert_eq!(start, 0);
+        assert_eq!(ape.version(), Version::V1);
+        assert_eq!(ape.title(), Some("v1"));
+
+        assert_eq!(Ape::read_at_end(&mut Cursor::new(&bytes), bytes.len() as u64 - 1)
+            .unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn save() {
+        let path = TempFile::new("ape-save.mp3");
+        let mut id3v1 = vec![0; 128];
+        id3v1[..6].copy_from_slice(b"TAGabc");
+        let lyrics = b"LYRICSBEGININD0000210000021LYRICS200";
+        let mut bytes = b"audio".to_vec();
+        bytes.extend_from_slice(lyrics);
+        bytes.extend_from_slice(&id3v1);
+        fs::write(&path, &bytes).unwrap();
+
+        let read = |path: &Path| {
+            let mut file = fs::File::open(path).unwrap();
+            let end = file.seek(SeekFrom::End(0)).unwrap() - 128 - lyrics.len() as u64;
+            Ape::read_at_end(&mut file, end).unwrap()
+        };
+
+        let mut ape = Ape::new();
+        ape.set_text(Ape::REPLAYGAIN_TRACK_GAIN, "-6.50 dB");
+        ape.set(Ape::ARTIST, Value::texts_from(vec!["a", "b"]));
+        ape.save(&path).unwrap();
+        let (actual, start) = read(&path);
+        assert_eq!(actual, ape);
+        assert_eq!(start, 5);
+        assert_eq!(&fs::read(&path).unwrap()[..5], b"audio");
+
+        // Shrinking the tag truncates the file.
+        ape.set_text("replaygain_track_gain", "+1.00 dB");
+        ape.remove(Ape::ARTIST).unwrap();
+        ape.save(&path).unwrap();
+        let (actual, _) = read(&path);
+        assert_eq!(actual.items().len(), 1);
+        assert_eq!(actual.get_text(Ape::REPLAYGAIN_TRACK_GAIN), Some("+1.00 dB"));
+        assert_eq!(fs::read(&path).unwrap().len(), bytes.len() + ape.encode().unwrap().len());
+
+        assert!(Ape::strip(&path).unwrap());
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        assert!(!Ape::strip(&path).unwrap());
+
+        ape.set_text("ID3", "x");
+        assert_eq!(ape.encode().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
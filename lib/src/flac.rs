@@ -1,16 +1,20 @@
 use bit_field::BitField;
 use byteorder::{BE, ByteOrder, ReadBytesExt};
-use encoding::{Encoding, DecoderTrap};
+use encoding::{Encoding, DecoderTrap, EncoderTrap};
 use encoding::all::{ISO_8859_1, UTF_8};
 use std::cmp;
 use std::convert::TryInto;
+use std::fs::OpenOptions;
 use std::io::{self, SeekFrom};
 use std::io::prelude::*;
+use std::ops::Range;
+use std::path::Path;
 
 use std::time::Duration;
 use std::fmt;
 use crate::error::*;
 use crate::util::*;
+use crate::util::file::{copy_range, rewrite_file};
 use crate::id3::v1::Id3v1;
 use crate::id3::v2::Id3v2;
 use crate::tags::TagsRef;
@@ -53,19 +57,21 @@ pub struct StreamInfo {
 }
 
 impl StreamInfo {
-    const LEN: usize = 34;
+    pub(crate) const LEN: usize = 34;
 
-    fn read_block(mut rd: impl Read) -> io::Result<Self> {
+    /// Returns the stream info and whether it's the last metadata block.
+    fn read_block(mut rd: impl Read) -> io::Result<(Self, bool)> {
         let hdr = BlockHeader::read(&mut rd)?;
         if hdr.len != StreamInfo::LEN as u32 {
             return Err(Error("invalid METADATA_BLOCK_STREAMINFO block len").into_invalid_data_err());
         }
         let mut buf = [0; StreamInfo::LEN];
         rd.read_exact(&mut buf)?;
-        Self::decode(&buf).map_err(|e| e.into_invalid_data_err())
+        let r = Self::decode(&buf).map_err(|e| e.into_invalid_data_err())?;
+        Ok((r, hdr.last))
     }
 
-    fn decode(buf: &[u8]) -> Result<Self> {
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self> {
         if buf.len() < Self::LEN {
             return Err(Error("METADATA_BLOCK_STREAMINFO is truncated"));
         }
@@ -258,7 +264,7 @@ pub struct Picture {
 impl Picture {
     const MIN_LEN: usize = 32;
 
-    fn read<T: Read>(rd: &mut Limited<T>) -> io::Result<Self> {
+    pub(crate) fn read<T: Read>(rd: &mut Limited<T>) -> io::Result<Self> {
         fn read_str<T: Read>(rd: &mut Limited<T>, encoding: &impl Encoding) -> io::Result<String> {
             let len = rd.read_u32::<BE>()?;
             let vec = read_vec_limited(rd, len as usize, "invalid string length")?;
@@ -291,6 +297,25 @@ impl Picture {
             data,
         })
     }
+
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        fn write_str(s: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+            let len: u32 = s.len().try_into().map_err(|_| Error("string is too long"))?;
+            buf.extend_from_slice(&len.to_be_bytes());
+            buf.extend_from_slice(s);
+            Ok(())
+        }
+
+        buf.extend_from_slice(&(self.kind.0 as u32).to_be_bytes());
+        let content_type = ISO_8859_1.encode(&self.content_type, EncoderTrap::Strict)
+            .map_err(|_| Error("content type can't be encoded with ISO-8859-1"))?;
+        write_str(&content_type, buf)?;
+        write_str(self.description.as_bytes(), buf)?;
+        for &v in &[self.width, self.height, self.color_depth, self.colors_used] {
+            buf.extend_from_slice(&v.to_be_bytes());
+        }
+        write_str(&self.data, buf)
+    }
 }
 
 impl fmt::Debug for Picture {
@@ -304,10 +329,10 @@ impl fmt::Debug for Picture {
     }
 }
 
-struct BlockHeader {
-    last: bool,
-    kind: BlockKind,
-    len: u32,
+pub(crate) struct BlockHeader {
+    pub last: bool,
+    pub kind: BlockKind,
+    pub len: u32,
 }
 
 impl BlockHeader {
@@ -331,10 +356,69 @@ impl BlockHeader {
             len,
         })
     }
+
+    fn encode(&self) -> Result<[u8; 4]> {
+        if self.len > MAX_BLOCK_LEN {
+            return Err(Error("metadata block is too large"));
+        }
+        let mut r = [0; 4];
+        BE::write_u32(&mut r, self.len);
+        r[0] = self.kind.0;
+        r[0].set_bit(7, self.last);
+        Ok(r)
+    }
+}
+
+const MAX_BLOCK_LEN: u32 = (1 << 24) - 1;
+
+/// Metadata block with the data as is.
+struct RawBlock {
+    kind: BlockKind,
+    data: Vec<u8>,
+}
+
+impl RawBlock {
+    /// Encodes the blocks followed by the padding block of `padding_len` if specified.
+    fn encode_all(blocks: &[RawBlock], padding_len: Option<u32>) -> Result<Vec<u8>> {
+        let mut r = Vec::new();
+        for (i, block) in blocks.iter().enumerate() {
+            let len: u32 = block.data.len().try_into().map_err(|_| Error("metadata block is too large"))?;
+            r.extend_from_slice(&BlockHeader {
+                last: padding_len.is_none() && i == blocks.len() - 1,
+                kind: block.kind,
+                len,
+            }.encode()?);
+            r.extend_from_slice(&block.data);
+        }
+        if let Some(len) = padding_len {
+            r.extend_from_slice(&BlockHeader {
+                last: true,
+                kind: BlockKind::PADDING,
+                len,
+            }.encode()?);
+            r.resize(r.len() + len as usize, 0);
+        }
+        Ok(r)
+    }
+}
+
+/// Options for `Flac::save`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SaveOptions {
+    /// Len of the padding block written when the file has to be rewritten.
+    pub padding_len: u32,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            padding_len: 4096,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Eq, PartialEq)]
-struct BlockKind(u8);
+pub(crate) struct BlockKind(u8);
 
 impl BlockKind {
     pub const STREAM_INFO: Self = Self(0);
@@ -370,6 +454,8 @@ pub struct Flac {
     id3v1: Option<Id3v1>,
     id3v2: Option<Id3v2>,
     vcomment: Option<Vcomment>,
+    /// Position of the metadata starting from the `fLaC` magic.
+    metadata_range: Range<u64>,
 }
 
 impl Flac {
@@ -403,11 +489,20 @@ impl Flac {
         self.pictures.iter()
     }
 
+    pub fn pictures_mut(&mut self) -> &mut Vec<Picture> {
+        &mut self.pictures
+    }
+
+    pub fn vcomment_mut(&mut self) -> &mut Option<Vcomment> {
+        &mut self.vcomment
+    }
+
     pub fn tags(&self) -> TagsRef {
         TagsRef {
             id3v1: self.id3v1.as_ref(),
             id3v2: self.id3v2.as_ref(),
             vcomment: self.vcomment.as_ref(),
+            .. Default::default()
         }
     }
 
@@ -418,6 +513,7 @@ impl Flac {
 
         let mut stream_info = None;
         let mut id3v2 = None;
+        let mut id3v2_end = 0;
 
         let pos_limit = cmp::min(1024 * 1024, limit);
         let mut pos = 0;
@@ -433,26 +529,28 @@ impl Flac {
                 }
             } else if id3v2.is_none() {
                 rd.seek(SeekFrom::Start(pos))?;
-                if let Some((id3_, id3_len)) = crate::id3::v2::Id3v2::read(&mut rd, Some(limit - pos)).into_opt()? {
+                if let Some((id3_, id3_len)) = Id3v2::read(&mut rd, Some(limit - pos)).into_opt()? {
                     id3v2 = Some(id3_);
                     pos += id3_len as u64;
+                    id3v2_end = pos;
                     continue;
                 }
             }
 
             pos += 1;
         }
-        if stream_info.is_none() {
-            return Err(Error("couldn't stream info block").into_invalid_data_err());
-        }
+        let (stream_info, mut last) = stream_info
+            .ok_or_else(|| Error("couldn't stream info block").into_invalid_data_err())?;
 
-        let mut rd = Limited::new(rd, limit - pos - 4);
+        let metadata_start = pos;
+        let blocks_pos = rd.stream_position()?;
+        let mut rd = Limited::new(rd, limit - blocks_pos);
 
         let mut pictures = Vec::new();
         let mut cue_sheet = None;
         let mut vcomment = None;
 
-        loop {
+        while !last {
             let block_header = BlockHeader::read(&mut rd)?;
 
             let handled = {
@@ -477,24 +575,131 @@ impl Flac {
                 rd.seek_relative(block_header.len as i64)?;
             }
 
-            if block_header.last {
-                break;
-            }
+            last = block_header.last;
         }
 
-        let audio_len_bytes = rd.max_available() -
-            id3v1.as_ref().map(|v| v.len() as u64).unwrap_or(0);
+        let audio_start = blocks_pos + rd.pos();
+        let mut rd = rd.into_inner();
+
+        let end = limit - id3v1.as_ref().map(|v| v.len() as u64).unwrap_or(0);
+        let (id3v2, audio_end, _) = Id3v2::read_chained(&mut rd,
+            id3v2.map(|v| (v, id3v2_end)), end)?;
+        let audio_len_bytes = audio_end.saturating_sub(audio_start);
 
         Ok(Self {
-            stream_info: stream_info.unwrap(),
+            stream_info,
             audio_len_bytes,
             cue_sheet,
             pictures,
             id3v1,
             id3v2,
             vcomment,
+            metadata_range: metadata_start..audio_start,
         })
     }
+
+    /// Writes the pictures and the Vorbis comment to the file at `path`. The rest of the metadata
+    /// blocks is kept as is.
+    /// The metadata is rewritten in place if it fits in the existing metadata and padding,
+    /// otherwise the whole file is rewritten.
+    pub fn save(&self, path: impl AsRef<Path>, options: &SaveOptions) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        // The file could change since it was read so the metadata is located anew.
+        let range = Self::read(&mut file)?.metadata_range;
+        let file_len = file.seek(SeekFrom::End(0))?;
+
+        let blocks_start = range.start + 4;
+        file.seek(SeekFrom::Start(blocks_start))?;
+        let blocks = self.encode_blocks(&mut Limited::new(&mut file, range.end - blocks_start))?;
+        let blocks_len = blocks.iter().map(|b| 4 + b.data.len() as u64).sum::<u64>();
+
+        // Padding len to fill the available space or None if it doesn't fit.
+        let available = range.end - blocks_start;
+        let padding_len = if blocks_len == available {
+            Some(None)
+        } else {
+            available.checked_sub(blocks_len + 4)
+                .filter(|&v| v <= MAX_BLOCK_LEN as u64)
+                .map(|v| Some(v as u32))
+        };
+
+        if let Some(padding_len) = padding_len {
+            let bytes = RawBlock::encode_all(&blocks, padding_len)
+                .map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))?;
+            debug_assert_eq!(bytes.len() as u64, available);
+            file.seek(SeekFrom::Start(blocks_start))?;
+            file.write_all(&bytes)?;
+            file.sync_all()
+        } else {
+            let bytes = RawBlock::encode_all(&blocks, Some(options.padding_len))
+                .map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))?;
+            rewrite_file(path, &mut file, |src, dst| {
+                copy_range(src, dst, 0..blocks_start)?;
+                dst.write_all(&bytes)?;
+                copy_range(src, dst, range.end..file_len)
+            })
+        }
+    }
+
+    /// Reads the existing blocks and returns the new block chain without padding.
+    /// The Vorbis comment and pictures are placed where the first such block was.
+    fn encode_blocks<T: Read>(&self, rd: &mut Limited<T>) -> io::Result<Vec<RawBlock>> {
+        let mut r = Vec::new();
+        let mut pictures_done = false;
+        let mut vcomment_done = false;
+        loop {
+            let hdr = BlockHeader::read(&mut *rd)?;
+            let data = read_vec_limited(rd, hdr.len as usize, "metadata block is truncated")?;
+            match hdr.kind {
+                BlockKind::PADDING => {}
+                BlockKind::PICTURE => if !pictures_done {
+                    self.encode_pictures(&mut r)?;
+                    pictures_done = true;
+                }
+                BlockKind::VORBIS_COMMENT => if !vcomment_done {
+                    self.encode_vcomment(&mut r)?;
+                    vcomment_done = true;
+                }
+                kind => r.push(RawBlock { kind, data }),
+            }
+            if hdr.last {
+                break;
+            }
+        }
+        if !vcomment_done {
+            self.encode_vcomment(&mut r)?;
+        }
+        if !pictures_done {
+            self.encode_pictures(&mut r)?;
+        }
+        Ok(r)
+    }
+
+    fn encode_vcomment(&self, blocks: &mut Vec<RawBlock>) -> io::Result<()> {
+        if let Some(vcomment) = &self.vcomment {
+            let mut data = Vec::new();
+            vcomment.write(&mut data, false)?;
+            blocks.push(RawBlock {
+                kind: BlockKind::VORBIS_COMMENT,
+                data,
+            });
+        }
+        Ok(())
+    }
+
+    fn encode_pictures(&self, blocks: &mut Vec<RawBlock>) -> io::Result<()> {
+        for picture in &self.pictures {
+            let mut data = Vec::new();
+            picture.encode(&mut data).map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))?;
+            blocks.push(RawBlock {
+                kind: BlockKind::PICTURE,
+                data,
+            });
+        }
+        Ok(())
+    }
 }
 
 fn strip_null(s: &[u8]) -> &[u8] {
@@ -509,4 +714,98 @@ fn decode_str(encoding: &impl Encoding, s: &[u8]) -> Result<String> {
 
 fn decode_null_terminated_str(encoding: &impl Encoding, s: &[u8]) -> Result<String> {
     decode_str(encoding, strip_null(s))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::fs;
+    use crate::util::file::test::TempFile;
+
+    const AUDIO: &[u8] = b"\xff\xf8audio";
+
+    pub(crate) fn stream_info_block(last: bool) -> Vec<u8> {
+        let mut r = BlockHeader {
+            last,
+            kind: BlockKind::STREAM_INFO,
+            len: StreamInfo::LEN as u32,
+        }.encode().unwrap().to_vec();
+        // 4096 samples blocks, 44.1 kHz, 2 channels, 16 bits, 44100 samples.
+        r.extend_from_slice(&[0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0,
+            0x0a, 0xc4, 0x42, 0xf0, 0x00, 0x00, 0xac, 0x44]);
+        r.extend_from_slice(&[0; 16]);
+        r
+    }
+
+    fn write_file(name: &str, padding_len: Option<u32>) -> TempFile {
+        let path = TempFile::new(&format!("{}.flac", name));
+        let mut bytes = b"fLaC".to_vec();
+        bytes.extend_from_slice(&stream_info_block(padding_len.is_none()));
+        let app = RawBlock {
+            kind: BlockKind::APPLICATION,
+            data: b"test".to_vec(),
+        };
+        if let Some(padding_len) = padding_len {
+            bytes.extend_from_slice(&RawBlock::encode_all(&[app], Some(padding_len)).unwrap());
+        }
+        bytes.extend_from_slice(AUDIO);
+        fs::write(&path, &bytes).unwrap();
+        path
+    }
+
+    fn read(path: &Path) -> Flac {
+        Flac::read(fs::File::open(path).unwrap()).unwrap()
+    }
+
+    fn picture(data_len: usize) -> Picture {
+        Picture {
+            kind: PictureKind::COVER_FRONT,
+            content_type: "image/png".into(),
+            description: "\u{263a}".into(),
+            width: 1,
+            height: 2,
+            color_depth: 3,
+            colors_used: 4,
+            data: vec![1; data_len],
+        }
+    }
+
+    #[test]
+    fn save_in_place() {
+        let path = write_file("save_in_place", Some(100));
+        let len = fs::metadata(&path).unwrap().len();
+        let mut flac = read(&path);
+        assert_eq!(flac.audio_len_bytes, AUDIO.len() as u64);
+        flac.pictures_mut().push(picture(10));
+        let mut vcomment = Vcomment::new("v");
+        vcomment.set("TITLE", "t").unwrap();
+        *flac.vcomment_mut() = Some(vcomment.clone());
+        flac.save(&path, &Default::default()).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        let flac = read(&path);
+        assert_eq!(flac.pictures, vec![picture(10)]);
+        assert_eq!(flac.vcomment, Some(vcomment));
+        assert_eq!(flac.audio_len_bytes, AUDIO.len() as u64);
+    }
+
+    #[test]
+    fn save_rewrite() {
+        let path = write_file("save_rewrite", None);
+        let mut flac = read(&path);
+        flac.pictures_mut().push(picture(1000));
+        flac.save(&path, &SaveOptions { padding_len: 10 }).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.ends_with(AUDIO));
+        let mut flac = read(&path);
+        assert_eq!(flac.pictures, vec![picture(1000)]);
+        assert_eq!(flac.audio_len_bytes, AUDIO.len() as u64);
+
+        // Removing the picture grows the padding.
+        flac.pictures_mut().clear();
+        flac.save(&path, &Default::default()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), bytes.len() as u64);
+        assert!(read(&path).pictures.is_empty());
+    }
+}
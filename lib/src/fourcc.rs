@@ -0,0 +1,21 @@
+use std::fmt;
+
+/// Four character code identifying MP4 atoms and RIFF/IFF chunks.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub struct FourCC(pub [u8; 4]);
+
+impl fmt::Display for FourCC {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Latin1 as in the iTunes '©' atoms.
+        for &b in &self.0 {
+            write!(f, "{}", b as char)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for FourCC {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FourCC(\"{}\")", self)
+    }
+}
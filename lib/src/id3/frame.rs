@@ -3,14 +3,20 @@ pub mod body;
 
 use bit_field::BitField;
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use flate2::read::ZlibDecoder;
+use std::borrow::Cow;
+use std::convert::TryFrom;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::io;
 use std::io::prelude::*;
+use std::mem;
 
 use crate::error::*;
+use crate::timestamp::Timestamp;
 use crate::util::*;
 use super::Version;
+use super::string::Encoder;
 use super::unsynch;
 use body::*;
 
@@ -19,6 +25,12 @@ pub use id::FrameId;
 const HEADER_LEN: usize = 10;
 const HEADER_V2_2_LEN: usize = 6;
 
+// Bit positions of the frame flags in the order: tag alter preservation, file alter preservation,
+// read only (flags byte 1), grouping, compression, encryption, unsynchronisation,
+// data length indicator (flags byte 2).
+const V2_3_FLAGS_MAP: [u8; 8] = [7, 6, 5, 5, 7, 6, 0, 0];
+const V2_4_FLAGS_MAP: [u8; 8] = [6, 5, 4, 6, 3, 2, 1, 0];
+
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub struct Language(u32);
 
@@ -80,16 +92,72 @@ impl Encoding {
             _ => return Err(Error("bad encoding type")),
         })
     }
+
+    fn to_u8(self) -> u8 {
+        use Encoding::*;
+        match self {
+            Latin1 => 0,
+            Utf16 => 1,
+            Utf16BE => 2,
+            Utf8 => 3,
+        }
+    }
+
+    /// Returns the encoding to use when writing `strings` to a tag of the specified version.
+    /// v2.3 only supports ISO-8859-1 and UTF-16 with BOM. ISO-8859-1 is replaced with a Unicode
+    /// encoding if it can't represent the strings.
+    fn for_version<'a>(self, version: Version, strings: impl IntoIterator<Item=&'a str>) -> Self {
+        use Encoding::*;
+        let unicode = if version.minor == 3 { Utf16 } else { Utf8 };
+        match self {
+            Latin1 if strings.into_iter().all(Encoder::is_latin1) => Latin1,
+            Latin1 => unicode,
+            Utf16BE | Utf8 if version.minor == 3 => Utf16,
+            _ => self,
+        }
+    }
+}
+
+/// Frame status flags. These are defined since v2.3, v2.2 frames have all flags cleared.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FrameFlags {
+    /// If set the frame should be discarded when the tag is altered and the frame is unknown
+    /// to the tagger.
+    pub tag_alter_preserve: bool,
+
+    /// If set the frame should be discarded when the file (but not the tag) is altered and
+    /// the frame is unknown to the tagger. Doesn't apply when the audio is completely replaced.
+    pub file_alter_preserve: bool,
+
+    /// If set the frame is intended to be read only and shouldn't be changed without knowing
+    /// why the flag was set.
+    pub read_only: bool,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Frame {
     pub id: FrameId,
+    pub flags: FrameFlags,
+    /// Group symbol this frame belongs to. See `GroupIdReg` for the group registrations.
+    pub group_id: Option<u8>,
     pub body: Body,
 }
 
 impl Frame {
-    fn read<R: Read>(rd: &mut Limited<R>, version: Version) -> io::Result<Option<Self>> {
+    pub fn new(id: FrameId, body: Body) -> Self {
+        Self {
+            id,
+            flags: FrameFlags::default(),
+            group_id: None,
+            body,
+        }
+    }
+
+    /// `unsynch` is the tag-level unsynchronisation flag, it's only relevant for v2.4 tags.
+    /// `synchsafe_len` tells whether the frame len is stored as synchsafe integer.
+    fn read<R: Read>(rd: &mut Limited<R>, version: Version, unsynch: bool, synchsafe_len: bool)
+        -> io::Result<Option<Self>>
+    {
         assert!(version.minor >= 2 && version.minor <= 4);
         if version.minor == 2 {
             return Self::read_v2_2(rd);
@@ -109,8 +177,6 @@ impl Frame {
         let flags1 = buf[8];
         let flags2 = buf[9];
 
-        const V2_3_FLAGS_MAP: [u8; 8] = [7, 6, 5, 5, 7, 6, 0, 0];
-        const V2_4_FLAGS_MAP: [u8; 8] = [6, 5, 4, 6, 3, 2, 1, 0];
         // Verify unused bits cleared and get the right flags map.
         let flags_map = match version.minor {
             3 => {
@@ -128,33 +194,168 @@ impl Frame {
             _ => unreachable!(),
         };
 
-        let _tag_alter_preserve = flags1.get_bit(flags_map[0] as usize);
-        let _file_alter_preserve = flags1.get_bit(flags_map[1] as usize);
-        let _read_only = flags1.get_bit(flags_map[2] as usize);
+        let flags = FrameFlags {
+            tag_alter_preserve: flags1.get_bit(flags_map[0] as usize),
+            file_alter_preserve: flags1.get_bit(flags_map[1] as usize),
+            read_only: flags1.get_bit(flags_map[2] as usize),
+        };
         let contains_group_id = flags2.get_bit(flags_map[3] as usize);
         let compressed = flags2.get_bit(flags_map[4] as usize);
         let encrypted = flags2.get_bit(flags_map[5] as usize);
-        let unsynched = flags2.get_bit(flags_map[6] as usize);
-        let _data_len_indicator = flags2.get_bit(flags_map[7] as usize);
-
-        // FIXME
-        assert!(!compressed);
-        assert!(!contains_group_id);
-        assert!(!encrypted);
-        assert!(!unsynched);
+        let unsynched = flags2.get_bit(flags_map[6] as usize) || version.minor == 4 && unsynch;
+        let data_len_indicator = flags2.get_bit(flags_map[7] as usize);
 
         let id = FrameId::new([buf[0], buf[1], buf[2], buf[3]]);
-        let len = unsynch::decode_u32(&buf[4..8])
-            .ok_or_else(|| Error("bad frame len").into_invalid_data_err())?;
+        let mut len = if synchsafe_len {
+            unsynch::decode_u32(&buf[4..8])
+                .ok_or_else(|| Error("bad frame len").into_invalid_data_err())?
+        } else {
+            BigEndian::read_u32(&buf[4..8])
+        };
 
-        let body = Body::read(rd, id, len)?;
+        // The additional fields follow the header in the order of their flags
+        // and are accounted in the frame len.
+        fn take_len(len: &mut u32, field_len: u32) -> io::Result<()> {
+            *len = len.checked_sub(field_len)
+                .ok_or_else(|| Error("frame truncated").into_invalid_data_err())?;
+            Ok(())
+        }
+        let mut group_id = None;
+        let mut encryption_method = None;
+        // The decoded body len: v2.3 stores it only for compressed frames while v2.4 has
+        // the data length indicator.
+        let mut data_len = None;
+        if version.minor == 3 {
+            if compressed {
+                take_len(&mut len, 4)?;
+                data_len = Some(rd.read_u32::<BigEndian>()?);
+            }
+            if encrypted {
+                take_len(&mut len, 1)?;
+                encryption_method = Some(rd.read_u8()?);
+            }
+            if contains_group_id {
+                take_len(&mut len, 1)?;
+                group_id = Some(rd.read_u8()?);
+            }
+        } else {
+            if contains_group_id {
+                take_len(&mut len, 1)?;
+                group_id = Some(rd.read_u8()?);
+            }
+            if encrypted {
+                take_len(&mut len, 1)?;
+                encryption_method = Some(rd.read_u8()?);
+            }
+            if data_len_indicator {
+                take_len(&mut len, 4)?;
+                data_len = Some(unsynch::read_u32(rd)?
+                    .ok_or_else(|| Error("bad frame data length indicator").into_invalid_data_err())?);
+            }
+        }
+
+        let mut bytes = read_vec_limited(rd, len as usize, "frame truncated")?;
+        if unsynched {
+            unsynch::decode(&mut bytes);
+        }
+        let body = if let Some(method) = encryption_method {
+            // Compression is applied before encryption so the data is kept as is.
+            Body::Encrypted(Encrypted {
+                method,
+                compressed,
+                data_len,
+                data: bytes,
+            })
+        } else {
+            if compressed {
+                bytes = decompress(&bytes, data_len)?;
+            }
+            Body::decode(id, bytes).map_err(|e| e.into_invalid_data_err())?
+        };
 
         Ok(Some(Self {
             id,
+            flags,
+            group_id,
             body,
         }))
     }
 
+    /// Encodes the frame for v2.3 or v2.4 tag, the frame ID must be valid in the `version`.
+    /// `unsynch` is the tag-level unsynchronisation flag, in v2.4 it's applied to each frame.
+    fn encode(&self, version: Version, unsynch: bool, buf: &mut Vec<u8>) -> Result<()> {
+        assert!(version.minor == 3 || version.minor == 4);
+        debug_assert!(!self.id.is_v22());
+
+        let (mut data, compressed, encryption_method, mut data_len) = match &self.body {
+            Body::Encrypted(v) => (v.data.clone(), v.compressed, Some(v.method), v.data_len),
+            body => (body.encode(version)?, false, None, None),
+        };
+        let unsynched = version.minor == 4 && unsynch;
+        if unsynched {
+            data_len = data_len.or(Some(data.len() as u32));
+            data = unsynch::encode(&data);
+        }
+
+        let mut flags1 = 0;
+        let mut flags2 = 0;
+        let mut extra = Vec::new();
+        let flags_map = if version.minor == 3 {
+            if compressed {
+                let data_len = data_len
+                    .ok_or(Error("compressed frame lacks decompressed size"))?;
+                flags2.set_bit(V2_3_FLAGS_MAP[4] as usize, true);
+                extra.extend_from_slice(&data_len.to_be_bytes());
+            }
+            if let Some(method) = encryption_method {
+                flags2.set_bit(V2_3_FLAGS_MAP[5] as usize, true);
+                extra.push(method);
+            }
+            if let Some(group_id) = self.group_id {
+                flags2.set_bit(V2_3_FLAGS_MAP[3] as usize, true);
+                extra.push(group_id);
+            }
+            V2_3_FLAGS_MAP
+        } else {
+            if let Some(group_id) = self.group_id {
+                flags2.set_bit(V2_4_FLAGS_MAP[3] as usize, true);
+                extra.push(group_id);
+            }
+            flags2.set_bit(V2_4_FLAGS_MAP[4] as usize, compressed);
+            if let Some(method) = encryption_method {
+                flags2.set_bit(V2_4_FLAGS_MAP[5] as usize, true);
+                extra.push(method);
+            }
+            flags2.set_bit(V2_4_FLAGS_MAP[6] as usize, unsynched);
+            if let Some(data_len) = data_len {
+                flags2.set_bit(V2_4_FLAGS_MAP[7] as usize, true);
+                extra.extend_from_slice(&unsynch::encode_u32(data_len)
+                    .ok_or(Error("frame is too large"))?);
+            }
+            V2_4_FLAGS_MAP
+        };
+        flags1.set_bit(flags_map[0] as usize, self.flags.tag_alter_preserve);
+        flags1.set_bit(flags_map[1] as usize, self.flags.file_alter_preserve);
+        flags1.set_bit(flags_map[2] as usize, self.flags.read_only);
+
+        let len = u32::try_from(extra.len() + data.len())
+            .map_err(|_| Error("frame is too large"))?;
+        let len = if version.minor == 3 {
+            len.to_be_bytes()
+        } else {
+            unsynch::encode_u32(len).ok_or(Error("frame is too large"))?
+        };
+
+        buf.extend_from_slice(&self.id.to_bytes());
+        buf.extend_from_slice(&len);
+        buf.push(flags1);
+        buf.push(flags2);
+        buf.extend_from_slice(&extra);
+        buf.extend_from_slice(&data);
+
+        Ok(())
+    }
+
     fn read_v2_2<R: Read>(rd: &mut Limited<R>) -> io::Result<Option<Self>> {
         let mut buf = [0; HEADER_V2_2_LEN];
 
@@ -174,6 +375,8 @@ impl Frame {
 
         Ok(Some(Self {
             id,
+            flags: FrameFlags::default(),
+            group_id: None,
             body,
         }))
     }
@@ -187,7 +390,7 @@ impl PartialEq for FrameKey {
         use Body::*;
 
         let ob = &o.0.body;
-        self.0.id == o.0.id &&
+        self.0.id == o.0.id && self.0.body.kind() == ob.kind() &&
             match &self.0.body {
                 Comment(v) => {
                     let o = ob.as_comment().unwrap();
@@ -200,6 +403,9 @@ impl PartialEq for FrameKey {
                 UserText(v) => v.description == ob.as_user_text().unwrap().description,
                 UserUrl(v) => v.description == ob.as_user_url().unwrap().description,
                 Url(v) => v == ob.as_url().unwrap(),
+                Encrypted(v) => v == ob.as_encrypted().unwrap(),
+                EncryptionMethodReg(v) => v.method == ob.as_encryption_method_reg().unwrap().method,
+                GroupIdReg(v) => v.group_id == ob.as_group_id_reg().unwrap().group_id,
                 | Bytes(_)
                 | Text(_)
                 | UniqueFileId(_)
@@ -223,6 +429,9 @@ impl Hash for FrameKey {
             UserText(v) => state.write(v.description.as_bytes()),
             UserUrl(v) => state.write(v.description.as_bytes()),
             Url(v) => state.write(v.as_bytes()),
+            Encrypted(v) => state.write(&v.data),
+            EncryptionMethodReg(v) => state.write_u8(v.method),
+            GroupIdReg(v) => state.write_u8(v.group_id),
             | Bytes(_)
             | Text(_)
             | UniqueFileId(_)
@@ -235,15 +444,44 @@ impl Hash for FrameKey {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Frames {
     vec: Vec<FrameKey>,
+    /// Whether v2.2 frame IDs are used by `set_picture` and the like.
+    v2_2: bool,
 }
 
 impl Frames {
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
+        Self {
+            vec: Vec::new(),
+            v2_2: false,
+        }
+    }
+
+    /// Creates an empty frame list for a tag of the specified version.
+    pub(crate) fn for_version(version: Version) -> Self {
         Self {
             vec: Vec::new(),
+            v2_2: version.minor == 2,
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Frame> {
+        self.vec.iter().map(|k| &k.0)
+    }
+
+    /// Changing the frame key (ID and the description where applicable) may leave several frames
+    /// with the same key, use `retain` to have such frames merged.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Frame> {
+        self.vec.iter_mut().map(|k| &mut k.0)
+    }
+
     pub fn get(&self, id: FrameId) -> impl Iterator<Item = &Frame> {
         self.vec.iter().filter(move |k| k.0.id == id).map(|k| &k.0)
     }
@@ -253,38 +491,320 @@ impl Frames {
     }
 
     pub fn first_text(&self, id: FrameId) -> Option<&Text> {
-        self.first(id).map(|f| f.body.as_text().unwrap())
+        self.get(id).filter_map(|f| f.body.as_text()).next()
     }
 
     pub fn first_text_str(&self, id: FrameId) -> Option<&str> {
         self.first_text(id).map(|t| t.strings[0].as_ref())
     }
 
-    pub(crate) fn insert(&mut self, frame: Frame) {
+    /// Adds the frame. If there's a frame with the same key (ID and the description where
+    /// applicable) the frames are merged as if they were read from the same tag.
+    pub fn insert(&mut self, frame: Frame) {
         let key = FrameKey(frame);
         if let Some(i) = self.vec.iter().position(|k| k == &key) {
-            let id = key.0.id;
-            if let Some(body) = self.vec[i].0.body.merge_from(key.0.body) {
-                self.vec.push(FrameKey(Frame { id, body }));
+            let Frame { id, flags, group_id, body } = key.0;
+            if let Some(body) = self.vec[i].0.body.merge_from(body) {
+                self.vec.push(FrameKey(Frame { id, flags, group_id, body }));
             }
         } else {
             self.vec.push(key);
         }
     }
 
-    pub(crate) fn read(rd: &mut impl Read, version: Version, len: u32) -> io::Result<Self> {
+    /// Adds the frame replacing the frame with the same key.
+    pub fn set(&mut self, frame: Frame) {
+        let key = FrameKey(frame);
+        self.vec.retain(|k| k != &key);
+        self.vec.push(key);
+    }
+
+    /// Replaces the frames having the `id` with a text frame.
+    /// ISO-8859-1 encoding is upgraded when the tag is encoded if the strings require so.
+    pub fn set_text(&mut self, id: FrameId, strings: impl IntoIterator<Item = impl Into<String>>) {
+        self.remove(id);
+        self.vec.push(FrameKey(Frame::new(id, Body::Text(Text {
+            encoding: Encoding::Latin1,
+            strings: strings.into_iter().map(|s| s.into()).collect(),
+        }))));
+    }
+
+    /// Removes all frames having the `id`. Returns the removed frames.
+    pub fn remove(&mut self, id: FrameId) -> Vec<Frame> {
+        self.remove_where(|f| f.id == id)
+    }
+
+    /// Replaces the picture with the same description. File icons are limited to one per kind
+    /// so the icon of the same kind is replaced too.
+    pub fn set_picture(&mut self, picture: Picture) {
+        let id = self.fid(FrameId::PICTURE, FrameId::V22_PICTURE);
+        let icon = picture.picture_kind == PictureKind::ICON
+            || picture.picture_kind == PictureKind::OTHER_ICON;
+        self.remove_where(|f| f.id == id && f.body.as_picture()
+            .map(|p| p.description == picture.description
+                || icon && p.picture_kind == picture.picture_kind)
+            .unwrap_or(false));
+        self.vec.push(FrameKey(Frame::new(id, Body::Picture(picture))));
+    }
+
+    pub fn remove_picture(&mut self, kind: PictureKind, description: &str) -> Option<Picture> {
+        let id = self.fid(FrameId::PICTURE, FrameId::V22_PICTURE);
+        self.remove_first(|f| f.id == id && f.body.as_picture()
+            .map(|p| p.picture_kind == kind && p.description == description)
+            .unwrap_or(false))
+            .and_then(|f| f.body.into_picture())
+    }
+
+    /// Replaces the comment with the same language and description.
+    pub fn set_comment(&mut self, comment: Comment) {
+        self.set(Frame::new(self.fid(FrameId::COMMENT, FrameId::V22_COMMENT),
+            Body::Comment(comment)));
+    }
+
+    pub fn remove_comment(&mut self, lang: Language, description: &str) -> Option<Comment> {
+        let id = self.fid(FrameId::COMMENT, FrameId::V22_COMMENT);
+        self.remove_first(|f| f.id == id && f.body.as_comment()
+            .map(|c| c.lang == lang && c.description == description)
+            .unwrap_or(false))
+            .and_then(|f| f.body.into_comment())
+    }
+
+    /// Replaces the `TXXX` frame with the same description.
+    pub fn set_user_text(&mut self, user_text: UserText) {
+        self.set(Frame::new(self.fid(FrameId::USER_TEXT, FrameId::V22_USER_TEXT),
+            Body::UserText(user_text)));
+    }
+
+    pub fn remove_user_text(&mut self, description: &str) -> Option<UserText> {
+        let id = self.fid(FrameId::USER_TEXT, FrameId::V22_USER_TEXT);
+        self.remove_first(|f| f.id == id && f.body.as_user_text()
+            .map(|t| t.description == description)
+            .unwrap_or(false))
+            .and_then(|f| f.body.into_user_text())
+    }
+
+    /// Calls `f` for each frame allowing to modify it, the frames for which `f` returns `false`
+    /// are removed. Frames that end up having the same key are merged as in `insert`.
+    pub fn retain(&mut self, mut f: impl FnMut(&mut Frame) -> bool) {
+        let vec = mem::take(&mut self.vec);
+        for mut k in vec {
+            if f(&mut k.0) {
+                self.insert(k.0);
+            }
+        }
+    }
+
+    fn fid(&self, post_v2_3: FrameId, pre_v2_3: FrameId) -> FrameId {
+        if self.v2_2 {
+            pre_v2_3
+        } else {
+            post_v2_3
+        }
+    }
+
+    fn remove_first(&mut self, mut f: impl FnMut(&Frame) -> bool) -> Option<Frame> {
+        let i = self.vec.iter().position(|k| f(&k.0))?;
+        Some(self.vec.remove(i).0)
+    }
+
+    fn remove_where(&mut self, mut f: impl FnMut(&Frame) -> bool) -> Vec<Frame> {
+        let mut r = Vec::new();
+        let mut i = 0;
+        while i < self.vec.len() {
+            if f(&self.vec[i].0) {
+                r.push(self.vec.remove(i).0);
+            } else {
+                i += 1;
+            }
+        }
+        r
+    }
+
+    /// Replaces the frames with the corresponding frames of `o`, the rest of the `o` frames is
+    /// added.
+    pub(crate) fn update_from(&mut self, o: Frames) {
+        for key in o.vec {
+            self.vec.retain(|k| k != &key);
+            self.vec.push(key);
+        }
+    }
+
+    pub(crate) fn read(rd: &mut impl Read, version: Version, len: u32, unsynch: bool)
+        -> io::Result<Self>
+    {
         if version.minor == 4 {
-            // FIXME determine_bpi
+            let buf = read_vec_limited(&mut Limited::new(rd, len as u64), len as usize,
+                "tag truncated")?;
+            let synchsafe_len = Self::is_synchsafe_len(&buf);
+            Self::read0(&mut &buf[..], len, version, unsynch, synchsafe_len)
+        } else {
+            Self::read0(rd, len, version, unsynch, false)
         }
+    }
 
-        Self::read0(rd, len, version)
+    /// Frame lens are synchsafe in v2.4, however iTunes used to write them as plain integers.
+    /// Walks the frames with both interpretations and picks the one that finds more frames
+    /// or the one that lands on the frame boundaries if both find the same number of frames.
+    fn is_synchsafe_len(buf: &[u8]) -> bool {
+        fn walk(buf: &[u8], synchsafe: bool) -> (usize, bool) {
+            let mut pos = 0;
+            let mut count = 0;
+            while pos + HEADER_LEN <= buf.len() {
+                let hdr = &buf[pos..pos + HEADER_LEN];
+                if hdr[0] == 0 {
+                    // Padding reached.
+                    return (count, true);
+                }
+                if !FrameId::new([hdr[0], hdr[1], hdr[2], hdr[3]]).is_valid() {
+                    return (count, false);
+                }
+                let len = if synchsafe {
+                    match unsynch::decode_u32(&hdr[4..8]) {
+                        Some(v) => v,
+                        None => return (count, false),
+                    }
+                } else {
+                    BigEndian::read_u32(&hdr[4..8])
+                };
+                count += 1;
+                pos = pos.saturating_add(HEADER_LEN + len as usize);
+            }
+            (count, pos == buf.len())
+        }
+
+        let (synchsafe_count, synchsafe_aligned) = walk(buf, true);
+        let (plain_count, plain_aligned) = walk(buf, false);
+        !(plain_count > synchsafe_count
+            || plain_count == synchsafe_count && plain_aligned && !synchsafe_aligned)
+    }
+
+    /// Encodes the frames for v2.3 or v2.4 tag. See `convert` for how the frames are adapted
+    /// to the `version`.
+    pub(crate) fn encode(&self, version: Version, unsynch: bool) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        for frame in self.convert(version) {
+            frame.encode(version, unsynch, &mut buf)?;
+        }
+        Ok(buf)
+    }
+
+    /// Converts the frames to v2.3 or v2.4 frames:
+    ///
+    /// * v2.2 frames get their v2.3 IDs.
+    /// * `TYER`, `TDAT` and `TIME` are merged into `TDRC` and vice versa.
+    /// * `TORY` is converted to `TDOR` and vice versa.
+    /// * `IPLS` is converted to `TIPL`, `TIPL` and `TMCL` are merged into `IPLS`.
+    /// * Other frames that don't exist in the `version` are dropped.
+    ///
+    /// The counterpart isn't created if it's already present.
+    fn convert(&self, version: Version) -> Vec<Cow<'_, Frame>> {
+        fn with_id(frame: Cow<'_, Frame>, id: FrameId) -> Cow<'_, Frame> {
+            let mut frame = frame.into_owned();
+            frame.id = id;
+            Cow::Owned(frame)
+        }
+
+        fn text_frame(id: FrameId, encoding: Encoding, strings: Vec<String>) -> Cow<'static, Frame> {
+            Cow::Owned(Frame::new(id, Body::Text(Text { encoding, strings })))
+        }
+
+        let frames: Vec<_> = self.vec.iter()
+            .filter_map(|k| if k.0.id.is_v22() {
+                k.0.id.upgrade_v22().map(|id| with_id(Cow::Borrowed(&k.0), id))
+            } else {
+                Some(Cow::Borrowed(&k.0))
+            })
+            .collect();
+        let has = |id| frames.iter().any(|f| f.id == id);
+        let text = |id| frames.iter()
+            .find(|f| f.id == id)
+            .and_then(|f| f.body.as_text())
+            .and_then(|t| t.strings.first())
+            .map(|s| s.as_str());
+
+        let mut r = Vec::with_capacity(frames.len());
+        if version.minor == 4 {
+            let mut date = text(FrameId::V23_YEAR)
+                .filter(|_| !has(FrameId::RELEASE_DATE))
+                .and_then(|y| decode_v2_3_date(y, text(FrameId::V23_DATE), text(FrameId::V23_TIME)));
+            let has_original_date = has(FrameId::ORIGINAL_RELEASE_DATE);
+            let has_people = has(FrameId::PEOPLE);
+
+            for frame in frames {
+                match frame.id {
+                    FrameId::V23_YEAR => if let Some(date) = date.take() {
+                        r.push(text_frame(FrameId::RELEASE_DATE, Encoding::Latin1,
+                            vec![date.to_string()]));
+                    }
+                    FrameId::V23_ORIGINAL_RELEASE_YEAR if !has_original_date =>
+                        r.push(with_id(frame, FrameId::ORIGINAL_RELEASE_DATE)),
+                    FrameId::INVOLVED_PEOPLE_LIST if !has_people =>
+                        r.push(with_id(frame, FrameId::PEOPLE)),
+                    id if id.is_v23_only() => {}
+                    _ => r.push(frame),
+                }
+            }
+        } else {
+            let mut date = text(FrameId::RELEASE_DATE)
+                .filter(|_| !has(FrameId::V23_YEAR))
+                .and_then(|s| s.parse::<Timestamp>().ok());
+            let mut original_year = text(FrameId::ORIGINAL_RELEASE_DATE)
+                .filter(|_| !has(FrameId::V23_ORIGINAL_RELEASE_YEAR))
+                .and_then(|s| s.parse::<Timestamp>().ok())
+                .map(|v| v.year());
+            let mut people = if has(FrameId::INVOLVED_PEOPLE_LIST) {
+                None
+            } else {
+                frames.iter()
+                    .filter(|f| f.id == FrameId::PEOPLE || f.id == FrameId::PERFORMERS)
+                    .filter_map(|f| f.body.as_text())
+                    .fold(None, |acc: Option<Text>, t| Some(match acc {
+                        Some(mut acc) => {
+                            acc.encoding = acc.encoding.common(t.encoding);
+                            acc.strings.extend(t.strings.iter().cloned());
+                            acc
+                        }
+                        None => t.clone(),
+                    }))
+            };
+
+            for frame in frames {
+                match frame.id {
+                    FrameId::RELEASE_DATE => if let Some(date) = date.take() {
+                        r.push(text_frame(FrameId::V23_YEAR, Encoding::Latin1,
+                            vec![format!("{:04}", date.year())]));
+                        if let (Some(m), Some(d)) = (date.month(), date.day()) {
+                            r.push(text_frame(FrameId::V23_DATE, Encoding::Latin1,
+                                vec![format!("{:02}{:02}", d, m)]));
+                        }
+                        if let (Some(h), Some(mi)) = (date.hour(), date.minute()) {
+                            r.push(text_frame(FrameId::V23_TIME, Encoding::Latin1,
+                                vec![format!("{:02}{:02}", h, mi)]));
+                        }
+                    }
+                    FrameId::ORIGINAL_RELEASE_DATE => if let Some(year) = original_year.take() {
+                        r.push(text_frame(FrameId::V23_ORIGINAL_RELEASE_YEAR, Encoding::Latin1,
+                            vec![format!("{:04}", year)]));
+                    }
+                    FrameId::PEOPLE | FrameId::PERFORMERS => if let Some(people) = people.take() {
+                        r.push(text_frame(FrameId::INVOLVED_PEOPLE_LIST, people.encoding,
+                            people.strings));
+                    }
+                    id if id.is_v24_only() => {}
+                    _ => r.push(frame),
+                }
+            }
+        }
+        r
     }
 
-    fn read0(rd: &mut impl Read, len: u32, version: Version) -> io::Result<Frames> {
+    fn read0(rd: &mut impl Read, len: u32, version: Version, unsynch: bool, synchsafe_len: bool)
+        -> io::Result<Frames>
+    {
         let rd = &mut Limited::new(rd, len as u64);
-        let mut r = Frames::new();
+        let mut r = Frames::for_version(version);
         while rd.max_available() > 0 {
-            match Frame::read(rd, version) {
+            match Frame::read(rd, version, unsynch, synchsafe_len) {
                 Ok(Some(frame)) => r.insert(frame),
                 Ok(None) => break,
                 Err(e) => return Err(e),
@@ -292,4 +812,172 @@ impl Frames {
         }
         Ok(r)
     }
-}
\ No newline at end of file
+}
+
+/// Decodes v2.3 date from the `TYER` (`YYYY`), `TDAT` (`DDMM`) and `TIME` (`HHMM`) values.
+/// Malformed `TDAT` and `TIME` values are ignored.
+pub(crate) fn decode_v2_3_date(year: &str, date: Option<&str>, time: Option<&str>)
+    -> Option<Timestamp>
+{
+    fn decode(s: Option<&str>) -> Option<(u8, u8)> {
+        let s = s.filter(|s| s.len() == 4 && s.bytes().all(|c| c.is_ascii_digit()))?;
+        Some((s[..2].parse().ok()?, s[2..].parse().ok()?))
+    }
+
+    let year = year.parse().ok()?;
+    if let Some((d, m)) = decode(date) {
+        let (h, mi) = decode(time).map(|(h, mi)| (Some(h), Some(mi))).unwrap_or((None, None));
+        if let Some(r) = Timestamp::new(year, Some(m), Some(d), h, mi, None)
+            .or_else(|| Timestamp::new(year, Some(m), Some(d), None, None, None))
+        {
+            return Some(r);
+        }
+    }
+    Timestamp::new(year, None, None, None, None, None)
+}
+
+fn decompress(buf: &[u8], len: Option<u32>) -> io::Result<Vec<u8>> {
+    let mut r = Vec::new();
+    // Read one byte more than expected to detect the len mismatch.
+    let limit = len.map(|v| v as u64 + 1).unwrap_or(u64::MAX);
+    ZlibDecoder::new(buf).take(limit).read_to_end(&mut r)
+        .map_err(|_| Error("bad compressed frame").into_invalid_data_err())?;
+    if let Some(len) = len {
+        if r.len() != len as usize {
+            return Err(Error("decompressed frame len mismatch").into_invalid_data_err());
+        }
+    }
+    Ok(r)
+}
+
+impl Default for Frames {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text_frame(id: FrameId, s: &str) -> Frame {
+        Frame::new(id, Body::Text(Text {
+            encoding: Encoding::Latin1,
+            strings: vec![s.into()],
+        }))
+    }
+
+    fn comment(lang: &[u8; 3], description: &str, text: &str) -> Comment {
+        Comment {
+            encoding: Encoding::Latin1,
+            lang: Language::new(*lang),
+            description: description.into(),
+            text: text.into(),
+        }
+    }
+
+    fn picture(kind: PictureKind, description: &str) -> Picture {
+        Picture {
+            encoding: Encoding::Latin1,
+            content_type: "image/png".into(),
+            picture_kind: kind,
+            description: description.into(),
+            data: vec![1],
+        }
+    }
+
+    #[test]
+    fn edit() {
+        let mut frames = Frames::new();
+        frames.insert(text_frame(FrameId::TITLE, "a"));
+        frames.insert(text_frame(FrameId::TITLE, "b"));
+        assert_eq!(frames.first_text(FrameId::TITLE).unwrap().strings, vec!["a", "b"]);
+
+        frames.set_text(FrameId::TITLE, vec!["c"]);
+        assert_eq!(frames.first_text(FrameId::TITLE).unwrap().strings, vec!["c"]);
+        assert_eq!(frames.len(), 1);
+
+        frames.set_comment(comment(b"eng", "", "1"));
+        frames.set_comment(comment(b"deu", "", "2"));
+        frames.set_comment(comment(b"eng", "", "3"));
+        assert_eq!(frames.get(FrameId::COMMENT).count(), 2);
+        assert_eq!(frames.remove_comment(Language::new(*b"eng"), ""), Some(comment(b"eng", "", "3")));
+        assert_eq!(frames.remove_comment(Language::new(*b"eng"), ""), None);
+
+        frames.set_user_text(UserText {
+            encoding: Encoding::Latin1,
+            description: "d".into(),
+            values: vec!["1".into()],
+        });
+        assert_eq!(frames.remove_user_text("d").unwrap().values, vec!["1"]);
+
+        frames.set_picture(picture(PictureKind::COVER_FRONT, "a"));
+        frames.set_picture(picture(PictureKind::COVER_BACK, "b"));
+        frames.set_picture(picture(PictureKind::COVER_FRONT, "b"));
+        assert_eq!(frames.get(FrameId::PICTURE).count(), 2);
+        assert_eq!(frames.remove_picture(PictureKind::COVER_FRONT, "b"),
+            Some(picture(PictureKind::COVER_FRONT, "b")));
+
+        assert_eq!(frames.remove(FrameId::PICTURE).len(), 1);
+        assert_eq!(frames.len(), 2);
+    }
+
+    #[test]
+    fn edit_v2_2() {
+        let mut frames = Frames::for_version(Version::V2_2);
+        frames.insert(Frame::new(FrameId::V22_PICTURE,
+            Body::Picture(picture(PictureKind::COVER_FRONT, "a"))));
+        frames.set_picture(picture(PictureKind::COVER_BACK, "a"));
+        assert_eq!(frames.iter().map(|f| f.id).collect::<Vec<_>>(), vec![FrameId::V22_PICTURE]);
+        assert_eq!(frames.remove_picture(PictureKind::COVER_BACK, "a"),
+            Some(picture(PictureKind::COVER_BACK, "a")));
+
+        frames.insert(Frame::new(FrameId::V22_COMMENT, Body::Comment(comment(b"eng", "", "1"))));
+        frames.set_comment(comment(b"eng", "", "2"));
+        assert_eq!(frames.iter().map(|f| f.id).collect::<Vec<_>>(), vec![FrameId::V22_COMMENT]);
+        assert_eq!(frames.remove_comment(Language::new(*b"eng"), ""),
+            Some(comment(b"eng", "", "2")));
+        assert!(frames.is_empty());
+    }
+
+    #[test]
+    fn set_picture() {
+        let mut frames = Frames::new();
+        frames.set_picture(picture(PictureKind::OTHER, "a"));
+        frames.set_picture(picture(PictureKind::OTHER, "b"));
+        frames.set_picture(picture(PictureKind::ICON, "c"));
+        frames.set_picture(picture(PictureKind::ICON, "d"));
+        frames.set_picture(picture(PictureKind::OTHER_ICON, "e"));
+        assert_eq!(frames.get(FrameId::PICTURE)
+            .map(|f| f.body.as_picture().unwrap().description.as_str())
+            .collect::<Vec<_>>(), vec!["a", "b", "d", "e"]);
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut frames = Frames::new();
+        frames.insert(text_frame(FrameId::TITLE, "a"));
+        frames.insert(text_frame(FrameId::ALBUM, "b"));
+        for f in frames.iter_mut() {
+            f.body.as_text_mut().unwrap().strings.push("c".into());
+        }
+        assert_eq!(frames.first_text(FrameId::TITLE).unwrap().strings, vec!["a", "c"]);
+        assert_eq!(frames.first_text(FrameId::ALBUM).unwrap().strings, vec!["b", "c"]);
+    }
+
+    #[test]
+    fn retain() {
+        let mut frames = Frames::new();
+        frames.insert(text_frame(FrameId::TITLE, "a"));
+        frames.insert(text_frame(FrameId::ALBUM, "b"));
+        frames.insert(text_frame(FrameId::ARTIST, "c"));
+        frames.retain(|f| {
+            if f.id == FrameId::ALBUM {
+                f.id = FrameId::TITLE;
+            }
+            f.id != FrameId::ARTIST
+        });
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames.first_text(FrameId::TITLE).unwrap().strings, vec!["a", "b"]);
+    }
+}
@@ -3,12 +3,15 @@ use std::io::prelude::*;
 
 use crate::error::*;
 use super::*;
-use super::super::string::Decoder;
+use super::super::string::{Decoder, Encoder};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum BodyKind {
     Bytes,
     Comment,
+    Encrypted,
+    EncryptionMethodReg,
+    GroupIdReg,
     Picture,
     Text,
     UniqueFileId,
@@ -24,6 +27,9 @@ pub enum BodyKind {
 pub enum Body {
     Bytes(Vec<u8>),
     Comment(Comment),
+    Encrypted(Encrypted),
+    EncryptionMethodReg(EncryptionMethodReg),
+    GroupIdReg(GroupIdReg),
     Picture(Picture),
     Text(Text),
     UniqueFileId(UniqueFileId),
@@ -41,6 +47,9 @@ impl Body {
         match self {
             Bytes(_) => BodyKind::Bytes,
             Comment(_) => BodyKind::Comment,
+            Encrypted(_) => BodyKind::Encrypted,
+            EncryptionMethodReg(_) => BodyKind::EncryptionMethodReg,
+            GroupIdReg(_) => BodyKind::GroupIdReg,
             Picture(_) => BodyKind::Picture,
             Text(_) => BodyKind::Text,
             UniqueFileId(_) => BodyKind::UniqueFileId,
@@ -66,6 +75,9 @@ impl Body {
                 debug_assert_eq!(v.description, o.description);
                 v.text.push_str(&o.text);
             }
+            Encrypted(v) => *v = o.into_encrypted().unwrap(),
+            EncryptionMethodReg(v) => *v = o.into_encryption_method_reg().unwrap(),
+            GroupIdReg(v) => *v = o.into_group_id_reg().unwrap(),
             Picture(v) => {
                 let mut o = o.into_picture().unwrap();
                 debug_assert_eq!(v.description, o.description);
@@ -102,7 +114,7 @@ impl Body {
         Self::decode(frame_id, bytes).map_err(|e| e.into_invalid_data_err())
     }
 
-    fn decode(frame_id: FrameId, buf: Vec<u8>) -> Result<Self> {
+    pub(crate) fn decode(frame_id: FrameId, buf: Vec<u8>) -> Result<Self> {
         if buf.is_empty() {
             return Err(Error("frame body is empty"));
         }
@@ -110,8 +122,13 @@ impl Body {
             FrameId::PICTURE => Picture::decode(&buf).map(Body::Picture),
             FrameId::V22_PICTURE => Picture::decode_v22(&buf).map(Body::Picture),
             FrameId::COMMENT | FrameId::V22_COMMENT => Comment::decode(&buf).map(Body::Comment),
-            FrameId::USER_TEXT => UserText::decode(&buf).map(Body::UserText),
+            FrameId::ENCRYPTION => EncryptionMethodReg::decode(&buf).map(Body::EncryptionMethodReg),
+            FrameId::GROUP_ID => GroupIdReg::decode(&buf).map(Body::GroupIdReg),
+            FrameId::USER_TEXT | FrameId::V22_USER_TEXT =>
+                UserText::decode(&buf).map(Body::UserText),
             FrameId::USER_URL => UserUrl::decode(&buf).map(Body::UserUrl),
+            // Involved people list is laid out as a text frame.
+            FrameId::INVOLVED_PEOPLE_LIST | FrameId::V22_PEOPLE => Text::decode(&buf).map(Body::Text),
             _ if frame_id.is_text() => Text::decode(&buf).map(Body::Text),
             _ if frame_id.is_url() => Self::decode_url(&buf).map(Body::Url),
             _ => Ok(Body::Bytes(buf)),
@@ -122,12 +139,49 @@ impl Body {
         let url =  Decoder::new(Encoding::Latin1).decode_maybe_null_terminated(buf)?;
         Ok(url)
     }
+
+    /// Encodes the body for v2.3 or v2.4 frame. The text encodings are adjusted to the ones
+    /// supported by the `version`.
+    pub(crate) fn encode(&self, version: Version) -> Result<Vec<u8>> {
+        use Body::*;
+        let mut buf = Vec::new();
+        match self {
+            Bytes(v) => buf.extend_from_slice(v),
+            Comment(v) => v.encode(version, &mut buf)?,
+            Encrypted(v) => buf.extend_from_slice(&v.data),
+            EncryptionMethodReg(v) => {
+                Encoder::new(Encoding::Latin1).encode_null_terminated(&v.owner_id, &mut buf)?;
+                buf.push(v.method);
+                buf.extend_from_slice(&v.data);
+            }
+            GroupIdReg(v) => {
+                Encoder::new(Encoding::Latin1).encode_null_terminated(&v.owner_id, &mut buf)?;
+                buf.push(v.group_id);
+                buf.extend_from_slice(&v.data);
+            }
+            Picture(v) => v.encode(version, &mut buf)?,
+            Text(v) => v.encode(version, &mut buf)?,
+            UniqueFileId(v) => {
+                Encoder::new(Encoding::Latin1).encode_null_terminated(&v.owner_id, &mut buf)?;
+                buf.extend_from_slice(&v.id);
+            }
+            Url(v) => Encoder::new(Encoding::Latin1).encode(v, &mut buf)?,
+            UserText(v) => v.encode(version, &mut buf)?,
+            UserUrl(v) => v.encode(version, &mut buf)?,
+            __Nonexhaustive => unreachable!(),
+        }
+        Ok(buf)
+    }
 }
 
 impl_as_into!(
 Body:
     into_bytes, as_bytes, as_bytes_mut <= Bytes ( Vec<u8> ),
     into_comment, as_comment, as_comment_mut <= Comment ( Comment ),
+    into_encrypted, as_encrypted, as_encrypted_mut <= Encrypted ( Encrypted ),
+    into_encryption_method_reg, as_encryption_method_reg, as_encryption_method_reg_mut
+        <= EncryptionMethodReg ( EncryptionMethodReg ),
+    into_group_id_reg, as_group_id_reg, as_group_id_reg_mut <= GroupIdReg ( GroupIdReg ),
     into_picture, as_picture, as_picture_mut <= Picture ( Picture ),
     into_text, as_text, as_text_mut <= Text ( Text ),
     into_unique_file_id, as_unqiue_file_id, as_sunqiue_file_id_mut <= UniqueFileId ( UniqueFileId ),
@@ -151,6 +205,12 @@ impl Text {
             strings,
         })
     }
+
+    fn encode(&self, version: Version, buf: &mut Vec<u8>) -> Result<()> {
+        let encoding = self.encoding.for_version(version, self.strings.iter().map(|s| s.as_str()));
+        buf.push(encoding.to_u8());
+        Encoder::new(encoding).encode_null_delimited(self.strings.iter().map(|s| s.as_str()), buf)
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -173,6 +233,14 @@ impl UserText {
             values,
         })
     }
+
+    fn encode(&self, version: Version, buf: &mut Vec<u8>) -> Result<()> {
+        let strings = || Some(self.description.as_str()).into_iter()
+            .chain(self.values.iter().map(|s| s.as_str()));
+        let encoding = self.encoding.for_version(version, strings());
+        buf.push(encoding.to_u8());
+        Encoder::new(encoding).encode_null_delimited(strings(), buf)
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -206,6 +274,89 @@ impl Comment {
             text,
         })
     }
+
+    fn encode(&self, version: Version, buf: &mut Vec<u8>) -> Result<()> {
+        let encoding = self.encoding.for_version(version,
+            [self.description.as_str(), self.text.as_str()].iter().cloned());
+        let encoder = Encoder::new(encoding);
+        buf.push(encoding.to_u8());
+        buf.extend_from_slice(&self.lang.to_bytes());
+        encoder.encode_null_terminated(&self.description, buf)?;
+        encoder.encode(&self.text, buf)
+    }
+}
+
+/// Body of a frame encrypted with the method registered by `EncryptionMethodReg`.
+/// The data is kept as is since there's no way to decrypt it.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Encrypted {
+    /// Encryption method symbol.
+    pub method: u8,
+
+    /// Whether the data was compressed prior to encryption.
+    pub compressed: bool,
+
+    /// Decompressed size (v2.3) or data length indicator (v2.4) if present.
+    pub data_len: Option<u32>,
+
+    pub data: Vec<u8>,
+}
+
+impl fmt::Debug for Encrypted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Encrypted")
+            .field("method", &self.method)
+            .field("compressed", &self.compressed)
+            .field("data_len", &self.data_len)
+            .field("data", &display_to_debug(format!("<{} B>", self.data.len())))
+            .finish()
+    }
+}
+
+/// `ENCR` frame body.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncryptionMethodReg {
+    pub owner_id: String,
+    /// Symbol the encrypted frames refer to.
+    pub method: u8,
+    pub data: Vec<u8>,
+}
+
+impl EncryptionMethodReg {
+    fn decode(buf: &[u8]) -> Result<Self> {
+        let (owner_id, buf) = Decoder::new(Encoding::Latin1).decode_null_terminated(buf)?;
+        if buf.is_empty() {
+            return Err(Error("frame truncated"));
+        }
+        Ok(Self {
+            owner_id,
+            method: buf[0],
+            data: buf[1..].into(),
+        })
+    }
+}
+
+/// `GRID` frame body.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GroupIdReg {
+    pub owner_id: String,
+    /// Symbol the grouped frames refer to.
+    pub group_id: u8,
+    pub data: Vec<u8>,
+}
+
+impl GroupIdReg {
+    fn decode(buf: &[u8]) -> Result<Self> {
+        let (owner_id, buf) = Decoder::new(Encoding::Latin1).decode_null_terminated(buf)?;
+        if buf.is_empty() {
+            return Err(Error("frame truncated"));
+        }
+        Ok(Self {
+            owner_id,
+            group_id: buf[0],
+            data: buf[1..].into(),
+        })
+    }
 }
 
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -408,6 +559,16 @@ impl Picture {
             data,
         })
     }
+
+    fn encode(&self, version: Version, buf: &mut Vec<u8>) -> Result<()> {
+        let encoding = self.encoding.for_version(version, Some(self.description.as_str()));
+        buf.push(encoding.to_u8());
+        Encoder::new(Encoding::Latin1).encode_null_terminated(&self.content_type, buf)?;
+        buf.push(self.picture_kind.0);
+        Encoder::new(encoding).encode_null_terminated(&self.description, buf)?;
+        buf.extend_from_slice(&self.data);
+        Ok(())
+    }
 }
 
 impl fmt::Debug for Picture {
@@ -434,7 +595,7 @@ impl UserUrl {
         let encoding = Encoding::from_u8(buf[0])?;
         let decoder = Decoder::new(encoding);
         let (description, buf) = decoder.decode_null_terminated(&buf[1..])?;
-        let url = decoder.decode_null_stripped(buf)?;
+        let url = Decoder::new(Encoding::Latin1).decode_null_stripped(buf)?;
 
         Ok(Self {
             encoding,
@@ -442,4 +603,11 @@ impl UserUrl {
             url,
         })
     }
+
+    fn encode(&self, version: Version, buf: &mut Vec<u8>) -> Result<()> {
+        let encoding = self.encoding.for_version(version, Some(self.description.as_str()));
+        buf.push(encoding.to_u8());
+        Encoder::new(encoding).encode_null_terminated(&self.description, buf)?;
+        Encoder::new(Encoding::Latin1).encode(&self.url, buf)
+    }
 }
\ No newline at end of file
@@ -38,6 +38,7 @@ impl FrameId {
     pub const RFC_RELEASE_DATE: Self = frame_id!(b"TDRL");
 
     pub const TAGGING_DATE: Self = frame_id!(b"TDRL");
+    pub const TAGGING_TIME: Self = frame_id!(b"TDTG");
     pub const ENCODED_BY: Self = frame_id!(b"TENC");
     pub const LYRICIST: Self = frame_id!(b"TEXT");
     pub const FILE_TYPE: Self = frame_id!(b"TFLT");
@@ -212,10 +213,118 @@ impl FrameId {
         ]
     }
 
+    /// Returns `true` if the ID consists of `A-Z` and `0-9` characters only.
+    pub fn is_valid(&self) -> bool {
+        let len = if self.is_v22() { 3 } else { 4 };
+        self.to_bytes()[..len].iter().all(|&c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    }
+
     pub fn is_v22(&self) -> bool {
         self.0 & 0xff == 0
     }
 
+    /// Returns v2.3 counterpart of this v2.2 frame ID if there's one.
+    pub fn upgrade_v22(&self) -> Option<Self> {
+        Some(match *self {
+            Self::V22_ALBUM => Self::ALBUM,
+            Self::V22_BPM => Self::BPM,
+            Self::V22_COMPOSER => Self::COMPOSER,
+            Self::V22_GENRE => Self::GENRE,
+            Self::V22_COPYRIGHT => Self::COPYRIGHT,
+            Self::V22_DELAY => Self::DELAY,
+            Self::V22_ENCODED_BY => Self::ENCODED_BY,
+            Self::V22_LYRICIST => Self::LYRICIST,
+            Self::V22_FILE_TYPE => Self::FILE_TYPE,
+            Self::V22_GROUP_TITLE => Self::GROUP_TITLE,
+            Self::V22_TITLE => Self::TITLE,
+            Self::V22_SUBTITLE => Self::SUBTITLE,
+            Self::V22_PEOPLE => Self::INVOLVED_PEOPLE_LIST,
+            Self::V22_INITIAL_KEY => Self::INITIAL_KEY,
+            Self::V22_LANGUAGE => Self::LANGUAGE,
+            Self::V22_LENGTH => Self::LENGTH,
+            Self::V22_MEDIA_TYPE => Self::MEDIA_TYPE,
+            Self::V22_ORIGINAL_ALBUM => Self::ORIGINAL_ALBUM,
+            Self::V22_ORIGINAL_FILENAME => Self::ORIGINAL_FILENAME,
+            Self::V22_ORIGINAL_LYRICIST => Self::ORIGINAL_LYRICIST,
+            Self::V22_ORIGINAL_ARTIST => Self::ORIGINAL_ARTIST,
+            Self::V22_ARTIST => Self::ARTIST,
+            Self::V22_ALBUM_ARTIST => Self::ALBUM_ARTIST,
+            Self::V22_CONDUCTOR => Self::CONDUCTOR,
+            Self::V22_REMIXER => Self::REMIXER,
+            Self::V22_DISC => Self::DISC,
+            Self::V22_PUBLISHER => Self::PUBLISHER,
+            Self::V22_ISRC => Self::ISRC,
+            Self::V22_TRACK => Self::TRACK,
+            Self::V22_ENCODER_SETTINGS => Self::ENCODER_SETTINGS,
+            Self::V22_USER_TEXT => Self::USER_TEXT,
+            Self::V22_DATE => Self::V23_DATE,
+            Self::V22_TIME => Self::V23_TIME,
+            Self::V22_RECORDING_DATES => Self::V23_RECORDING_DATES,
+            Self::V22_SIZE => Self::V23_SIZE,
+            Self::V22_YEAR => Self::V23_YEAR,
+            Self::V22_COMMERCIAL_URL => Self::COMMERCIAL_URL,
+            Self::V22_COPYRIGHT_URL => Self::COPYRIGHT_URL,
+            Self::V22_AUDIO_FILE_URL => Self::AUDIO_FILE_URL,
+            Self::V22_ARTIST_URL => Self::ARTIST_URL,
+            Self::V22_AUDIO_SOURCE_URL => Self::AUDIO_SOURCE_URL,
+            Self::V22_PUBLISHER_URL => Self::PUBLISHER_URL,
+            Self::V22_USER_URL => Self::USER_URL,
+            Self::V22_AUDIO_ENCRYPTION => Self::AUDIO_ENCRYPTION,
+            Self::V22_PICTURE => Self::PICTURE,
+            Self::V22_COMMENT => Self::COMMENT,
+            Self::V22_EQUALIZATION => Self::V23_EQUALIZATION,
+            Self::V22_EVENT_TIMING_CODES => Self::EVENT_TIMING_CODES,
+            Self::V22_GENERAL_ENCAP_OBJECT => Self::GENERAL_ENCAP_OBJECT,
+            Self::V22_MUSIC_CD_ID => Self::MUSIC_CD_ID,
+            Self::V22_MPEG_LOCATION_LUT => Self::MPEG_LOCATION_LUT,
+            Self::V22_POPULARIMETER => Self::POPULARIMETER,
+            Self::V22_RELATIVE_VOL_ADJUST => Self::V23_RELATIVE_VOL_ADJUST,
+            Self::V22_REVERB => Self::REVERB,
+            Self::V22_SYNC_LYRICS => Self::SYNC_LYRICS,
+            Self::V22_SYNC_TEMPO_CODES => Self::SYNC_TEMPO_CODES,
+            Self::V22_UNIQUE_FILE_ID => Self::UNIQUE_FILE_ID,
+            Self::V22_UNSYNC_LYRICS => Self::UNSYNC_LYRICS,
+            _ => return None,
+        })
+    }
+
+    /// Returns `true` if the frame exists in v2.3 but not in v2.4.
+    pub fn is_v23_only(&self) -> bool {
+        matches!(*self,
+            Self::INVOLVED_PEOPLE_LIST
+            | Self::V23_DATE
+            | Self::V23_EQUALIZATION
+            | Self::V23_ORIGINAL_RELEASE_YEAR
+            | Self::V23_RECORDING_DATES
+            | Self::V23_RELATIVE_VOL_ADJUST
+            | Self::V23_SIZE
+            | Self::V23_TIME
+            | Self::V23_YEAR)
+    }
+
+    /// Returns `true` if the frame exists in v2.4 but not in v2.3.
+    pub fn is_v24_only(&self) -> bool {
+        matches!(*self,
+            Self::ALBUM_SORT_ORDER
+            | Self::ARTIST_SORT_ORDER
+            | Self::AUDIO_SEEK_POINT_INDEX
+            | Self::DISC_SUBTITLE
+            | Self::ENCODING_DATE
+            | Self::EQUALIZATION
+            | Self::MOOD
+            | Self::ORIGINAL_RELEASE_DATE
+            | Self::PEOPLE
+            | Self::PERFORMERS
+            | Self::PRODUCED_NOTICE
+            | Self::RELATIVE_VOL_ADJUST
+            | Self::RELEASE_DATE
+            | Self::RFC_RELEASE_DATE
+            | Self::SEEK
+            | Self::SIGNATURE
+            | Self::TAGGING_TIME
+            | Self::TITLE_SORT_ORDER)
+    }
+
     pub fn text(sub_id: [u8; 3]) -> Self {
         Self::prefixed(b'T', sub_id)
     }
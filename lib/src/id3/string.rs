@@ -1,5 +1,5 @@
 use encoding::all::{ISO_8859_1, UTF_16BE, UTF_16LE};
-use encoding::{DecoderTrap, Encoding as _};
+use encoding::{DecoderTrap, EncoderTrap, Encoding as _};
 
 pub use crate::id3::frame::Encoding;
 use crate::error::*;
@@ -77,7 +77,7 @@ impl Decoder {
         while let Some(i) = self.find_null(buf) {
             let s = self.decode(&buf[..i])?;
             r.push(s);
-            buf = &buf[i + 1..];
+            buf = &buf[i + self.null_len()..];
         }
         r.push(self.decode(buf)?);
 
@@ -115,4 +115,64 @@ impl Decoder {
             s.iter().position(|&c| c == 0)
         }
     }
-}
\ No newline at end of file
+}
+
+pub struct Encoder {
+    encoding: Encoding,
+}
+
+impl Encoder {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+        }
+    }
+
+    /// Returns `true` if `s` can be encoded with ISO-8859-1.
+    pub fn is_latin1(s: &str) -> bool {
+        s.chars().all(|c| (c as u32) < 0x100)
+    }
+
+    pub fn encode(&self, s: &str, buf: &mut Vec<u8>) -> Result<()> {
+        use Encoding::*;
+        match self.encoding {
+            Latin1 => ISO_8859_1.encode_to(s, EncoderTrap::Strict, buf)
+                .map_err(|_| Error("string can't be encoded with ISO-8859-1"))?,
+            Utf16 => {
+                buf.extend_from_slice(&[0xff, 0xfe]);
+                UTF_16LE.encode_to(s, EncoderTrap::Strict, buf)
+                    .map_err(|_| Error("bad UTF-16LE string"))?;
+            }
+            Utf16BE => UTF_16BE.encode_to(s, EncoderTrap::Strict, buf)
+                .map_err(|_| Error("bad UTF-16BE string"))?,
+            Utf8 => buf.extend_from_slice(s.as_bytes()),
+        }
+        Ok(())
+    }
+
+    pub fn encode_null_terminated(&self, s: &str, buf: &mut Vec<u8>) -> Result<()> {
+        self.encode(s, buf)?;
+        self.push_null(buf);
+        Ok(())
+    }
+
+    pub fn encode_null_delimited<'a>(&self, strings: impl IntoIterator<Item=&'a str>,
+        buf: &mut Vec<u8>) -> Result<()>
+    {
+        for (i, s) in strings.into_iter().enumerate() {
+            if i > 0 {
+                self.push_null(buf);
+            }
+            self.encode(s, buf)?;
+        }
+        Ok(())
+    }
+
+    fn push_null(&self, buf: &mut Vec<u8>) {
+        use Encoding::*;
+        match self.encoding {
+            Latin1 | Utf8 => buf.push(0),
+            Utf16 | Utf16BE => buf.extend_from_slice(&[0, 0]),
+        }
+    }
+}
@@ -14,4 +14,87 @@ pub fn decode_u32(b: &[u8]) -> Option<u32> {
         if b[2] < 0x80 { (b[2] as u32) <<  7 } else { return None; } |
         if b[3] < 0x80 { (b[3] as u32) <<  0 } else { return None; }
     )
-}
\ No newline at end of file
+}
+
+/// Returns `None` if `v` doesn't fit into 28 bits.
+pub fn encode_u32(v: u32) -> Option<[u8; 4]> {
+    if v >= 1 << 28 {
+        return None;
+    }
+    Some([
+        (v >> 21) as u8 & 0x7f,
+        (v >> 14) as u8 & 0x7f,
+        (v >> 7) as u8 & 0x7f,
+        v as u8 & 0x7f,
+    ])
+}
+
+/// Applies the unsynchronisation scheme by inserting `0x00` after every `0xFF` that is followed
+/// by `0x00` or by a byte with three most significant bits set, or that ends the data.
+pub fn encode(buf: &[u8]) -> Vec<u8> {
+    let mut r = Vec::with_capacity(buf.len());
+    for (i, &b) in buf.iter().enumerate() {
+        r.push(b);
+        if b == 0xff && buf.get(i + 1).map(|&next| next == 0 || next >= 0xe0).unwrap_or(true) {
+            r.push(0);
+        }
+    }
+    r
+}
+
+/// Reverses the unsynchronisation scheme in place by replacing every `0xFF 0x00` with `0xFF`.
+pub fn decode(buf: &mut Vec<u8>) {
+    let mut len = 0;
+    let mut prev = 0;
+    for i in 0..buf.len() {
+        let b = buf[i];
+        if prev != 0xff || b != 0 {
+            buf[len] = b;
+            len += 1;
+        }
+        prev = b;
+    }
+    buf.truncate(len);
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn encode_u32() {
+        assert_eq!(super::encode_u32(0), Some([0, 0, 0, 0]));
+        assert_eq!(super::encode_u32(0x1234), Some([0, 0, 0x24, 0x34]));
+        assert_eq!(super::encode_u32((1 << 28) - 1), Some([0x7f; 4]));
+        assert_eq!(super::encode_u32(1 << 28), None);
+        assert_eq!(super::decode_u32(&super::encode_u32(123_456).unwrap()), Some(123_456));
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(super::encode(&[]), &[]);
+        assert_eq!(super::encode(&[0xff]), &[0xff, 0x00]);
+        assert_eq!(super::encode(&[0xff, 0x00]), &[0xff, 0x00, 0x00]);
+        assert_eq!(super::encode(&[0xff, 0xe0, 0xff, 0xdf]), &[0xff, 0x00, 0xe0, 0xff, 0xdf]);
+        assert_eq!(super::encode(&[0xfe, 0xff, 0x01, 0xff]), &[0xfe, 0xff, 0x01, 0xff, 0x00]);
+
+        let data = [0xff, 0xff, 0x00, 0xe0, 0xff, 0xff];
+        let mut v = super::encode(&data);
+        super::decode(&mut v);
+        assert_eq!(v, &data);
+    }
+
+    #[test]
+    fn decode() {
+        fn d(v: &[u8]) -> Vec<u8> {
+            let mut v = v.to_vec();
+            super::decode(&mut v);
+            v
+        }
+
+        assert_eq!(d(&[]), &[]);
+        assert_eq!(d(&[0xff]), &[0xff]);
+        assert_eq!(d(&[0xff, 0x00]), &[0xff]);
+        assert_eq!(d(&[0xff, 0x00, 0x00]), &[0xff, 0x00]);
+        assert_eq!(d(&[0xff, 0x00, 0xe0, 0xff, 0x00, 0xff, 0x00]), &[0xff, 0xe0, 0xff, 0xff]);
+        assert_eq!(d(&[0x00, 0xfe, 0x00, 0xff, 0x01]), &[0x00, 0xfe, 0x00, 0xff, 0x01]);
+    }
+}
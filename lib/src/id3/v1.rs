@@ -668,6 +668,35 @@ impl Id3v1 {
         (if self.ext.is_some() { FULL_LEN } else { LEN }) as u32
     }
 
+    /// Encodes the tag into 128 bytes block or 355 bytes if there's the extended tag, which
+    /// precedes the v1 block. The strings are encoded as ISO-8859-1 with the unsupported chars
+    /// replaced by `?` and truncated to the field len. The track is written as in v1.1
+    /// shortening the comment to 28 bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut r = Vec::with_capacity(self.len() as usize);
+        if let Some(ext) = &self.ext {
+            ext.encode(&mut r);
+        }
+
+        let buf = &mut [0; LEN];
+        buf[..3].copy_from_slice(b"TAG");
+        encode_str(&self.title, &mut buf[3..33]);
+        encode_str(&self.artist, &mut buf[33..63]);
+        encode_str(&self.album, &mut buf[63..93]);
+        encode_str(&self.year, &mut buf[93..97]);
+        match self.track {
+            Some(track) if track != 0 => {
+                encode_str(&self.comment, &mut buf[97..125]);
+                buf[126] = track;
+            }
+            _ => encode_str(&self.comment, &mut buf[97..127]),
+        }
+        buf[127] = self.genre.map(|g| g.0).unwrap_or(255);
+        r.extend_from_slice(buf);
+
+        r
+    }
+
     pub(crate) fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
         let mut data = [0; FULL_LEN];
         for &len in &[FULL_LEN, LEN] {
@@ -694,7 +723,8 @@ impl Id3v1 {
                 }
             }
         }
-        unreachable!()
+        // The file is too short.
+        Err(Error("ID3v1 is truncated").into_io_err(ErrorKind::UnexpectedEof))
     }
 
     fn decode(buf: &[u8]) -> Result<Self> {
@@ -702,7 +732,7 @@ impl Id3v1 {
             return Err(Error("couldn't find ID3v1 magic"));
         }
 
-        let ext = if buf[4] == b'+' {
+        let ext = if buf[3] == b'+' {
             if buf.len() < LEN + EXT_LEN || &buf[EXT_LEN..EXT_LEN + 3] != b"TAG" {
                 return Err(Error("couldn't find ID3v1 magic"));
             }
@@ -765,8 +795,8 @@ impl Ext {
         let album = decode_str(&buf[124..184]);
         let speed = if buf[184] == 0 { None } else { Some(buf[184]) };
         let genre = decode_str(&buf[185..215]);
-        let start_time = decode_str(&buf[185..215]);
-        let end_time = decode_str(&buf[185..215]);
+        let start_time = decode_str(&buf[215..221]);
+        let end_time = decode_str(&buf[221..227]);
         Self {
             title,
             artist,
@@ -777,8 +807,79 @@ impl Ext {
             end_time,
         }
     }
+
+    fn encode(&self, r: &mut Vec<u8>) {
+        let buf = &mut [0; EXT_LEN];
+        buf[..4].copy_from_slice(b"TAG+");
+        encode_str(&self.title, &mut buf[4..64]);
+        encode_str(&self.artist, &mut buf[64..124]);
+        encode_str(&self.album, &mut buf[124..184]);
+        buf[184] = self.speed.unwrap_or(0);
+        encode_str(&self.genre, &mut buf[185..215]);
+        encode_str(&self.start_time, &mut buf[215..221]);
+        encode_str(&self.end_time, &mut buf[221..227]);
+        r.extend_from_slice(buf);
+    }
 }
 
 fn decode_str(buf: &[u8]) -> String {
     Decoder::new(Encoding::Latin1).decode_maybe_null_terminated(buf).unwrap()
-}
\ No newline at end of file
+}
+
+/// Writes `s` as ISO-8859-1 into the zero-filled `buf` truncating it if needed.
+fn encode_str(s: &str, buf: &mut [u8]) {
+    for (c, b) in s.chars().zip(buf.iter_mut()) {
+        *b = if (c as u32) < 0x100 { c as u8 } else { b'?' };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        let tag = Id3v1 {
+            title: "title \u{e9}\u{263a}".into(),
+            artist: "a".repeat(40),
+            album: "album".into(),
+            year: "2001".into(),
+            comment: "c".repeat(29),
+            track: Some(7),
+            genre: Some(Genre::ROCK),
+            ext: None,
+        };
+        let buf = tag.encode();
+        assert_eq!(buf.len(), LEN);
+        assert_eq!(&buf[3..11], b"title \xe9?");
+        assert_eq!(Id3v1::decode(&buf).unwrap(), Id3v1 {
+            title: "title \u{e9}?".into(),
+            artist: "a".repeat(30),
+            comment: "c".repeat(28),
+            ..tag.clone()
+        });
+
+        let tag = Id3v1 {
+            comment: "c".repeat(30),
+            track: None,
+            genre: None,
+            ext: Some(Ext {
+                title: "t".repeat(60),
+                artist: "artist".into(),
+                album: "album".into(),
+                speed: Some(2),
+                genre: "genre".into(),
+                start_time: "001:02".into(),
+                end_time: "003:04".into(),
+            }),
+            ..tag
+        };
+        let buf = tag.encode();
+        assert_eq!(buf.len(), FULL_LEN);
+        let actual = Id3v1::decode(&buf).unwrap();
+        assert_eq!(actual.comment, tag.comment);
+        assert_eq!(actual.track, None);
+        assert_eq!(actual.genre, None);
+        assert_eq!(actual.ext, tag.ext);
+    }
+}
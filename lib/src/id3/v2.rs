@@ -1,30 +1,282 @@
 use bit_field::BitField;
-use byteorder::{ReadBytesExt, BigEndian};
+use byteorder::{BigEndian, ByteOrder};
+use std::cmp;
 use std::io::prelude::*;
-use std::io;
+use std::io::{self, Cursor, SeekFrom};
+use std::ops::Range;
 
 use crate::error::*;
 use crate::timestamp::Timestamp;
 use crate::util::*;
 use super::Version;
-use super::frame::{FrameId, Frames};
+use super::frame::{decode_v2_3_date, FrameId, Frames};
 use super::frame::body::Picture;
 use super::unsynch;
 
 pub(crate) const HEADER_LEN: usize = 10;
 
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum TagSizeRestriction {
+    /// No more than 128 frames and 1 MB total tag size.
+    Frames128Len1M,
+
+    /// No more than 64 frames and 128 KB total tag size.
+    Frames64Len128K,
+
+    /// No more than 32 frames and 40 KB total tag size.
+    Frames32Len40K,
+
+    /// No more than 32 frames and 4 KB total tag size.
+    Frames32Len4K,
+}
+
+impl TagSizeRestriction {
+    pub fn max_frame_count(&self) -> u32 {
+        use TagSizeRestriction::*;
+        match self {
+            Frames128Len1M => 128,
+            Frames64Len128K => 64,
+            Frames32Len40K | Frames32Len4K => 32,
+        }
+    }
+
+    pub fn max_len_bytes(&self) -> u32 {
+        use TagSizeRestriction::*;
+        match self {
+            Frames128Len1M => 1024 * 1024,
+            Frames64Len128K => 128 * 1024,
+            Frames32Len40K => 40 * 1024,
+            Frames32Len4K => 4 * 1024,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum TextEncodingRestriction {
+    None,
+
+    /// Strings are only encoded with ISO-8859-1 or UTF-8.
+    Latin1OrUtf8,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum TextLenRestriction {
+    None,
+
+    /// No string is longer than 1024 characters.
+    Chars1024,
+
+    /// No string is longer than 128 characters.
+    Chars128,
+
+    /// No string is longer than 30 characters.
+    Chars30,
+}
+
+impl TextLenRestriction {
+    pub fn max_chars(&self) -> Option<u32> {
+        use TextLenRestriction::*;
+        match self {
+            None => Option::None,
+            Chars1024 => Some(1024),
+            Chars128 => Some(128),
+            Chars30 => Some(30),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ImageFormatRestriction {
+    None,
+
+    /// Images are encoded only with PNG or JPEG.
+    PngOrJpeg,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ImageSizeRestriction {
+    None,
+
+    /// All images are 256x256 pixels or smaller.
+    Max256,
+
+    /// All images are 64x64 pixels or smaller.
+    Max64,
+
+    /// All images are exactly 64x64 pixels, unless required otherwise.
+    Exact64,
+}
+
+/// Tag restrictions (v2.4). These are the restrictions the tagger is supposed to follow
+/// when altering the tag.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Restrictions {
+    pub tag_size: TagSizeRestriction,
+    pub text_encoding: TextEncodingRestriction,
+    pub text_len: TextLenRestriction,
+    pub image_format: ImageFormatRestriction,
+    pub image_size: ImageSizeRestriction,
+}
+
+impl Restrictions {
+    fn from_u8(v: u8) -> Self {
+        let tag_size = match v.get_bits(6..8) {
+            0b00 => TagSizeRestriction::Frames128Len1M,
+            0b01 => TagSizeRestriction::Frames64Len128K,
+            0b10 => TagSizeRestriction::Frames32Len40K,
+            0b11 => TagSizeRestriction::Frames32Len4K,
+            _ => unreachable!(),
+        };
+        let text_encoding = if v.get_bit(5) {
+            TextEncodingRestriction::Latin1OrUtf8
+        } else {
+            TextEncodingRestriction::None
+        };
+        let text_len = match v.get_bits(3..5) {
+            0b00 => TextLenRestriction::None,
+            0b01 => TextLenRestriction::Chars1024,
+            0b10 => TextLenRestriction::Chars128,
+            0b11 => TextLenRestriction::Chars30,
+            _ => unreachable!(),
+        };
+        let image_format = if v.get_bit(2) {
+            ImageFormatRestriction::PngOrJpeg
+        } else {
+            ImageFormatRestriction::None
+        };
+        let image_size = match v.get_bits(0..2) {
+            0b00 => ImageSizeRestriction::None,
+            0b01 => ImageSizeRestriction::Max256,
+            0b10 => ImageSizeRestriction::Max64,
+            0b11 => ImageSizeRestriction::Exact64,
+            _ => unreachable!(),
+        };
+        Self {
+            tag_size,
+            text_encoding,
+            text_len,
+            image_format,
+            image_size,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExtendedHeader {
+    /// Padding len (v2.3).
+    pub padding_len: Option<u32>,
+
+    /// Whether the tag is an update of a tag found earlier in the file (v2.4).
+    pub update: bool,
+
+    /// CRC-32 of the frames. In v2.3 the padding is excluded from the CRC data.
+    /// The CRC is verified when the tag is read.
+    pub crc: Option<u32>,
+
+    /// Tag restrictions (v2.4).
+    pub restrictions: Option<Restrictions>,
+}
+
+impl ExtendedHeader {
+    /// Returns the header and its len in bytes.
+    fn read<T: Read>(rd: &mut Limited<T>, version: Version, size_bytes: [u8; 4])
+        -> io::Result<(Self, u32)>
+    {
+        if version.minor == 3 {
+            // Extended header len excludes the size bytes.
+            let len = BigEndian::read_u32(&size_bytes);
+            if len < 6 {
+                return Err(Error("extended header size is too small").into_invalid_data_err());
+            }
+            let data = read_vec_limited(rd, len as usize, "extended header is truncated")?;
+            let crc_present = data[0].get_bit(7);
+            let padding_len = BigEndian::read_u32(&data[2..6]);
+            let crc = if crc_present {
+                if data.len() < 10 {
+                    return Err(Error("extended header CRC is truncated").into_invalid_data_err());
+                }
+                Some(BigEndian::read_u32(&data[6..10]))
+            } else {
+                None
+            };
+
+            Ok((Self {
+                padding_len: Some(padding_len),
+                update: false,
+                crc,
+                restrictions: None,
+            }, len + 4))
+        } else {
+            let len = unsynch::decode_u32(&size_bytes)
+                .ok_or_else(|| Error("extended header size is not synch safe").into_invalid_data_err())?;
+            if len < 6 {
+                return Err(Error("extended header size is too small").into_invalid_data_err());
+            }
+            let data = read_vec_limited(rd, len as usize - 4, "extended header is truncated")?;
+            let flag_bytes_len = data[0] as usize;
+            if flag_bytes_len == 0 || data.len() < 1 + flag_bytes_len {
+                return Err(Error("bad extended header flags").into_invalid_data_err());
+            }
+            let flags = data[1];
+
+            // Each flag has its data prefixed with the data len.
+            let mut buf = &data[1 + flag_bytes_len..];
+            let mut read_flag_data = |expected_len: usize| {
+                if buf.is_empty() || buf[0] as usize != expected_len || buf.len() < 1 + expected_len {
+                    return Err(Error("bad extended header flag data").into_invalid_data_err());
+                }
+                let r = &buf[1..1 + expected_len];
+                buf = &buf[1 + expected_len..];
+                Ok(r)
+            };
+
+            let update = flags.get_bit(6);
+            if update {
+                read_flag_data(0)?;
+            }
+            let crc = if flags.get_bit(5) {
+                let b = read_flag_data(5)?;
+                if b[0] > 0x0f {
+                    return Err(Error("bad extended header CRC").into_invalid_data_err());
+                }
+                let crc = unsynch::decode_u32(&b[1..])
+                    .ok_or_else(|| Error("bad extended header CRC").into_invalid_data_err())?;
+                Some((b[0] as u32) << 28 | crc)
+            } else {
+                None
+            };
+            let restrictions = if flags.get_bit(4) {
+                Some(Restrictions::from_u8(read_flag_data(1)?[0]))
+            } else {
+                None
+            };
+
+            Ok((Self {
+                padding_len: None,
+                update,
+                crc,
+                restrictions,
+            }, len))
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Header {
     pub version: Version,
     pub unsynch: bool,
     pub extended: bool,
+    /// `None` if the extended header flag is set but the extended header is missing.
+    pub extended_header: Option<ExtendedHeader>,
     pub experimental: bool,
     pub footer_present: bool,
 }
 
 impl Header {
-    fn read<T: Read>(rd: &mut Limited<T>, bytes: &[u8; HEADER_LEN], len: u32)
-        -> io::Result<(Self, u32, u32)>
+    /// Returns the header, the extended header len and the bytes that were read past the header
+    /// but belong to the frames.
+    fn read<T: Read>(rd: &mut Limited<T>, bytes: &[u8; HEADER_LEN])
+        -> io::Result<(Self, u32, Option<[u8; 4]>)>
     {
         let version = Version::new(2, bytes[3], bytes[4]);
         if version.minor < 2 || version.minor > 4 {
@@ -62,38 +314,56 @@ impl Header {
         let experimental = flags.get_bit(5);
         let footer_present = flags.get_bit(4);
 
-        let ext_len = if extended {
-            // FIXME https://github.com/quodlibet/quodlibet/issues/126
-            let (ext_len, _data_len) = if version >= Version::V2_4 {
-                let ext_len = unsynch::read_u32(rd)?
-                    .ok_or_else(|| Error("extended header size is not synch safe").into_invalid_data_err())?;
-                if ext_len < 4 {
-                    return Err(Error("extended header size is too small").into_invalid_data_err());
-                }
-                (ext_len, ext_len - 4)
+        let (extended_header, ext_len, frames_prefix) = if extended {
+            let mut size_bytes = [0; 4];
+            rd.read_exact(&mut size_bytes)?;
+            if size_bytes.iter().all(|&c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+                // Some taggers set the flag without writing the extended header, in this case
+                // the frames follow the header.
+                // See https://github.com/quodlibet/quodlibet/issues/126
+                (None, 0, Some(size_bytes))
             } else {
-                let ext_len = rd.read_u32::<BigEndian>()?;
-                (ext_len, ext_len)
-            };
-
-            let _ext_data = read_vec_limited(rd, ext_len as usize, "extended header is truncated");
-
-            ext_len
+                let (ext, ext_len) = ExtendedHeader::read(rd, version, size_bytes)?;
+                (Some(ext), ext_len, None)
+            }
         } else {
-            0
+            (None, 0, None)
         };
 
-        let footer_len = if footer_present { HEADER_LEN as u32 } else { 0 };
-        let tag_len = HEADER_LEN as u32 + ext_len + len + footer_len;
-
         let header = Header {
             version,
             unsynch,
             extended,
+            extended_header,
             experimental,
             footer_present,
         };
-        Ok((header, ext_len, tag_len))
+        Ok((header, ext_len, frames_prefix))
+    }
+}
+
+/// Options for `Id3v2::encode`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncodeOptions {
+    /// Tag version, must be v2.3 or v2.4. If `None` the version of the tag is kept, v2.2 tags
+    /// are encoded as v2.3.
+    pub version: Option<Version>,
+
+    /// Len of the zero padding written after the frames.
+    pub padding_len: u32,
+
+    /// Whether to apply the unsynchronisation. It's done to the whole tag in v2.3 and to each
+    /// frame in v2.4.
+    pub unsynch: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            version: None,
+            padding_len: 1024,
+            unsynch: false,
+        }
     }
 }
 
@@ -104,6 +374,21 @@ pub struct Id3v2 {
 }
 
 impl Id3v2 {
+    /// Creates an empty tag of the specified version.
+    pub fn new(version: Version) -> Self {
+        Self {
+            header: Header {
+                version,
+                unsynch: false,
+                extended: false,
+                extended_header: None,
+                experimental: false,
+                footer_present: false,
+            },
+            frames: Frames::for_version(version),
+        }
+    }
+
     pub fn header(&self) -> &Header {
         &self.header
     }
@@ -112,6 +397,10 @@ impl Id3v2 {
         &self.frames
     }
 
+    pub fn frames_mut(&mut self) -> &mut Frames {
+        &mut self.frames
+    }
+
     pub fn album(&self) -> Option<&str> {
         self.frames.first_text_str(self.fid(FrameId::ALBUM, FrameId::V22_ALBUM))
     }
@@ -133,24 +422,131 @@ impl Id3v2 {
             let s = self.frames.first_text_str(FrameId::RELEASE_DATE)?;
             s.parse().ok()
         } else {
-            let year = self.frames.first_text_str(self.fid(FrameId::V23_YEAR, FrameId::V22_YEAR))
-                .and_then(|s| s.parse().ok());
-            if let Some(year) = year {
-                let month_day = self.frames.first_text_str(self.fid(FrameId::V23_DATE, FrameId::V22_DATE))
-                    .filter(|s| s.len() == 4)
-                    .and_then(|s| s[..2].parse().ok().map(|m| (m, s[2..].parse().ok())));
-                let month = month_day.map(|(m, _)| m);
-                let day = month_day.and_then(|(_, d)| d);
-                Timestamp::new(year, month, day, None, None, None)
-            } else {
-                None
-            }
+            let year = self.frames.first_text_str(self.fid(FrameId::V23_YEAR, FrameId::V22_YEAR))?;
+            let date = self.frames.first_text_str(self.fid(FrameId::V23_DATE, FrameId::V22_DATE));
+            let time = self.frames.first_text_str(self.fid(FrameId::V23_TIME, FrameId::V22_TIME));
+            decode_v2_3_date(year, date, time)
         }
     }
 
     pub fn pictures(&self) -> impl Iterator<Item=&Picture> {
         self.frames.get(self.fid(FrameId::PICTURE, FrameId::V22_PICTURE))
-            .map(|f| f.body.as_picture().unwrap())
+            .filter_map(|f| f.body.as_picture())
+    }
+
+    /// Offset from the end of this tag to the start of the next tag as specified by
+    /// the `SEEK` frame.
+    pub fn seek_offset(&self) -> Option<u32> {
+        self.frames.first(FrameId::SEEK)
+            .and_then(|f| f.body.as_bytes())
+            .filter(|b| b.len() == 4)
+            .map(|b| BigEndian::read_u32(b))
+    }
+
+    /// Merges the tag found later in the file into this one. If the later tag is an update
+    /// its frames override the corresponding frames of this tag, otherwise it replaces this tag.
+    fn merge_from(&mut self, o: Self) {
+        if o.header.extended_header.as_ref().map(|e| e.update) == Some(true) {
+            self.frames.update_from(o.frames);
+        } else {
+            *self = o;
+        }
+    }
+
+    /// Finds the tags that follow the `first` tag (if any) via the `SEEK` frame chain and the tag
+    /// appended to the data ending at `end` (identified by the footer) and merges them with
+    /// the `first` tag. `first` is the tag and the position where it ends.
+    /// Returns the merged tag, the position where the appended tag starts (or `end`) and
+    /// the positions of the found tags.
+    pub(crate) fn read_chained(rd: &mut (impl Read + Seek), first: Option<(Self, u64)>, end: u64)
+        -> io::Result<(Option<Self>, u64, Vec<Range<u64>>)>
+    {
+        // Guards against SEEK loops.
+        const MAX_CHAIN_LEN: usize = 16;
+
+        let appended = Self::read_appended(rd, end)?;
+        let appended_start = appended.as_ref().map(|(_, pos)| *pos).unwrap_or(end);
+
+        let mut merged = Vec::new();
+        let (mut tag, mut tag_end) = match first {
+            Some((tag, tag_end)) => (Some(tag), tag_end),
+            None => (None, 0),
+        };
+        for _ in 0..MAX_CHAIN_LEN {
+            let seek_offset = match tag.as_ref().and_then(|t| t.seek_offset()) {
+                Some(v) => v,
+                None => break,
+            };
+            let pos = tag_end + seek_offset as u64;
+            if pos >= appended_start {
+                break;
+            }
+            match Self::find(rd, pos, appended_start)? {
+                Some((next, next_pos, next_len)) => {
+                    tag.as_mut().unwrap().merge_from(next);
+                    tag_end = next_pos + next_len as u64;
+                    merged.push(next_pos..tag_end);
+                }
+                None => break,
+            }
+        }
+
+        if let Some((appended, _)) = appended {
+            merged.push(appended_start..end);
+            if let Some(tag) = &mut tag {
+                tag.merge_from(appended);
+            } else {
+                tag = Some(appended);
+            }
+        }
+
+        Ok((tag, appended_start, merged))
+    }
+
+    /// Looks for a tag starting at `pos` or shortly after it (the `SEEK` frame specifies
+    /// the minimum offset to the next tag). Returns the tag, its position and len.
+    fn find(rd: &mut (impl Read + Seek), pos: u64, end: u64) -> io::Result<Option<(Self, u64, u32)>> {
+        const MAX_SCAN_LEN: u64 = 4096;
+
+        let scan_len = cmp::min(end - pos, MAX_SCAN_LEN);
+        rd.seek(SeekFrom::Start(pos))?;
+        let mut buf = Vec::new();
+        rd.take(scan_len).read_to_end(&mut buf)?;
+        for (i, _) in buf.windows(3).enumerate().filter(|(_, w)| w == b"ID3") {
+            let tag_pos = pos + i as u64;
+            rd.seek(SeekFrom::Start(tag_pos))?;
+            if let Some((tag, tag_len)) = Self::read(rd, Some(end - tag_pos)).into_opt()? {
+                return Ok(Some((tag, tag_pos, tag_len)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the tag that ends at `end` by locating its footer.
+    /// Returns the tag and the position where it starts.
+    fn read_appended(rd: &mut (impl Read + Seek), end: u64) -> io::Result<Option<(Self, u64)>> {
+        if end < 2 * HEADER_LEN as u64 {
+            return Ok(None);
+        }
+        rd.seek(SeekFrom::Start(end - HEADER_LEN as u64))?;
+        let mut bytes = [0; HEADER_LEN];
+        rd.read_exact(&mut bytes)?;
+        if &bytes[..3] != b"3DI" || bytes[3] != 4 || bytes[4] == 0xff || !bytes[5].get_bit(4) {
+            return Ok(None);
+        }
+        let len = match unsynch::decode_u32(&bytes[6..10]) {
+            Some(v) => v as u64,
+            None => return Ok(None),
+        };
+
+        let start = match end.checked_sub(2 * HEADER_LEN as u64 + len) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        rd.seek(SeekFrom::Start(start))?;
+        Ok(Self::read(rd, Some(end - start)).into_opt()?
+            .filter(|&(_, tag_len)| tag_len as u64 == end - start)
+            .map(|(tag, _)| (tag, start)))
     }
 
     pub(crate) fn read(rd: &mut impl Read, limit: Option<u64>) -> io::Result<(Self, u32)> {
@@ -167,26 +563,103 @@ impl Id3v2 {
         let len = unsynch::decode_u32(&bytes[6..10])
             .ok_or_else(|| Error("bad tag len").into_invalid_data_err())?;
 
-        let (header, ext_len, tag_len) = Header::read(rd, &bytes, len)?;
+        // Tag len excludes the header and footer but includes the extended header.
+        let footer_present = bytes[5].get_bit(4);
+        let footer_len = if footer_present { HEADER_LEN as u32 } else { 0 };
+        let tag_len = HEADER_LEN as u32 + len + footer_len;
+
+        // Prior to v2.4 unsynchronisation is applied to everything after the header while
+        // in v2.4 it's done on per-frame basis.
+        let tag = if bytes[3] < 4 && bytes[5].get_bit(7) {
+            let mut buf = read_vec_limited(rd, len as usize, "tag truncated")?;
+            unsynch::decode(&mut buf);
+            let len = buf.len() as u32;
+            Self::read_body(&mut Limited::new(Cursor::new(buf), len as u64), &bytes, len)?
+        } else {
+            Self::read_body(rd, &bytes, len)?
+        };
+
+        Ok((tag, tag_len))
+    }
+
+    /// Encodes the tag according to the `options`. Neither extended header nor footer
+    /// is written.
+    pub fn encode(&self, options: &EncodeOptions) -> io::Result<Vec<u8>> {
+        self.encode0(options).map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))
+    }
 
-        if header.unsynch {
-            // FIXME decode
-            panic!("FIXME");
+    fn encode0(&self, options: &EncodeOptions) -> Result<Vec<u8>> {
+        let version = options.version.unwrap_or(if self.header.version.minor == 4 {
+            Version::V2_4
+        } else {
+            Version::V2_3
+        });
+        if version != Version::V2_3 && version != Version::V2_4 {
+            return Err(Error("only v2.3 and v2.4 tags can be encoded"));
+        }
+
+        let mut frames = self.frames.encode(version, options.unsynch)?;
+        if version.minor == 3 && options.unsynch {
+            frames = unsynch::encode(&frames);
         }
 
-        let frames_len = len - ext_len;
-        if rd.max_available() < frames_len as u64 {
+        let len = (frames.len() as u64).checked_add(options.padding_len as u64)
+            .filter(|&v| v <= u32::MAX as u64)
+            .and_then(|v| unsynch::encode_u32(v as u32))
+            .ok_or(Error("tag is too large"))?;
+
+        let mut flags = 0;
+        flags.set_bit(7, options.unsynch);
+        flags.set_bit(5, self.header.experimental);
+
+        let mut r = Vec::with_capacity(HEADER_LEN + frames.len() + options.padding_len as usize);
+        r.extend_from_slice(b"ID3");
+        r.push(version.minor);
+        r.push(version.rev);
+        r.push(flags);
+        r.extend_from_slice(&len);
+        r.extend_from_slice(&frames);
+        r.resize(r.len() + options.padding_len as usize, 0);
+
+        Ok(r)
+    }
+
+    fn read_body<T: Read>(rd: &mut Limited<T>, bytes: &[u8; HEADER_LEN], len: u32)
+        -> io::Result<Self>
+    {
+        let (header, ext_len, frames_prefix) = Header::read(rd, bytes)?;
+        let frames_prefix = frames_prefix.as_ref().map(|v| &v[..]).unwrap_or(&[]);
+
+        let frames_len = len.checked_sub(ext_len)
+            .ok_or_else(|| Error("extended header size is too big").into_invalid_data_err())?;
+        if rd.max_available() + (frames_prefix.len() as u64) < frames_len as u64 {
             return Err(unexpected_eof_err("tag truncated"));
         }
+        let rd = &mut frames_prefix.chain(rd);
 
-        let frames = Frames::read(rd, header.version, frames_len)?;
+        let crc = header.extended_header.as_ref().and_then(|e| e.crc);
+        let frames = if let Some(crc) = crc {
+            let buf = read_vec_limited(&mut Limited::new(rd, frames_len as u64), frames_len as usize,
+                "tag truncated")?;
 
-        let tag = Self {
-            header,
-            frames,
+            // v2.3 CRC excludes the padding.
+            let padding_len = header.extended_header.as_ref().unwrap().padding_len.unwrap_or(0);
+            let crc_data = buf.len().checked_sub(padding_len as usize)
+                .map(|len| &buf[..len])
+                .ok_or_else(|| Error("padding size is too big").into_invalid_data_err())?;
+            if crc32fast::hash(crc_data) != crc {
+                return Err(Error("tag CRC mismatch").into_invalid_data_err());
+            }
+
+            Frames::read(&mut &buf[..], header.version, frames_len, header.unsynch)?
+        } else {
+            Frames::read(rd, header.version, frames_len, header.unsynch)?
         };
 
-        Ok((tag, tag_len))
+        Ok(Self {
+            header,
+            frames,
+        })
     }
 
     fn fid(&self, post_v2_3: FrameId, pre_v2_3: FrameId) -> FrameId {
@@ -196,4 +669,578 @@ impl Id3v2 {
             pre_v2_3
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use crate::id3::frame::{Encoding, Frame, FrameFlags, Language};
+    use crate::id3::frame::body::*;
+
+    fn read(bytes: &[u8]) -> (Id3v2, u32) {
+        Id3v2::read(&mut Cursor::new(bytes), None).unwrap()
+    }
+
+    fn compress(buf: &[u8]) -> Vec<u8> {
+        let mut w = ZlibEncoder::new(Vec::new(), Compression::default());
+        w.write_all(buf).unwrap();
+        w.finish().unwrap()
+    }
+
+    fn tag_bytes(minor: u8, frames: &[u8]) -> Vec<u8> {
+        let len = frames.len() as u32;
+        let mut r = vec![b'I', b'D', b'3', minor, 0, 0,
+            (len >> 21) as u8 & 0x7f, (len >> 14) as u8 & 0x7f, (len >> 7) as u8 & 0x7f, len as u8 & 0x7f];
+        r.extend_from_slice(frames);
+        r
+    }
+
+    #[test]
+    fn unsynch_v2_3() {
+        let (tag, len) = read(&[
+            b'I', b'D', b'3', 3, 0, 0b1000_0000, 0, 0, 0, 16,
+            b'T', b'I', b'T', b'2', 0, 0, 0, 3, 0, 0,
+            0, 0xff, 0x00, 0xe0,
+            0, 0,
+        ]);
+        assert_eq!(len, 26);
+        assert!(tag.header().unsynch);
+        assert_eq!(tag.title(), Some("\u{ff}\u{e0}"));
+    }
+
+    #[test]
+    fn unsynch_v2_4() {
+        let (tag, len) = read(&[
+            b'I', b'D', b'3', 4, 0, 0, 0, 0, 0, 36,
+            b'T', b'I', b'T', b'2', 0, 0, 0, 8, 0, 0b0000_0011,
+            0, 0, 0, 3,
+            0, 0xff, 0x00, 0xe0,
+            b'T', b'A', b'L', b'B', 0, 0, 0, 4, 0, 0,
+            0, b'a', b'b', b'c',
+        ]);
+        assert_eq!(len, 46);
+        assert!(!tag.header().unsynch);
+        assert_eq!(tag.title(), Some("\u{ff}\u{e0}"));
+        assert_eq!(tag.album(), Some("abc"));
+
+        let (tag, _) = read(&[
+            b'I', b'D', b'3', 4, 0, 0b1000_0000, 0, 0, 0, 15,
+            b'T', b'I', b'T', b'2', 0, 0, 0, 5, 0, 0,
+            0, b'a', 0xff, 0x00, 0xe0,
+        ]);
+        assert!(tag.header().unsynch);
+        assert_eq!(tag.title(), Some("a\u{ff}\u{e0}"));
+    }
+
+    #[test]
+    fn compressed_v2_3() {
+        let body = compress(b"\x00engdesc\x00text");
+        let mut frames = vec![b'C', b'O', b'M', b'M', 0, 0, 0, body.len() as u8 + 4, 0, 0b1000_0000,
+            0, 0, 0, 13];
+        frames.extend_from_slice(&body);
+
+        let (tag, _) = read(&tag_bytes(3, &frames));
+        let comment = tag.frames().first(FrameId::COMMENT).unwrap().body.as_comment().unwrap();
+        assert_eq!(comment.lang, Language::new(*b"eng"));
+        assert_eq!(comment.description, "desc");
+        assert_eq!(comment.text, "text");
+
+        frames[13] = 12;
+        assert_eq!(Id3v2::read(&mut Cursor::new(tag_bytes(3, &frames)), None).unwrap_err().kind(),
+            io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn compressed_v2_4() {
+        let body = compress(b"\x03foo\x00bar");
+        let mut frames = vec![b'T', b'X', b'X', b'X', 0, 0, 0, body.len() as u8 + 4, 0, 0b0000_1001,
+            0, 0, 0, 8];
+        frames.extend_from_slice(&body);
+
+        let (tag, _) = read(&tag_bytes(4, &frames));
+        let text = tag.frames().first(FrameId::USER_TEXT).unwrap().body.as_user_text().unwrap();
+        assert_eq!(text.description, "foo");
+        assert_eq!(text.values, &["bar"]);
+    }
+
+    #[test]
+    fn grouped_and_encrypted_v2_4() {
+        let (tag, _) = read(&tag_bytes(4, &[
+            b'G', b'R', b'I', b'D', 0, 0, 0, 5, 0, 0,
+            b'a', b'b', 0, 0x80, 1,
+            b'E', b'N', b'C', b'R', 0, 0, 0, 5, 0, 0,
+            b'c', b'd', 0, 0x81, 2,
+            b'T', b'I', b'T', b'2', 0, 0, 0, 3, 0, 0b0100_0000,
+            0x80, 0, b't',
+            b'C', b'O', b'M', b'M', 0, 0, 0, 5, 0, 0b0100_0100,
+            0x80, 0x81, 9, 9, 9,
+        ]));
+
+        let grid = tag.frames().first(FrameId::GROUP_ID).unwrap().body.as_group_id_reg().unwrap();
+        assert_eq!(grid.owner_id, "ab");
+        assert_eq!(grid.group_id, 0x80);
+        assert_eq!(grid.data, &[1]);
+
+        let encr = tag.frames().first(FrameId::ENCRYPTION).unwrap()
+            .body.as_encryption_method_reg().unwrap();
+        assert_eq!(encr.owner_id, "cd");
+        assert_eq!(encr.method, 0x81);
+        assert_eq!(encr.data, &[2]);
+
+        let title = tag.frames().first(FrameId::TITLE).unwrap();
+        assert_eq!(title.group_id, Some(0x80));
+        assert_eq!(tag.title(), Some("t"));
+
+        let comment = tag.frames().first(FrameId::COMMENT).unwrap();
+        assert_eq!(comment.group_id, Some(0x80));
+        assert_eq!(comment.body.as_encrypted().unwrap(), &Encrypted {
+            method: 0x81,
+            compressed: false,
+            data_len: None,
+            data: vec![9, 9, 9],
+        });
+    }
+
+    #[test]
+    fn grouped_and_encrypted_v2_3() {
+        let (tag, _) = read(&tag_bytes(3, &[
+            b'T', b'A', b'L', b'B', 0, 0, 0, 3, 0, 0b0110_0000,
+            0x81, 0x80, 7,
+            b'T', b'I', b'T', b'2', 0, 0, 0, 2, 0, 0,
+            0, b't',
+        ]));
+
+        let album = tag.frames().first(FrameId::ALBUM).unwrap();
+        assert_eq!(album.group_id, Some(0x80));
+        assert_eq!(album.body.as_encrypted().unwrap().method, 0x81);
+        assert_eq!(album.body.as_encrypted().unwrap().data, &[7]);
+        assert_eq!(tag.album(), None);
+        assert_eq!(tag.title(), Some("t"));
+    }
+
+    #[test]
+    fn frame_flags() {
+        let (tag, _) = read(&tag_bytes(3, &[
+            b'T', b'I', b'T', b'2', 0, 0, 0, 2, 0b1010_0000, 0,
+            0, b't',
+        ]));
+        assert_eq!(tag.frames().first(FrameId::TITLE).unwrap().flags, FrameFlags {
+            tag_alter_preserve: true,
+            file_alter_preserve: false,
+            read_only: true,
+        });
+
+        let (tag, _) = read(&tag_bytes(4, &[
+            b'T', b'I', b'T', b'2', 0, 0, 0, 2, 0b0110_0000, 0,
+            0, b't',
+        ]));
+        assert_eq!(tag.frames().first(FrameId::TITLE).unwrap().flags, FrameFlags {
+            tag_alter_preserve: true,
+            file_alter_preserve: true,
+            read_only: false,
+        });
+    }
+
+    #[test]
+    fn extended_header_v2_3() {
+        let frames = [
+            b'T', b'I', b'T', b'2', 0, 0, 0, 2, 0, 0,
+            0, b't',
+        ];
+        let crc = crc32fast::hash(&frames);
+        let mut bytes = vec![b'I', b'D', b'3', 3, 0, 0b0100_0000, 0, 0, 0, 28,
+            0, 0, 0, 10, 0x80, 0, 0, 0, 0, 2];
+        bytes.extend_from_slice(&crc.to_be_bytes());
+        bytes.extend_from_slice(&frames);
+        bytes.extend_from_slice(&[0, 0]);
+
+        let (tag, len) = read(&bytes);
+        assert_eq!(len, 38);
+        assert_eq!(tag.header().extended_header, Some(ExtendedHeader {
+            padding_len: Some(2),
+            update: false,
+            crc: Some(crc),
+            restrictions: None,
+        }));
+        assert_eq!(tag.title(), Some("t"));
+
+        bytes[20] ^= 1;
+        assert_eq!(Id3v2::read(&mut Cursor::new(bytes), None).unwrap_err().kind(),
+            io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn extended_header_v2_4() {
+        let frames = [
+            b'T', b'I', b'T', b'2', 0, 0, 0, 2, 0, 0,
+            0, b't',
+            0, 0, 0,
+        ];
+        let crc = crc32fast::hash(&frames);
+        let mut bytes = vec![b'I', b'D', b'3', 4, 0, 0b0100_0000, 0, 0, 0, 30,
+            0, 0, 0, 15, 1, 0b0111_0000,
+            0,
+            5, (crc >> 28) as u8, (crc >> 21) as u8 & 0x7f, (crc >> 14) as u8 & 0x7f,
+                (crc >> 7) as u8 & 0x7f, crc as u8 & 0x7f,
+            1, 0b0110_0110];
+        bytes.extend_from_slice(&frames);
+
+        let (tag, len) = read(&bytes);
+        assert_eq!(len, 40);
+        assert_eq!(tag.header().extended_header, Some(ExtendedHeader {
+            padding_len: None,
+            update: true,
+            crc: Some(crc),
+            restrictions: Some(Restrictions {
+                tag_size: TagSizeRestriction::Frames64Len128K,
+                text_encoding: TextEncodingRestriction::Latin1OrUtf8,
+                text_len: TextLenRestriction::None,
+                image_format: ImageFormatRestriction::PngOrJpeg,
+                image_size: ImageSizeRestriction::Max64,
+            }),
+        }));
+        assert_eq!(tag.title(), Some("t"));
+    }
+
+    #[test]
+    fn extended_header_missing() {
+        let (tag, _) = read(&[
+            b'I', b'D', b'3', 3, 0, 0b0100_0000, 0, 0, 0, 12,
+            b'T', b'I', b'T', b'2', 0, 0, 0, 2, 0, 0,
+            0, b't',
+        ]);
+        assert!(tag.header().extended);
+        assert_eq!(tag.header().extended_header, None);
+        assert_eq!(tag.title(), Some("t"));
+    }
+
+    fn appended_tag_bytes(frames: &[u8]) -> Vec<u8> {
+        assert!(frames.len() < 0x80);
+        let mut r = vec![b'I', b'D', b'3', 4, 0, 0b0001_0000, 0, 0, 0, frames.len() as u8];
+        r.extend_from_slice(frames);
+        r.extend_from_slice(&[b'3', b'D', b'I', 4, 0, 0b0001_0000, 0, 0, 0, frames.len() as u8]);
+        r
+    }
+
+    #[test]
+    fn appended() {
+        let mut bytes = vec![0xff; 100];
+        bytes.extend_from_slice(&appended_tag_bytes(&[
+            b'T', b'I', b'T', b'2', 0, 0, 0, 2, 0, 0,
+            0, b't',
+        ]));
+        let end = bytes.len() as u64;
+        bytes.extend_from_slice(b"TAG");
+
+        let (tag, start, merged) = Id3v2::read_chained(&mut Cursor::new(&bytes), None, end)
+            .unwrap();
+        assert_eq!(start, 100);
+        assert_eq!(merged, vec![100..end]);
+        assert_eq!(tag.unwrap().title(), Some("t"));
+
+        let (tag, start, _) = Id3v2::read_chained(&mut Cursor::new(&bytes), None, end - 1).unwrap();
+        assert_eq!(start, end - 1);
+        assert!(tag.is_none());
+    }
+
+    #[test]
+    fn seek_chain() {
+        let mut bytes = tag_bytes(4, &[
+            b'T', b'I', b'T', b'2', 0, 0, 0, 2, 0, 0,
+            0, b'a',
+            b'T', b'A', b'L', b'B', 0, 0, 0, 2, 0, 0,
+            0, b'b',
+            b'S', b'E', b'E', b'K', 0, 0, 0, 4, 0, 0,
+            0, 0, 0, 10,
+        ]);
+        let first_end = bytes.len() as u64;
+        let (first, _) = read(&bytes);
+        bytes.extend_from_slice(&[0xff; 15]);
+
+        // Update tag.
+        bytes.extend_from_slice(&[b'I', b'D', b'3', 4, 0, 0b0100_0000, 0, 0, 0, 19,
+            0, 0, 0, 7, 1, 0b0100_0000, 0,
+            b'T', b'I', b'T', b'2', 0, 0, 0, 2, 0, 0,
+            0, b'c',
+        ]);
+        let end = bytes.len() as u64;
+
+        let (tag, start, merged) = Id3v2::read_chained(&mut Cursor::new(&bytes),
+            Some((first, first_end)), end).unwrap();
+        assert_eq!(start, end);
+        assert_eq!(merged, vec![first_end + 15..end]);
+        let tag = tag.unwrap();
+        assert_eq!(tag.title(), Some("c"));
+        assert_eq!(tag.album(), Some("b"));
+    }
+
+    fn text_frame(id: &[u8], text: &[u8], len: [u8; 4]) -> Vec<u8> {
+        let mut r = id.to_vec();
+        r.extend_from_slice(&len);
+        r.extend_from_slice(&[0, 0, 0]);
+        r.extend_from_slice(text);
+        r
+    }
+
+    #[test]
+    fn plain_frame_len_v2_3() {
+        let title = [b'a'; 200];
+        let (tag, _) = read(&tag_bytes(3, &text_frame(b"TIT2", &title, [0, 0, 0, 201])));
+        assert_eq!(tag.title(), Some(&*String::from_utf8(title.to_vec()).unwrap()));
+    }
+
+    #[test]
+    fn frame_len_v2_4() {
+        let title = [b'a'; 255];
+        let title_str = String::from_utf8(title.to_vec()).unwrap();
+
+        // Synchsafe.
+        let mut frames = text_frame(b"TIT2", &title, [0, 0, 2, 0]);
+        frames.extend_from_slice(&text_frame(b"TALB", b"b", [0, 0, 0, 2]));
+        frames.extend_from_slice(&[0; 10]);
+        let (tag, _) = read(&tag_bytes(4, &frames));
+        assert_eq!(tag.title(), Some(&*title_str));
+        assert_eq!(tag.album(), Some("b"));
+
+        // Plain as written by iTunes, synchsafe interpretation gives wrong frame boundaries.
+        let mut frames = text_frame(b"TIT2", &title, [0, 0, 1, 0]);
+        frames.extend_from_slice(&text_frame(b"TALB", b"b", [0, 0, 0, 2]));
+        let (tag, _) = read(&tag_bytes(4, &frames));
+        assert_eq!(tag.title(), Some(&*title_str));
+        assert_eq!(tag.album(), Some("b"));
+
+        // Plain, not a valid synchsafe integer.
+        let title = [b'a'; 199];
+        let frames = text_frame(b"TIT2", &title, [0, 0, 0, 200]);
+        let (tag, _) = read(&tag_bytes(4, &frames));
+        assert_eq!(tag.title(), Some(&*String::from_utf8(title.to_vec()).unwrap()));
+    }
+
+    fn frame(id: FrameId, body: Body) -> Frame {
+        Frame {
+            id,
+            flags: FrameFlags::default(),
+            group_id: None,
+            body,
+        }
+    }
+
+    fn text(id: FrameId, encoding: Encoding, strings: &[&str]) -> Frame {
+        frame(id, Body::Text(Text {
+            encoding,
+            strings: strings.iter().map(|&s| s.into()).collect(),
+        }))
+    }
+
+    fn new_tag(version: Version, frames: Vec<Frame>) -> Id3v2 {
+        let mut r = Id3v2::new(version);
+        for f in frames {
+            r.frames.insert(f);
+        }
+        r
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let tag = new_tag(Version::V2_4, vec![
+            text(FrameId::TITLE, Encoding::Latin1, &["\u{ff}\u{e0}"]),
+            text(FrameId::ARTIST, Encoding::Utf16, &["a", "\u{263a}", "\u{ff}"]),
+            frame(FrameId::USER_TEXT, Body::UserText(UserText {
+                encoding: Encoding::Utf16,
+                description: "desc".into(),
+                values: vec!["\u{263a}".into(), "v".into()],
+            })),
+            frame(FrameId::COMMENT, Body::Comment(Comment {
+                encoding: Encoding::Latin1,
+                lang: Language::new(*b"eng"),
+                description: "".into(),
+                text: "comment".into(),
+            })),
+            frame(FrameId::PICTURE, Body::Picture(Picture {
+                encoding: Encoding::Utf16,
+                content_type: "image/png".into(),
+                picture_kind: PictureKind::COVER_FRONT,
+                description: "\u{263a}".into(),
+                data: vec![0xff, 0xd8, 0xff, 0x00, 0xff],
+            })),
+            frame(FrameId::ARTIST_URL, Body::Url("http://a".into())),
+            frame(FrameId::USER_URL, Body::UserUrl(UserUrl {
+                encoding: Encoding::Utf16,
+                description: "\u{263a}".into(),
+                url: "http://b".into(),
+            })),
+            frame(FrameId::PRIVATE, Body::Bytes(vec![b'o', 0, 0xff, 0xff])),
+            frame(FrameId::ENCRYPTION, Body::EncryptionMethodReg(EncryptionMethodReg {
+                owner_id: "enc".into(),
+                method: 0x80,
+                data: vec![1, 2],
+            })),
+            frame(FrameId::GROUP_ID, Body::GroupIdReg(GroupIdReg {
+                owner_id: "grp".into(),
+                group_id: 0x81,
+                data: vec![],
+            })),
+            Frame {
+                id: FrameId::ALBUM,
+                flags: FrameFlags {
+                    tag_alter_preserve: true,
+                    file_alter_preserve: false,
+                    read_only: true,
+                },
+                group_id: Some(0x81),
+                body: Body::Encrypted(Encrypted {
+                    method: 0x80,
+                    compressed: true,
+                    data_len: Some(10),
+                    data: vec![0xff, 0xe0, 0xff],
+                }),
+            },
+        ]);
+
+        for &version in &[Version::V2_3, Version::V2_4] {
+            for &unsynch in &[false, true] {
+                let bytes = tag.encode(&EncodeOptions {
+                    version: Some(version),
+                    padding_len: 16,
+                    unsynch,
+                }).unwrap();
+                let (actual, len) = read(&bytes);
+                assert_eq!(len as usize, bytes.len());
+                assert_eq!(actual.header().version, version);
+                assert_eq!(actual.header().unsynch, unsynch);
+                assert_eq!(actual.frames().iter().collect::<Vec<&Frame>>(),
+                    tag.frames().iter().collect::<Vec<&Frame>>());
+                if unsynch {
+                    assert!(!bytes.windows(2).any(|w| w[0] == 0xff && w[1] >= 0xe0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn encode_convert_v2_3_to_v2_4() {
+        let tag = new_tag(Version::V2_3, vec![
+            text(FrameId::TITLE, Encoding::Latin1, &["t"]),
+            text(FrameId::V23_YEAR, Encoding::Latin1, &["2001"]),
+            text(FrameId::V23_DATE, Encoding::Latin1, &["3112"]),
+            text(FrameId::V23_TIME, Encoding::Latin1, &["2359"]),
+            text(FrameId::V23_ORIGINAL_RELEASE_YEAR, Encoding::Latin1, &["1999"]),
+            text(FrameId::INVOLVED_PEOPLE_LIST, Encoding::Utf16, &["producer", "\u{263a}"]),
+            text(FrameId::V23_SIZE, Encoding::Latin1, &["100"]),
+            frame(FrameId::V23_RELATIVE_VOL_ADJUST, Body::Bytes(vec![0, 16])),
+        ]);
+        assert_eq!(tag.release_date().unwrap().to_string(), "2001-12-31T23:59");
+
+        let (actual, _) = read(&tag.encode(&EncodeOptions {
+            version: Some(Version::V2_4),
+            ..Default::default()
+        }).unwrap());
+        assert_eq!(actual.frames().iter().map(|f| f.id).collect::<Vec<_>>(), vec![
+            FrameId::TITLE,
+            FrameId::RELEASE_DATE,
+            FrameId::ORIGINAL_RELEASE_DATE,
+            FrameId::PEOPLE,
+        ]);
+        assert_eq!(actual.release_date(), tag.release_date());
+        assert_eq!(actual.frames().first_text_str(FrameId::ORIGINAL_RELEASE_DATE), Some("1999"));
+        assert_eq!(actual.frames().first_text(FrameId::PEOPLE).unwrap().strings,
+            vec!["producer", "\u{263a}"]);
+
+        let v2_2 = new_tag(Version::V2_2, vec![
+            text(FrameId::V22_YEAR, Encoding::Latin1, &["2001"]),
+            text(FrameId::V22_DATE, Encoding::Latin1, &["0102"]),
+        ]);
+        let (actual, _) = read(&v2_2.encode(&EncodeOptions {
+            version: Some(Version::V2_4),
+            ..Default::default()
+        }).unwrap());
+        assert_eq!(actual.frames().first_text_str(FrameId::RELEASE_DATE), Some("2001-02-01"));
+    }
+
+    #[test]
+    fn encode_convert_v2_4_to_v2_3() {
+        let tag = new_tag(Version::V2_4, vec![
+            text(FrameId::TITLE, Encoding::Latin1, &["t"]),
+            text(FrameId::RELEASE_DATE, Encoding::Latin1, &["2001-12-31T23:59:58"]),
+            text(FrameId::ORIGINAL_RELEASE_DATE, Encoding::Latin1, &["1999-05"]),
+            text(FrameId::PEOPLE, Encoding::Latin1, &["producer", "a"]),
+            text(FrameId::PERFORMERS, Encoding::Utf8, &["guitar", "\u{263a}"]),
+            text(FrameId::ARTIST_SORT_ORDER, Encoding::Latin1, &["a"]),
+            frame(FrameId::RELATIVE_VOL_ADJUST, Body::Bytes(vec![0, 1, 0, 0])),
+            frame(FrameId::SEEK, Body::Bytes(vec![0, 0, 0, 1])),
+        ]);
+
+        let (actual, _) = read(&tag.encode(&EncodeOptions {
+            version: Some(Version::V2_3),
+            ..Default::default()
+        }).unwrap());
+        assert_eq!(actual.frames().iter().map(|f| f.id).collect::<Vec<_>>(), vec![
+            FrameId::TITLE,
+            FrameId::V23_YEAR,
+            FrameId::V23_DATE,
+            FrameId::V23_TIME,
+            FrameId::V23_ORIGINAL_RELEASE_YEAR,
+            FrameId::INVOLVED_PEOPLE_LIST,
+        ]);
+        assert_eq!(actual.frames().first_text_str(FrameId::V23_DATE), Some("3112"));
+        assert_eq!(actual.release_date().unwrap().to_string(), "2001-12-31T23:59");
+        assert_eq!(actual.frames().first_text_str(FrameId::V23_ORIGINAL_RELEASE_YEAR), Some("1999"));
+        let people = actual.frames().first_text(FrameId::INVOLVED_PEOPLE_LIST).unwrap();
+        assert_eq!(people.encoding, Encoding::Utf16);
+        assert_eq!(people.strings, vec!["producer", "a", "guitar", "\u{263a}"]);
+
+        // Existing v2.3 frames take precedence.
+        let tag = new_tag(Version::V2_4, vec![
+            text(FrameId::RELEASE_DATE, Encoding::Latin1, &["2001"]),
+            text(FrameId::V23_YEAR, Encoding::Latin1, &["2002"]),
+        ]);
+        let (actual, _) = read(&tag.encode(&EncodeOptions {
+            version: Some(Version::V2_3),
+            ..Default::default()
+        }).unwrap());
+        assert_eq!(actual.frames().iter().map(|f| f.id).collect::<Vec<_>>(),
+            vec![FrameId::V23_YEAR]);
+        assert_eq!(actual.frames().first_text_str(FrameId::V23_YEAR), Some("2002"));
+    }
+
+    #[test]
+    fn encode_encoding() {
+        let tag = new_tag(Version::V2_2, vec![
+            text(FrameId::V22_TITLE, Encoding::Latin1, &["\u{263a}"]),
+            text(FrameId::V22_ALBUM, Encoding::Latin1, &["a"]),
+            text(FrameId::V22_ARTIST, Encoding::Utf16, &["b"]),
+            frame(FrameId::new_v22(*b"CRM"), Body::Bytes(vec![1])),
+        ]);
+
+        let encoding = |tag: &Id3v2, id| tag.frames().first_text(id).unwrap().encoding;
+
+        let (v2_3, _) = read(&tag.encode(&EncodeOptions {
+            version: Some(Version::V2_3),
+            ..Default::default()
+        }).unwrap());
+        assert_eq!(v2_3.title(), Some("\u{263a}"));
+        assert_eq!(encoding(&v2_3, FrameId::TITLE), Encoding::Utf16);
+        assert_eq!(encoding(&v2_3, FrameId::ALBUM), Encoding::Latin1);
+
+        let (v2_4, _) = read(&tag.encode(&EncodeOptions {
+            version: Some(Version::V2_4),
+            ..Default::default()
+        }).unwrap());
+        assert_eq!(encoding(&v2_4, FrameId::TITLE), Encoding::Utf8);
+        assert_eq!(encoding(&v2_4, FrameId::ARTIST), Encoding::Utf16);
+
+        let utf8 = new_tag(Version::V2_4, vec![text(FrameId::TITLE, Encoding::Utf8, &["a"])]);
+        let (v2_3, _) = read(&utf8.encode(&EncodeOptions {
+            version: Some(Version::V2_3),
+            ..Default::default()
+        }).unwrap());
+        assert_eq!(encoding(&v2_3, FrameId::TITLE), Encoding::Utf16);
+
+        assert!(utf8.encode(&EncodeOptions {
+            version: Some(Version::V2_2),
+            ..Default::default()
+        }).is_err());
+    }
+}
@@ -6,13 +6,22 @@
 #[macro_use]
 mod macros;
 
+pub mod aiff;
+pub mod ape;
 pub mod error;
 pub mod flac;
+pub mod fourcc;
 pub mod id3;
+pub mod lyrics3;
+pub mod mac;
 pub mod meta;
+pub mod mp4;
 pub mod mpeg;
+pub mod ogg;
 pub mod tags;
 pub mod timestamp;
 mod util;
-mod vcomment;
+pub mod vcomment;
+pub mod wav;
+pub mod wavpack;
 
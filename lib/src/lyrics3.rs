@@ -0,0 +1,209 @@
+use encoding::{Encoding, DecoderTrap};
+use encoding::all::ISO_8859_1;
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use crate::error::*;
+use crate::util::*;
+
+const BEGIN: &[u8] = b"LYRICSBEGIN";
+const V1_END: &[u8] = b"LYRICSEND";
+const V2_END: &[u8] = b"LYRICS200";
+const V1_MAX_LEN: usize = 5100;
+/// Len of the v2 tag size field.
+const V2_SIZE_LEN: usize = 6;
+/// Max len that fits into the v2 tag size field.
+const V2_MAX_LEN: u64 = 999_999;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Version {
+    V1,
+    V2,
+}
+
+/// The `IND` field.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Indications {
+    pub lyrics: bool,
+    pub timestamps: bool,
+    pub inhibit_random: bool,
+}
+
+/// Lyrics3 tag that precedes ID3v1 at the end of MP3 files. In v1 only the lyrics are set.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Lyrics3 {
+    pub version: Version,
+    /// `LYR`
+    pub lyrics: Option<String>,
+    /// `INF`
+    pub info: Option<String>,
+    /// `AUT`
+    pub author: Option<String>,
+    /// `EAL`
+    pub album: Option<String>,
+    /// `EAR`
+    pub artist: Option<String>,
+    /// `ETT`
+    pub title: Option<String>,
+    /// `IND`
+    pub indications: Option<Indications>,
+    /// Fields with other IDs in the order of appearance.
+    pub other: Vec<(String, String)>,
+}
+
+impl Lyrics3 {
+    fn new(version: Version) -> Self {
+        Self {
+            version,
+            lyrics: None,
+            info: None,
+            author: None,
+            album: None,
+            artist: None,
+            title: None,
+            indications: None,
+            other: Vec::new(),
+        }
+    }
+
+    /// Reads the tag that ends at `end`. Returns the tag and its start position.
+    pub(crate) fn read_at_end(rd: &mut (impl Read + Seek), end: u64) -> io::Result<(Self, u64)> {
+        let max_len = cmp::min(end, (BEGIN.len() + V1_MAX_LEN + V1_END.len()) as u64);
+        let trailer_len = V2_SIZE_LEN + V2_END.len();
+        if max_len < (BEGIN.len() + V1_END.len()) as u64 {
+            return Err(Error("couldn't find Lyrics3 tag").into_invalid_data_err());
+        }
+        rd.seek(SeekFrom::Start(end - max_len))?;
+        let buf = read_vec_limited(&mut Limited::new(&mut *rd, max_len), max_len as usize,
+            "Lyrics3 tag is truncated")?;
+
+        if buf.ends_with(V2_END) {
+            let size = &buf[buf.len() - trailer_len..buf.len() - V2_END.len()];
+            let len = decode_num(size)
+                .ok_or_else(|| Error("bad Lyrics3v2 tag size").into_invalid_data_err())?;
+            let start = end.checked_sub(len + trailer_len as u64)
+                .ok_or_else(|| Error("bad Lyrics3v2 tag size").into_invalid_data_err())?;
+            rd.seek(SeekFrom::Start(start))?;
+            let buf = read_vec_limited(&mut Limited::new(rd, len), len as usize,
+                "Lyrics3v2 tag is truncated")?;
+            let tag = Self::decode_v2(&buf).map_err(|e| e.into_invalid_data_err())?;
+            Ok((tag, start))
+        } else if buf.ends_with(V1_END) {
+            let body = &buf[..buf.len() - V1_END.len()];
+            let i = body.windows(BEGIN.len()).rposition(|v| v == BEGIN)
+                .ok_or_else(|| Error("couldn't find Lyrics3v1 tag start").into_invalid_data_err())?;
+            let mut tag = Self::new(Version::V1);
+            tag.lyrics = Some(decode_str(&body[i + BEGIN.len()..]));
+            Ok((tag, end - max_len + i as u64))
+        } else {
+            Err(Error("couldn't find Lyrics3 tag").into_invalid_data_err())
+        }
+    }
+
+    fn decode_v2(buf: &[u8]) -> Result<Self> {
+        if !buf.starts_with(BEGIN) {
+            return Err(Error("couldn't find Lyrics3v2 tag start"));
+        }
+        let mut buf = &buf[BEGIN.len()..];
+        let mut r = Self::new(Version::V2);
+        while !buf.is_empty() {
+            if buf.len() < 8 {
+                return Err(Error("Lyrics3v2 field is truncated"));
+            }
+            let id = &buf[..3];
+            let len = decode_num(&buf[3..8]).ok_or(Error("bad Lyrics3v2 field size"))? as usize;
+            let data = buf.get(8..8 + len).ok_or(Error("Lyrics3v2 field is truncated"))?;
+            buf = &buf[8 + len..];
+
+            let value = decode_str(data);
+            match id {
+                b"LYR" => r.lyrics = Some(value),
+                b"INF" => r.info = Some(value),
+                b"AUT" => r.author = Some(value),
+                b"EAL" => r.album = Some(value),
+                b"EAR" => r.artist = Some(value),
+                b"ETT" => r.title = Some(value),
+                b"IND" => {
+                    let flag = |i| data.get(i) == Some(&b'1');
+                    r.indications = Some(Indications {
+                        lyrics: flag(0),
+                        timestamps: flag(1),
+                        inhibit_random: flag(2),
+                    });
+                }
+                _ => r.other.push((decode_str(id), value)),
+            }
+        }
+        Ok(r)
+    }
+}
+
+fn decode_num(buf: &[u8]) -> Option<u64> {
+    if buf.iter().all(|c| c.is_ascii_digit()) {
+        std::str::from_utf8(buf).ok()?.parse().ok().filter(|&v| v <= V2_MAX_LEN)
+    } else {
+        None
+    }
+}
+
+fn decode_str(buf: &[u8]) -> String {
+    ISO_8859_1.decode(buf, DecoderTrap::Replace).unwrap()
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    pub(crate) fn field(id: &str, data: &str) -> String {
+        format!("{}{:05}{}", id, data.len(), data)
+    }
+
+    pub(crate) fn tag_v2(fields: &[String]) -> Vec<u8> {
+        let body = format!("LYRICSBEGIN{}", fields.concat());
+        format!("{}{:06}LYRICS200", body, body.len()).into_bytes()
+    }
+
+    #[test]
+    fn read() {
+        let mut bytes = b"audio".to_vec();
+        bytes.extend_from_slice(&tag_v2(&[
+            field("IND", "11"),
+            field("LYR", "[00:01]la la"),
+            field("INF", "info"),
+            field("AUT", "author"),
+            field("EAL", "album"),
+            field("EAR", "artist"),
+            field("ETT", "title"),
+            field("IMG", "x.jpg||"),
+        ]));
+        let (tag, start) = Lyrics3::read_at_end(&mut Cursor::new(&bytes), bytes.len() as u64).unwrap();
+        assert_eq!(start, 5);
+        assert_eq!(tag, Lyrics3 {
+            version: Version::V2,
+            lyrics: Some("[00:01]la la".into()),
+            info: Some("info".into()),
+            author: Some("author".into()),
+            album: Some("album".into()),
+            artist: Some("artist".into()),
+            title: Some("title".into()),
+            indications: Some(Indications {
+                lyrics: true,
+                timestamps: true,
+                inhibit_random: false,
+            }),
+            other: vec![("IMG".into(), "x.jpg||".into())],
+        });
+
+        let bytes = b"audioLYRICSBEGINsome\r\nlyricsLYRICSEND";
+        let (tag, start) = Lyrics3::read_at_end(&mut Cursor::new(&bytes), bytes.len() as u64).unwrap();
+        assert_eq!(start, 5);
+        assert_eq!(tag.version, Version::V1);
+        assert_eq!(tag.lyrics.as_deref(), Some("some\r\nlyrics"));
+
+        assert_eq!(Lyrics3::read_at_end(&mut Cursor::new(&b"audio"), 5).unwrap_err().kind(),
+            io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,275 @@
+use bit_field::BitField;
+use byteorder::{ByteOrder, LE};
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use crate::ape::Ape;
+use crate::error::*;
+use crate::id3::unsynch;
+use crate::id3::v1::Id3v1;
+use crate::tags::TagsRef;
+use crate::util::*;
+
+const MAGIC: &[u8] = b"MAC ";
+const OLD_HEADER_LEN: usize = 32;
+const DESCRIPTOR_LEN: usize = 52;
+const HEADER_LEN: usize = 24;
+
+/// The first version with the descriptor followed by the header.
+const DESCRIPTOR_VERSION: u16 = 3980;
+
+// Format flags of the old header.
+const FLAG_8_BIT: usize = 0;
+const FLAG_24_BIT: usize = 3;
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct CompressionLevel(pub u16);
+
+impl CompressionLevel {
+    pub const FAST: Self = Self(1000);
+    pub const NORMAL: Self = Self(2000);
+    pub const HIGH: Self = Self(3000);
+    pub const EXTRA_HIGH: Self = Self(4000);
+    pub const INSANE: Self = Self(5000);
+}
+
+impl fmt::Debug for CompressionLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Self::FAST => "FAST",
+            Self::NORMAL => "NORMAL",
+            Self::HIGH => "HIGH",
+            Self::EXTRA_HIGH => "EXTRA_HIGH",
+            Self::INSANE => "INSANE",
+            _ => return write!(f, "CompressionLevel({})", self.0),
+        })
+    }
+}
+
+/// Stream header, the old (before 3.98) and new layouts are decoded into the same struct.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Header {
+    pub version: u16,
+    pub compression_level: CompressionLevel,
+    pub format_flags: u16,
+    pub blocks_per_frame: u32,
+    pub final_frame_blocks: u32,
+    pub frame_count: u32,
+    pub bits_per_sample: u16,
+    pub channel_count: u16,
+    pub samples_per_sec: u32,
+}
+
+impl Header {
+    fn read(rd: &mut impl Read) -> io::Result<Self> {
+        let mut buf = [0; DESCRIPTOR_LEN];
+        rd.read_exact(&mut buf[..OLD_HEADER_LEN])?;
+        if !buf.starts_with(MAGIC) {
+            return Err(Error("bad Monkey's Audio magic").into_invalid_data_err());
+        }
+        let version = LE::read_u16(&buf[4..6]);
+        let r = if version >= DESCRIPTOR_VERSION {
+            rd.read_exact(&mut buf[OLD_HEADER_LEN..])?;
+            let descriptor_len = LE::read_u32(&buf[8..12]) as u64;
+            let skip = descriptor_len.checked_sub(DESCRIPTOR_LEN as u64)
+                .ok_or_else(|| Error("bad Monkey's Audio descriptor len").into_invalid_data_err())?;
+            io::copy(&mut rd.take(skip), &mut io::sink())?;
+
+            let mut buf = [0; HEADER_LEN];
+            rd.read_exact(&mut buf)?;
+            Self {
+                version,
+                compression_level: CompressionLevel(LE::read_u16(&buf[0..2])),
+                format_flags: LE::read_u16(&buf[2..4]),
+                blocks_per_frame: LE::read_u32(&buf[4..8]),
+                final_frame_blocks: LE::read_u32(&buf[8..12]),
+                frame_count: LE::read_u32(&buf[12..16]),
+                bits_per_sample: LE::read_u16(&buf[16..18]),
+                channel_count: LE::read_u16(&buf[18..20]),
+                samples_per_sec: LE::read_u32(&buf[20..24]),
+            }
+        } else {
+            let compression_level = CompressionLevel(LE::read_u16(&buf[6..8]));
+            let format_flags = LE::read_u16(&buf[8..10]);
+            let blocks_per_frame = if version >= 3950 {
+                73728 * 4
+            } else if version >= 3900
+                || (version >= 3800 && compression_level == CompressionLevel::EXTRA_HIGH)
+            {
+                73728
+            } else {
+                9216
+            };
+            let bits_per_sample = if format_flags.get_bit(FLAG_8_BIT) {
+                8
+            } else if format_flags.get_bit(FLAG_24_BIT) {
+                24
+            } else {
+                16
+            };
+            Self {
+                version,
+                compression_level,
+                format_flags,
+                blocks_per_frame,
+                final_frame_blocks: LE::read_u32(&buf[28..32]),
+                frame_count: LE::read_u32(&buf[24..28]),
+                bits_per_sample,
+                channel_count: LE::read_u16(&buf[10..12]),
+                samples_per_sec: LE::read_u32(&buf[12..16]),
+            }
+        };
+        if r.channel_count == 0 || r.samples_per_sec == 0 {
+            return Err(Error("bad Monkey's Audio header").into_invalid_data_err());
+        }
+        Ok(r)
+    }
+
+    /// Total len in blocks, i.e. samples per channel.
+    pub fn len_blocks(&self) -> u64 {
+        if self.frame_count == 0 {
+            0
+        } else {
+            (self.frame_count as u64 - 1) * self.blocks_per_frame as u64
+                + self.final_frame_blocks as u64
+        }
+    }
+}
+
+/// Monkey's Audio file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mac {
+    header: Header,
+    duration: Duration,
+    bits_per_sec: Option<u32>,
+    ape: Option<Ape>,
+    id3v1: Option<Id3v1>,
+}
+
+impl Mac {
+    pub fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
+        // Some encoders put ID3v2 tag before the stream, it's skipped.
+        rd.seek(SeekFrom::Start(0))?;
+        let mut buf = [0; 10];
+        rd.read_exact(&mut buf)?;
+        let start = if &buf[..3] == b"ID3" {
+            let len = unsynch::decode_u32(&buf[6..10])
+                .ok_or_else(|| Error("bad ID3v2 tag len").into_invalid_data_err())?;
+            10 + len as u64 + if buf[5].get_bit(4) { 10 } else { 0 }
+        } else {
+            0
+        };
+        rd.seek(SeekFrom::Start(start))?;
+        let header = Header::read(&mut rd)?;
+
+        let (ape, id3v1, audio_end) = Ape::read_trailing(&mut rd)?;
+
+        let duration = duration(header.len_blocks(), header.samples_per_sec);
+        let bits_per_sec = bits_per_sec(audio_end.saturating_sub(start), duration);
+
+        Ok(Self {
+            header,
+            duration,
+            bits_per_sec,
+            ape,
+            id3v1,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn bits_per_sec(&self) -> Option<u32> {
+        self.bits_per_sec
+    }
+
+    pub fn ape(&self) -> Option<&Ape> {
+        self.ape.as_ref()
+    }
+
+    pub fn id3v1(&self) -> Option<&Id3v1> {
+        self.id3v1.as_ref()
+    }
+
+    pub fn tags(&self) -> TagsRef<'_> {
+        TagsRef {
+            id3v1: self.id3v1.as_ref(),
+            ape: self.ape.as_ref(),
+            .. Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn read() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&3990u16.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(&(DESCRIPTOR_LEN as u32).to_le_bytes());
+        bytes.resize(DESCRIPTOR_LEN, 0);
+        bytes.extend_from_slice(&2000u16.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&73728u32.to_le_bytes());
+        bytes.extend_from_slice(&14472u32.to_le_bytes());
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.resize(88200, 0);
+        bytes.extend_from_slice(&crate::ape::test::tag(&[
+            crate::ape::test::item("Title", b"title", 0)]));
+
+        let mac = Mac::read(Cursor::new(&bytes)).unwrap();
+        assert_eq!(mac.header(), &Header {
+            version: 3990,
+            compression_level: CompressionLevel::NORMAL,
+            format_flags: 0,
+            blocks_per_frame: 73728,
+            final_frame_blocks: 14472,
+            frame_count: 2,
+            bits_per_sample: 16,
+            channel_count: 2,
+            samples_per_sec: 44100,
+        });
+        assert_eq!(mac.duration(), Duration::from_secs(2));
+        assert_eq!(mac.bits_per_sec(), Some(44100 * 8));
+        assert_eq!(mac.tags().title().unwrap(), "title");
+    }
+
+    #[test]
+    fn read_old() {
+        let mut bytes = b"ID3\x03\0\0\0\0\0\x02\0\0".to_vec();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&3970u16.to_le_bytes());
+        bytes.extend_from_slice(&4000u16.to_le_bytes());
+        bytes.extend_from_slice(&(1u16 << FLAG_24_BIT).to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&48000u32.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&24000u32.to_le_bytes());
+
+        let mac = Mac::read(Cursor::new(&bytes)).unwrap();
+        let h = mac.header();
+        assert_eq!(h.compression_level, CompressionLevel::EXTRA_HIGH);
+        assert_eq!(h.blocks_per_frame, 73728 * 4);
+        assert_eq!(h.bits_per_sample, 24);
+        assert_eq!(h.channel_count, 1);
+        assert_eq!(h.len_blocks(), 24000);
+        assert_eq!(mac.duration(), Duration::from_millis(500));
+        assert!(mac.ape().is_none());
+    }
+}
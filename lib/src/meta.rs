@@ -3,15 +3,30 @@ use std::fmt;
 use std::io;
 use std::time::Duration;
 
+use crate::aiff::Aiff;
+use crate::mac::Mac;
+use crate::mp4::Mp4;
 use crate::mpeg::Mpeg;
 use crate::flac::Flac;
+use crate::ogg::{self, Opus, Speex, Vorbis};
 use crate::tags::TagsRef;
+use crate::wav::Wav;
+use crate::wavpack::WavPack;
 use crate::util::*;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum FormatKind {
+    Aiff,
     Flac,
+    Mac,
+    Mp4,
     Mpeg,
+    OggFlac,
+    OggOpus,
+    OggSpeex,
+    OggVorbis,
+    Wav,
+    WavPack,
 
     #[doc(hidden)]
     __Nonexhaustive,
@@ -19,8 +34,17 @@ pub enum FormatKind {
 
 #[derive(Debug)]
 pub enum FormatRef<'a> {
+    Aiff(&'a Aiff),
     Flac(&'a Flac),
+    Mac(&'a Mac),
+    Mp4(&'a Mp4),
     Mpeg(&'a Mpeg),
+    OggFlac(&'a ogg::Flac),
+    OggOpus(&'a Opus),
+    OggSpeex(&'a Speex),
+    OggVorbis(&'a Vorbis),
+    Wav(&'a Wav),
+    WavPack(&'a WavPack),
 
     #[doc(hidden)]
     __Nonexhaustive,
@@ -30,8 +54,17 @@ impl FormatRef<'_> {
     pub fn kind(&self) -> FormatKind {
         use FormatRef::*;
         match self {
+            Aiff(_) => FormatKind::Aiff,
             Flac(_) => FormatKind::Flac,
+            Mac(_) => FormatKind::Mac,
+            Mp4(_) => FormatKind::Mp4,
             Mpeg(_) => FormatKind::Mpeg,
+            OggFlac(_) => FormatKind::OggFlac,
+            OggOpus(_) => FormatKind::OggOpus,
+            OggSpeex(_) => FormatKind::OggSpeex,
+            OggVorbis(_) => FormatKind::OggVorbis,
+            Wav(_) => FormatKind::Wav,
+            WavPack(_) => FormatKind::WavPack,
             __Nonexhaustive => unreachable!(),
         }
     }
@@ -41,8 +74,25 @@ impl fmt::Display for FormatRef<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use FormatRef::*;
         match self {
+            Aiff(v) => if let Some(c) = v.comm().compression_type {
+                write!(f, "AIFF-C ({})", c)
+            } else {
+                write!(f, "AIFF")
+            }
             Flac(_) => write!(f, "FLAC"),
+            Mac(_) => write!(f, "Monkey's Audio"),
+            Mp4(v) => write!(f, "MP4 ({})", v.track().codec),
             Mpeg(v) => write!(f, "MPEG {} Layer {}", v.header().version, v.header().layer),
+            OggFlac(_) => write!(f, "Ogg FLAC"),
+            OggOpus(_) => write!(f, "Ogg Opus"),
+            OggSpeex(_) => write!(f, "Ogg Speex"),
+            OggVorbis(_) => write!(f, "Ogg Vorbis"),
+            Wav(v) => write!(f, "WAV ({:?})", v.format().kind()),
+            WavPack(v) => if v.is_lossless() {
+                write!(f, "WavPack")
+            } else {
+                write!(f, "WavPack (hybrid)")
+            }
             __Nonexhaustive => unreachable!(),
         }
     }
@@ -50,21 +100,48 @@ impl fmt::Display for FormatRef<'_> {
 
 impl_enum_bits_ref!(
 FormatRef:
+    as_aiff <= Aiff ( Aiff ),
     as_flac <= Flac ( Flac ),
+    as_mac <= Mac ( Mac ),
+    as_mp4 <= Mp4 ( Mp4 ),
     as_mpeg <= Mpeg ( Mpeg ),
+    as_ogg_flac <= OggFlac ( ogg::Flac ),
+    as_ogg_opus <= OggOpus ( Opus ),
+    as_ogg_speex <= OggSpeex ( Speex ),
+    as_ogg_vorbis <= OggVorbis ( Vorbis ),
+    as_wav <= Wav ( Wav ),
+    as_wavpack <= WavPack ( WavPack ),
 );
 
 enum Format {
+    Aiff(Aiff),
     Flac(Flac),
+    Mac(Mac),
+    Mp4(Mp4),
     Mpeg(Mpeg),
+    OggFlac(ogg::Flac),
+    OggOpus(Opus),
+    OggSpeex(Speex),
+    OggVorbis(Vorbis),
+    Wav(Wav),
+    WavPack(WavPack),
 }
 
 impl Format {
     fn to_ref(&self) -> FormatRef {
         use Format::*;
         match self {
+            Aiff(v) => FormatRef::Aiff(v),
             Flac(v) => FormatRef::Flac(v),
+            Mac(v) => FormatRef::Mac(v),
+            Mp4(v) => FormatRef::Mp4(v),
             Mpeg(v) => FormatRef::Mpeg(v),
+            OggFlac(v) => FormatRef::OggFlac(v),
+            OggOpus(v) => FormatRef::OggOpus(v),
+            OggSpeex(v) => FormatRef::OggSpeex(v),
+            OggVorbis(v) => FormatRef::OggVorbis(v),
+            Wav(v) => FormatRef::Wav(v),
+            WavPack(v) => FormatRef::WavPack(v),
         }
     }
 }
@@ -75,6 +152,33 @@ pub struct Meta {
 
 impl Meta {
     pub fn read(mut rd: impl Read + Seek) -> io::Result<Option<Self>> {
+        if let Some(f) = Vorbis::read(&mut rd).into_opt()? {
+            return Ok(Some(Self::new(Format::OggVorbis(f))));
+        }
+        if let Some(f) = Opus::read(&mut rd).into_opt()? {
+            return Ok(Some(Self::new(Format::OggOpus(f))));
+        }
+        if let Some(f) = ogg::Flac::read(&mut rd).into_opt()? {
+            return Ok(Some(Self::new(Format::OggFlac(f))));
+        }
+        if let Some(f) = Speex::read(&mut rd).into_opt()? {
+            return Ok(Some(Self::new(Format::OggSpeex(f))));
+        }
+        if let Some(f) = Wav::read(&mut rd).into_opt()? {
+            return Ok(Some(Self::new(Format::Wav(f))));
+        }
+        if let Some(f) = Aiff::read(&mut rd).into_opt()? {
+            return Ok(Some(Self::new(Format::Aiff(f))));
+        }
+        if let Some(f) = Mp4::read(&mut rd).into_opt()? {
+            return Ok(Some(Self::new(Format::Mp4(f))));
+        }
+        if let Some(f) = Mac::read(&mut rd).into_opt()? {
+            return Ok(Some(Self::new(Format::Mac(f))));
+        }
+        if let Some(f) = WavPack::read(&mut rd).into_opt()? {
+            return Ok(Some(Self::new(Format::WavPack(f))));
+        }
         if let Some(f) = Mpeg::read(&mut rd).into_opt()? {
             return Ok(Some(Self::new(Format::Mpeg(f))));
         }
@@ -91,48 +195,102 @@ impl Meta {
     pub fn duration(&self) -> Option<Duration> {
         use Format::*;
         match &self.format {
+            Aiff(v) => Some(v.duration()),
             Flac(v) => v.duration(),
+            Mac(v) => Some(v.duration()),
+            Mp4(v) => v.duration(),
             Mpeg(v) => Some(v.duration()),
+            OggFlac(v) => v.duration(),
+            OggOpus(v) => v.duration(),
+            OggSpeex(v) => v.duration(),
+            OggVorbis(v) => v.duration(),
+            Wav(v) => v.duration(),
+            WavPack(v) => v.duration(),
         }
     }
 
     pub fn channel_count(&self) -> u32 {
         use Format::*;
         match &self.format {
+            Aiff(v) => v.comm().channel_count as u32,
             Flac(v) => v.stream_info().channel_count as u32,
+            Mac(v) => v.header().channel_count as u32,
+            Mp4(v) => v.track().channel_count as u32,
             Mpeg(v) => v.header().channel_mode.count(),
+            OggFlac(v) => v.stream_info().channel_count as u32,
+            OggOpus(v) => v.head().channel_count as u32,
+            OggSpeex(v) => v.header().channel_count as u32,
+            OggVorbis(v) => v.ident_header().channel_count as u32,
+            Wav(v) => v.format().channel_count as u32,
+            WavPack(v) => v.channel_count(),
         }
     }
 
     pub fn samples_per_sec(&self) -> u32 {
         use Format::*;
         match &self.format {
+            Aiff(v) => v.comm().samples_per_sec,
             Flac(v) => v.stream_info().samples_per_sec,
+            Mac(v) => v.header().samples_per_sec,
+            Mp4(v) => v.track().samples_per_sec,
             Mpeg(v) => v.header().samples_per_sec as u32,
+            OggFlac(v) => v.stream_info().samples_per_sec,
+            OggOpus(_) => ogg::opus::SAMPLES_PER_SEC,
+            OggSpeex(v) => v.header().samples_per_sec,
+            OggVorbis(v) => v.ident_header().samples_per_sec,
+            Wav(v) => v.format().samples_per_sec,
+            WavPack(v) => v.samples_per_sec(),
         }
     }
 
     pub fn bits_per_sec(&self) -> Option<u32> {
         use Format::*;
         match &self.format {
+            Aiff(v) => v.bits_per_sec(),
             Flac(v) => v.bits_per_sec(),
+            Mac(v) => v.bits_per_sec(),
+            Mp4(v) => v.bits_per_sec(),
             Mpeg(v) => Some(v.bits_per_sec() as u32),
+            OggFlac(v) => v.bits_per_sec(),
+            OggOpus(v) => v.bits_per_sec(),
+            OggSpeex(v) => v.bits_per_sec(),
+            OggVorbis(v) => v.bits_per_sec(),
+            Wav(v) => Some(v.bits_per_sec()),
+            WavPack(v) => v.bits_per_sec(),
         }
     }
 
     pub fn bits_per_sample(&self) -> Option<u32> {
         use Format::*;
         match &self.format {
+            Aiff(v) => Some(v.comm().bits_per_sample as u32),
             Flac(v) => Some(v.stream_info().bits_per_sample as u32),
+            Mac(v) => Some(v.header().bits_per_sample as u32),
+            Mp4(v) => v.track().bits_per_sample.map(|v| v as u32),
             Mpeg(_) => None,
+            OggFlac(v) => Some(v.stream_info().bits_per_sample as u32),
+            OggOpus(_) => None,
+            OggSpeex(_) => None,
+            OggVorbis(_) => None,
+            Wav(v) => Some(v.format().bits_per_sample as u32),
+            WavPack(v) => Some(v.bits_per_sample()),
         }
     }
 
     pub fn tags(&self) -> TagsRef {
         use Format::*;
         match &self.format {
+            Aiff(v) => v.tags(),
             Flac(v) => v.tags(),
+            Mac(v) => v.tags(),
+            Mp4(v) => v.tags(),
             Mpeg(v) => v.tags(),
+            OggFlac(v) => v.tags(),
+            OggOpus(v) => v.tags(),
+            OggSpeex(v) => v.tags(),
+            OggVorbis(v) => v.tags(),
+            Wav(v) => v.tags(),
+            WavPack(v) => v.tags(),
         }
     }
 
@@ -0,0 +1,1019 @@
+pub mod ilst;
+
+use byteorder::{ByteOrder, BE};
+use std::cmp;
+use std::convert::TryFrom;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::*;
+use crate::tags::TagsRef;
+use crate::util::*;
+use crate::util::file::{copy_range, rewrite_file};
+
+pub use crate::fourcc::FourCC;
+pub use ilst::Ilst;
+
+/// Max len of the atom data read into memory, larger atoms are considered corrupted.
+const MAX_ATOM_LEN: u64 = 256 * 1024 * 1024;
+
+impl FourCC {
+    pub(crate) const FTYP: Self = Self(*b"ftyp");
+    pub(crate) const MOOV: Self = Self(*b"moov");
+    pub(crate) const MVHD: Self = Self(*b"mvhd");
+    pub(crate) const TRAK: Self = Self(*b"trak");
+    pub(crate) const MDIA: Self = Self(*b"mdia");
+    pub(crate) const MDHD: Self = Self(*b"mdhd");
+    pub(crate) const HDLR: Self = Self(*b"hdlr");
+    pub(crate) const MINF: Self = Self(*b"minf");
+    pub(crate) const STBL: Self = Self(*b"stbl");
+    pub(crate) const STSD: Self = Self(*b"stsd");
+    pub(crate) const STSZ: Self = Self(*b"stsz");
+    pub(crate) const STCO: Self = Self(*b"stco");
+    pub(crate) const CO64: Self = Self(*b"co64");
+    pub(crate) const UDTA: Self = Self(*b"udta");
+    pub(crate) const META: Self = Self(*b"meta");
+    pub(crate) const ILST: Self = Self(*b"ilst");
+    pub(crate) const FREE: Self = Self(*b"free");
+    pub(crate) const MDAT: Self = Self(*b"mdat");
+
+    const SOUN: Self = Self(*b"soun");
+    const ALAC: Self = Self(*b"alac");
+    const ESDS: Self = Self(*b"esds");
+    const WAVE: Self = Self(*b"wave");
+}
+
+/// Decodes the atom header from `buf` that has `avail` bytes till the end of the parent.
+/// Returns the atom kind, header len and the whole atom len.
+fn decode_header(buf: &[u8], avail: u64) -> Result<(FourCC, usize, u64)> {
+    if buf.len() < 8 {
+        return Err(Error("atom header is truncated"));
+    }
+    let kind = FourCC([buf[4], buf[5], buf[6], buf[7]]);
+    let (header_len, len) = match BE::read_u32(&buf[..4]) {
+        0 => (8, avail),
+        1 => {
+            if buf.len() < 16 {
+                return Err(Error("atom header is truncated"));
+            }
+            (16, BE::read_u64(&buf[8..16]))
+        }
+        v => (8, v as u64),
+    };
+    if len < header_len as u64 {
+        return Err(Error("bad atom len"));
+    }
+    if len > avail {
+        return Err(Error("atom is truncated"));
+    }
+    Ok((kind, header_len, len))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Atom<'a> {
+    pub kind: FourCC,
+    /// Position of the atom in the file.
+    pub pos: u64,
+    pub header_len: usize,
+    pub data: &'a [u8],
+}
+
+impl<'a> Atom<'a> {
+    pub fn len(&self) -> u64 {
+        (self.header_len + self.data.len()) as u64
+    }
+
+    pub fn data_pos(&self) -> u64 {
+        self.pos + self.header_len as u64
+    }
+
+    pub fn children(&self) -> Atoms<'a> {
+        self.children_at(0)
+    }
+
+    /// Children that start at `offset` in the atom data.
+    pub fn children_at(&self, offset: usize) -> Atoms<'a> {
+        let offset = cmp::min(offset, self.data.len());
+        Atoms::new(&self.data[offset..], self.data_pos() + offset as u64)
+    }
+
+    pub fn child(&self, kind: FourCC) -> Result<Option<Atom<'a>>> {
+        find(self.children(), kind)
+    }
+
+    pub fn path(&self, path: &[FourCC]) -> Result<Option<Atom<'a>>> {
+        let mut r = *self;
+        for &kind in path {
+            r = if let Some(v) = r.child(kind)? {
+                v
+            } else {
+                return Ok(None);
+            };
+        }
+        Ok(Some(r))
+    }
+
+    /// Children of the `meta` atom which is a full atom in MP4 but not in QuickTime.
+    pub fn meta_children(&self) -> Atoms<'a> {
+        if self.data.len() >= 4 && self.data[..4] == [0; 4] {
+            self.children_at(4)
+        } else {
+            self.children()
+        }
+    }
+}
+
+pub(crate) struct Atoms<'a> {
+    buf: &'a [u8],
+    pos: u64,
+}
+
+impl<'a> Atoms<'a> {
+    /// Iterates over the atoms in `buf` which is located at `pos` in the file.
+    pub fn new(buf: &'a [u8], pos: u64) -> Self {
+        Self {
+            buf,
+            pos,
+        }
+    }
+}
+
+impl<'a> Iterator for Atoms<'a> {
+    type Item = Result<Atom<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let (kind, header_len, len) = match decode_header(self.buf, self.buf.len() as u64) {
+            Ok(v) => v,
+            Err(e) => {
+                self.buf = &[];
+                return Some(Err(e));
+            }
+        };
+        let r = Atom {
+            kind,
+            pos: self.pos,
+            header_len,
+            data: &self.buf[header_len..len as usize],
+        };
+        self.buf = &self.buf[len as usize..];
+        self.pos += len;
+        Some(Ok(r))
+    }
+}
+
+pub(crate) fn find(atoms: Atoms, kind: FourCC) -> Result<Option<Atom>> {
+    for atom in atoms {
+        let atom = atom?;
+        if atom.kind == kind {
+            return Ok(Some(atom));
+        }
+    }
+    Ok(None)
+}
+
+/// Starts an atom in `buf`, returns its position to pass to `end_atom`.
+pub(crate) fn begin_atom(buf: &mut Vec<u8>, kind: FourCC) -> usize {
+    let r = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(&kind.0);
+    r
+}
+
+/// Writes the len of the atom started at `start` which ends at the end of `buf`.
+pub(crate) fn end_atom(buf: &mut [u8], start: usize) -> Result<()> {
+    let len = u32::try_from(buf.len() - start).map_err(|_| Error("atom is too large"))?;
+    BE::write_u32(&mut buf[start..], len);
+    Ok(())
+}
+
+/// Encodes the `free` atom of `len` bytes.
+fn encode_free(buf: &mut Vec<u8>, len: u64) -> Result<()> {
+    debug_assert!(len >= 8);
+    let len = u32::try_from(len).map_err(|_| Error("atom is too large"))?;
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(&FourCC::FREE.0);
+    buf.resize(buf.len() + len as usize - 8, 0);
+    Ok(())
+}
+
+/// Reads the top level atom at `pos` in the file, returns its kind, header len and len.
+pub(crate) fn read_header(rd: &mut (impl Read + Seek), pos: u64, file_len: u64)
+    -> io::Result<(FourCC, usize, u64)>
+{
+    rd.seek(SeekFrom::Start(pos))?;
+    let mut buf = [0; 16];
+    let n = cmp::min(buf.len() as u64, file_len - pos) as usize;
+    rd.read_exact(&mut buf[..n])?;
+    decode_header(&buf[..n], file_len - pos).map_err(|e| e.into_invalid_data_err())
+}
+
+/// Reads the whole top level atom at `pos`.
+pub(crate) fn read_atom(rd: &mut (impl Read + Seek), pos: u64, len: u64) -> io::Result<Vec<u8>> {
+    if len > MAX_ATOM_LEN {
+        return Err(Error("atom is too large").into_invalid_data_err());
+    }
+    rd.seek(SeekFrom::Start(pos))?;
+    read_vec_limited(&mut Limited::new(rd, len), len as usize, "atom truncated")
+}
+
+/// Decodes timescale and duration from `mvhd` or `mdhd` atom.
+fn decode_media_header(buf: &[u8]) -> Result<(u32, u64)> {
+    let (timescale_pos, len) = match buf.first() {
+        Some(0) => (12, 20),
+        Some(1) => (20, 32),
+        _ => return Err(Error("bad media header")),
+    };
+    if buf.len() < len {
+        return Err(Error("media header is truncated"));
+    }
+    let timescale = BE::read_u32(&buf[timescale_pos..]);
+    let duration = if len == 20 {
+        Some(BE::read_u32(&buf[16..])).filter(|&v| v != u32::MAX).map(|v| v as u64)
+    } else {
+        Some(BE::read_u64(&buf[24..])).filter(|&v| v != u64::MAX)
+    };
+    Ok((timescale, duration.unwrap_or(0)))
+}
+
+fn media_duration(len: u64, timescale: u32) -> Option<Duration> {
+    if timescale == 0 || len == 0 {
+        return None;
+    }
+    Some(duration(len, timescale))
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Esds {
+    /// MPEG-4 object type, 0x40 is MPEG-4 audio (AAC), 0x6b is MP3.
+    pub object_type: u8,
+
+    /// MPEG-4 audio object type, 2 is AAC LC, 5 is HE-AAC (SBR), 29 is HE-AACv2 (PS).
+    pub audio_object_type: Option<u8>,
+
+    pub avg_bits_per_sec: Option<u32>,
+}
+
+impl Esds {
+    fn decode(buf: &[u8]) -> Result<Self> {
+        /// Returns the descriptor tag, body and the rest of the buffer.
+        fn descriptor(buf: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+            let err = || Error("bad esds descriptor");
+            let tag = *buf.first().ok_or_else(err)?;
+            let mut len = 0;
+            let mut i = 1;
+            loop {
+                let b = *buf.get(i).ok_or_else(err)?;
+                len = len << 7 | (b & 0x7f) as usize;
+                i += 1;
+                if b & 0x80 == 0 {
+                    break;
+                }
+                if i > 4 {
+                    return Err(err());
+                }
+            }
+            let body = buf.get(i..i + len).ok_or_else(err)?;
+            Ok((tag, body, &buf[i + len..]))
+        }
+
+        fn find(mut buf: &[u8], tag: u8) -> Result<Option<&[u8]>> {
+            while !buf.is_empty() {
+                let (t, body, rest) = descriptor(buf)?;
+                if t == tag {
+                    return Ok(Some(body));
+                }
+                buf = rest;
+            }
+            Ok(None)
+        }
+
+        let err = || Error("bad esds atom");
+
+        let es = find(buf.get(4..).ok_or_else(err)?, 3)?.ok_or_else(err)?;
+        let flags = *es.get(2).ok_or_else(err)?;
+        let mut i = 3;
+        if flags & 0x80 != 0 {
+            i += 2;
+        }
+        if flags & 0x40 != 0 {
+            i += 1 + *es.get(i).ok_or_else(err)? as usize;
+        }
+        if flags & 0x20 != 0 {
+            i += 2;
+        }
+
+        let dc = find(es.get(i..).ok_or_else(err)?, 4)?.ok_or_else(err)?;
+        if dc.len() < 13 {
+            return Err(err());
+        }
+        let object_type = dc[0];
+        let avg_bits_per_sec = Some(BE::read_u32(&dc[9..13])).filter(|&v| v != 0);
+
+        let audio_object_type = find(&dc[13..], 5)?
+            .filter(|v| v.len() >= 2)
+            .map(|v| match v[0] >> 3 {
+                31 => 32 + ((v[0] & 0b111) << 3 | v[1] >> 5),
+                v => v,
+            });
+
+        Ok(Self {
+            object_type,
+            audio_object_type,
+            avg_bits_per_sec,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AudioTrack {
+    /// Sample entry kind, `mp4a` for AAC, `alac` for Apple Lossless.
+    pub codec: FourCC,
+    pub channel_count: u8,
+    pub samples_per_sec: u32,
+
+    /// Bits per sample, only set for the lossless codecs.
+    pub bits_per_sample: Option<u8>,
+
+    /// Average bitrate as set by the encoder.
+    pub nominal_bits_per_sec: Option<u32>,
+
+    pub esds: Option<Esds>,
+
+    /// Sum of the sample sizes in bytes.
+    pub len_bytes: u64,
+
+    pub duration: Option<Duration>,
+}
+
+impl AudioTrack {
+    /// Decodes the `trak` atom, returns `None` if it's not a sound track.
+    fn decode(trak: Atom) -> Result<Option<Self>> {
+        use FourCC as F;
+
+        let mdia = trak.child(F::MDIA)?.ok_or(Error("missing mdia atom"))?;
+        let hdlr = mdia.child(F::HDLR)?.ok_or(Error("missing hdlr atom"))?;
+        if hdlr.data.len() < 12 || hdlr.data[8..12] != F::SOUN.0 {
+            return Ok(None);
+        }
+        let (timescale, len) = decode_media_header(
+            mdia.child(F::MDHD)?.ok_or(Error("missing mdhd atom"))?.data)?;
+        let duration = media_duration(len, timescale);
+
+        let stbl = mdia.path(&[F::MINF, F::STBL])?.ok_or(Error("missing stbl atom"))?;
+        let stsd = stbl.child(F::STSD)?.ok_or(Error("missing stsd atom"))?;
+        let entry = stsd.children_at(8).next().ok_or(Error("empty stsd atom"))??;
+        let d = entry.data;
+        if d.len() < 28 {
+            return Err(Error("audio sample entry is truncated"));
+        }
+        let (mut channel_count, mut samples_per_sec, children_offset) = match BE::read_u16(&d[8..]) {
+            0 => (BE::read_u16(&d[16..]) as u32, BE::read_u32(&d[24..]) >> 16, 28),
+            1 => (BE::read_u16(&d[16..]) as u32, BE::read_u32(&d[24..]) >> 16, 28 + 16),
+            2 => {
+                if d.len() < 28 + 36 {
+                    return Err(Error("audio sample entry is truncated"));
+                }
+                (BE::read_u32(&d[40..]), f64::from_bits(BE::read_u64(&d[32..])) as u32, 28 + 36)
+            }
+            _ => return Err(Error("unsupported audio sample entry version")),
+        };
+        let mut bits_per_sample = None;
+        let mut nominal_bits_per_sec = None;
+        let mut esds = None;
+        for child in entry.children_at(children_offset) {
+            let child = child?;
+            match child.kind {
+                F::ALAC if entry.kind == F::ALAC => {
+                    let c = child.data;
+                    if c.len() < 4 + 24 {
+                        return Err(Error("alac atom is truncated"));
+                    }
+                    bits_per_sample = Some(c[4 + 5]);
+                    channel_count = c[4 + 9] as u32;
+                    nominal_bits_per_sec = Some(BE::read_u32(&c[4 + 16..])).filter(|&v| v != 0);
+                    samples_per_sec = BE::read_u32(&c[4 + 20..]);
+                }
+                F::ESDS => esds = Some(Esds::decode(child.data)?),
+                F::WAVE => if let Some(v) = find(child.children(), F::ESDS)? {
+                    esds = Some(Esds::decode(v.data)?);
+                }
+                _ => {}
+            }
+        }
+        if let Some(esds) = &esds {
+            nominal_bits_per_sec = esds.avg_bits_per_sec;
+        }
+        if channel_count == 0 || channel_count > 255 {
+            return Err(Error("bad channel count"));
+        }
+        if samples_per_sec == 0 {
+            samples_per_sec = timescale;
+        }
+
+        let len_bytes = if let Some(stsz) = stbl.child(F::STSZ)? {
+            let s = stsz.data;
+            if s.len() < 12 {
+                return Err(Error("stsz atom is truncated"));
+            }
+            let sample_len = BE::read_u32(&s[4..]) as u64;
+            let count = BE::read_u32(&s[8..]) as u64;
+            if sample_len != 0 {
+                sample_len * count
+            } else {
+                let table = s.get(12..12 + count as usize * 4)
+                    .ok_or(Error("stsz atom is truncated"))?;
+                table.chunks(4).map(|v| BE::read_u32(v) as u64).sum()
+            }
+        } else {
+            0
+        };
+
+        Ok(Some(Self {
+            codec: entry.kind,
+            channel_count: channel_count as u8,
+            samples_per_sec,
+            bits_per_sample,
+            nominal_bits_per_sec,
+            esds,
+            len_bytes,
+            duration,
+        }))
+    }
+}
+
+/// Place of the `ilst` atom in `moov`.
+struct IlstSlot {
+    /// The `ilst` atom with the `free` atoms following it, or an empty range where to insert
+    /// the `ilst` atom.
+    range: Range<u64>,
+
+    /// Positions and header lens of the atoms containing the range, `moov` first.
+    parents: Vec<(u64, usize)>,
+
+    /// Number of the missing atoms that must be created around `ilst`: 1 for `meta`,
+    /// 2 for `udta` and `meta`.
+    missing: usize,
+}
+
+impl IlstSlot {
+    fn find(moov: Atom) -> Result<Self> {
+        use FourCC as F;
+
+        let end = |a: &Atom| a.pos + a.len();
+        let mut parents = vec![(moov.pos, moov.header_len)];
+        let udta = if let Some(v) = moov.child(F::UDTA)? {
+            v
+        } else {
+            return Ok(Self { range: end(&moov)..end(&moov), parents, missing: 2 });
+        };
+        parents.push((udta.pos, udta.header_len));
+        let meta = if let Some(v) = udta.child(F::META)? {
+            v
+        } else {
+            return Ok(Self { range: end(&udta)..end(&udta), parents, missing: 1 });
+        };
+        parents.push((meta.pos, meta.header_len));
+
+        let mut range: Option<Range<u64>> = None;
+        for atom in meta.meta_children() {
+            let atom = atom?;
+            match (&mut range, atom.kind) {
+                (None, F::ILST) => range = Some(atom.pos..end(&atom)),
+                (Some(r), F::FREE) => r.end = end(&atom),
+                (Some(_), _) => break,
+                (None, _) => {}
+            }
+        }
+        Ok(Self {
+            range: range.unwrap_or(end(&meta)..end(&meta)),
+            parents,
+            missing: 0,
+        })
+    }
+
+    /// Encodes the contents of the slot: `ilst` followed by the `free` atom of `padding_len`
+    /// wrapped in the missing atoms.
+    fn encode(&self, ilst: Option<&[u8]>, padding_len: u64) -> Result<Vec<u8>> {
+        let mut r = Vec::new();
+        let ilst = if let Some(v) = ilst {
+            v
+        } else if self.missing > 0 {
+            return Ok(r);
+        } else {
+            &[]
+        };
+        let udta = if self.missing > 1 { Some(begin_atom(&mut r, FourCC::UDTA)) } else { None };
+        let meta = if self.missing > 0 {
+            let start = begin_atom(&mut r, FourCC::META);
+            r.extend_from_slice(&[0; 4]);
+            let hdlr = begin_atom(&mut r, FourCC::HDLR);
+            r.extend_from_slice(&[0; 8]);
+            r.extend_from_slice(b"mdirappl");
+            r.extend_from_slice(&[0; 9]);
+            end_atom(&mut r, hdlr)?;
+            Some(start)
+        } else {
+            None
+        };
+        r.extend_from_slice(ilst);
+        if padding_len >= 8 {
+            encode_free(&mut r, padding_len)?;
+        }
+        if let Some(start) = meta {
+            end_atom(&mut r, start)?;
+        }
+        if let Some(start) = udta {
+            end_atom(&mut r, start)?;
+        }
+        Ok(r)
+    }
+}
+
+/// Adds `delta` to the chunk offsets in `stco` and `co64` atoms of `moov` that are not less
+/// than `from`.
+fn patch_chunk_offsets(moov: &mut [u8], moov_pos: u64, from: u64, delta: i64) -> Result<()> {
+    use FourCC as F;
+
+    let mut tables = Vec::new();
+    {
+        let moov_atom = Atoms::new(moov, moov_pos).next().unwrap()?;
+        for trak in moov_atom.children() {
+            let trak = trak?;
+            if trak.kind != F::TRAK {
+                continue;
+            }
+            if let Some(stbl) = trak.path(&[F::MDIA, F::MINF, F::STBL])? {
+                for atom in stbl.children() {
+                    let atom = atom?;
+                    if atom.kind == F::STCO || atom.kind == F::CO64 {
+                        let start = (atom.data_pos() - moov_pos) as usize;
+                        tables.push((atom.kind == F::CO64, start..start + atom.data.len()));
+                    }
+                }
+            }
+        }
+    }
+
+    for (wide, range) in tables {
+        let buf = &mut moov[range];
+        if buf.len() < 8 {
+            return Err(Error("chunk offset atom is truncated"));
+        }
+        let count = BE::read_u32(&buf[4..8]) as usize;
+        let entry_len = if wide { 8 } else { 4 };
+        let buf = buf[8..].get_mut(..count * entry_len)
+            .ok_or(Error("chunk offset atom is truncated"))?;
+        for entry in buf.chunks_exact_mut(entry_len) {
+            let v = if wide { BE::read_u64(entry) } else { BE::read_u32(entry) as u64 };
+            if v < from {
+                continue;
+            }
+            let v = (v as i64).checked_add(delta).ok_or(Error("chunk offset overflow"))? as u64;
+            if wide {
+                BE::write_u64(entry, v);
+            } else {
+                let v = u32::try_from(v)
+                    .map_err(|_| Error("chunk offset overflow, co64 atom is required"))?;
+                BE::write_u32(entry, v);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SaveOptions {
+    /// Len of the `free` atom placed after `ilst` when `moov` has to be resized.
+    pub padding_len: u32,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            padding_len: 4096,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mp4 {
+    major_brand: FourCC,
+    compatible_brands: Vec<FourCC>,
+    track: AudioTrack,
+    duration: Option<Duration>,
+    bits_per_sec: Option<u32>,
+    ilst: Option<Ilst>,
+}
+
+impl Mp4 {
+    pub fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
+        let file_len = rd.seek(SeekFrom::End(0))?;
+
+        let mut ftyp = None;
+        let mut moov = None;
+        let mut mdat_len = 0;
+        let mut pos = 0;
+        while file_len - pos >= 8 {
+            let (kind, header_len, len) = read_header(&mut rd, pos, file_len)?;
+            if pos == 0 && kind != FourCC::FTYP {
+                return Err(Error("missing ftyp atom").into_invalid_data_err());
+            }
+            match kind {
+                FourCC::FTYP if ftyp.is_none() => ftyp = Some(read_atom(&mut rd, pos, len)?),
+                FourCC::MOOV if moov.is_none() => moov = Some((pos, read_atom(&mut rd, pos, len)?)),
+                FourCC::MDAT => mdat_len += len - header_len as u64,
+                _ => {}
+            }
+            pos += len;
+        }
+        let ftyp = ftyp.ok_or_else(|| Error("missing ftyp atom").into_invalid_data_err())?;
+        let (moov_pos, moov) = moov.ok_or_else(|| Error("missing moov atom").into_invalid_data_err())?;
+
+        Self::decode(&ftyp, Atoms::new(&moov, moov_pos), mdat_len)
+            .map_err(|e| e.into_invalid_data_err())
+    }
+
+    pub fn major_brand(&self) -> FourCC {
+        self.major_brand
+    }
+
+    pub fn compatible_brands(&self) -> &[FourCC] {
+        &self.compatible_brands
+    }
+
+    /// The first sound track.
+    pub fn track(&self) -> &AudioTrack {
+        &self.track
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Nominal bitrate if set, otherwise the average bitrate.
+    pub fn bits_per_sec(&self) -> Option<u32> {
+        self.bits_per_sec
+    }
+
+    pub fn ilst(&self) -> Option<&Ilst> {
+        self.ilst.as_ref()
+    }
+
+    pub fn ilst_mut(&mut self) -> &mut Option<Ilst> {
+        &mut self.ilst
+    }
+
+    pub fn tags(&self) -> TagsRef<'_> {
+        TagsRef {
+            ilst: self.ilst.as_ref(),
+            .. Default::default()
+        }
+    }
+
+    /// Writes the `ilst` atom to the file at `path`, `None` removes it.
+    /// The `ilst` is rewritten in place if it fits in the existing `ilst` and the `free` atoms
+    /// following it or the `moov` atom. Otherwise `moov` is resized and if it precedes the media
+    /// data the whole file is rewritten with the chunk offsets adjusted.
+    pub fn save(&self, path: impl AsRef<Path>, options: &SaveOptions) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let file_len = file.seek(SeekFrom::End(0))?;
+
+        // The file could change since it was read so the atoms are located anew.
+        let mut atoms = Vec::new();
+        let mut pos = 0;
+        while file_len - pos >= 8 {
+            let (kind, _, len) = read_header(&mut file, pos, file_len)?;
+            atoms.push((kind, pos, len));
+            pos += len;
+        }
+        let moov_idx = atoms.iter().position(|&(kind, ..)| kind == FourCC::MOOV)
+            .ok_or_else(|| Error("missing moov atom").into_invalid_data_err())?;
+        let (_, moov_pos, moov_len) = atoms[moov_idx];
+        let moov_end = moov_pos + moov_len;
+        let moov = read_atom(&mut file, moov_pos, moov_len)?;
+
+        let ilst = self.ilst.as_ref().map(|v| v.encode()).transpose()
+            .map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))?;
+        let slot = Atoms::new(&moov, moov_pos).next().unwrap()
+            .and_then(IlstSlot::find)
+            .map_err(|e| e.into_invalid_data_err())?;
+        let slot_len = slot.range.end - slot.range.start;
+
+        // The ilst with the padding fits in the slot.
+        let new_len = ilst.as_ref().map(|v| v.len() as u64).unwrap_or(0);
+        if slot.missing == 0 && (new_len == slot_len || new_len + 8 <= slot_len) {
+            let bytes = slot.encode(ilst.as_deref(), slot_len - new_len)
+                .map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))?;
+            debug_assert_eq!(bytes.len() as u64, slot_len);
+            file.seek(SeekFrom::Start(slot.range.start))?;
+            file.write_all(&bytes)?;
+            return file.sync_all();
+        }
+
+        let bytes = slot.encode(ilst.as_deref(), options.padding_len as u64)
+            .map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))?;
+        let delta = bytes.len() as i64 - slot_len as i64;
+        let mut new_moov = Vec::with_capacity(moov.len() + bytes.len());
+        new_moov.extend_from_slice(&moov[..(slot.range.start - moov_pos) as usize]);
+        new_moov.extend_from_slice(&bytes);
+        new_moov.extend_from_slice(&moov[(slot.range.end - moov_pos) as usize..]);
+        for &(pos, header_len) in &slot.parents {
+            let buf = &mut new_moov[(pos - moov_pos) as usize..];
+            if header_len == 16 {
+                let len = BE::read_u64(&buf[8..16]) as i64 + delta;
+                BE::write_u64(&mut buf[8..16], len as u64);
+            } else {
+                let len = u32::try_from(BE::read_u32(buf) as i64 + delta)
+                    .map_err(|_| Error("atom is too large").into_io_err(io::ErrorKind::InvalidInput))?;
+                BE::write_u32(buf, len);
+            }
+        }
+        let new_moov_len = new_moov.len() as u64;
+
+        // The free atom following moov can absorb the change.
+        let available = moov_len + match atoms.get(moov_idx + 1) {
+            Some(&(FourCC::FREE, _, len)) => len,
+            _ => 0,
+        };
+        if new_moov_len == available || new_moov_len + 8 <= available {
+            if new_moov_len < available {
+                encode_free(&mut new_moov, available - new_moov_len)
+                    .map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))?;
+            }
+            file.seek(SeekFrom::Start(moov_pos))?;
+            file.write_all(&new_moov)?;
+            return file.sync_all();
+        }
+
+        // Nothing follows moov so the chunk offsets don't change.
+        if moov_idx == atoms.len() - 1 {
+            file.seek(SeekFrom::Start(moov_pos))?;
+            file.write_all(&new_moov)?;
+            file.set_len(moov_pos + new_moov_len)?;
+            return file.sync_all();
+        }
+
+        patch_chunk_offsets(&mut new_moov, moov_pos, moov_end, delta)
+            .map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))?;
+        rewrite_file(path, &mut file, |src, dst| {
+            copy_range(src, dst, 0..moov_pos)?;
+            dst.write_all(&new_moov)?;
+            copy_range(src, dst, moov_end..file_len)
+        })
+    }
+
+    fn decode(ftyp: &[u8], mut moov: Atoms, mdat_len: u64) -> Result<Self> {
+        use FourCC as F;
+
+        let ftyp = &ftyp[8..];
+        if ftyp.len() < 8 {
+            return Err(Error("ftyp atom is truncated"));
+        }
+        let major_brand = FourCC([ftyp[0], ftyp[1], ftyp[2], ftyp[3]]);
+        let compatible_brands = ftyp[8..].chunks_exact(4)
+            .map(|v| FourCC([v[0], v[1], v[2], v[3]]))
+            .collect();
+
+        let moov = moov.next().unwrap()?;
+        let mvhd = moov.child(F::MVHD)?.ok_or(Error("missing mvhd atom"))?;
+        let (timescale, len) = decode_media_header(mvhd.data)?;
+
+        let mut track = None;
+        for trak in moov.children() {
+            let trak = trak?;
+            if trak.kind == F::TRAK {
+                track = AudioTrack::decode(trak)?;
+                if track.is_some() {
+                    break;
+                }
+            }
+        }
+        let track = track.ok_or(Error("no sound track"))?;
+
+        let duration = track.duration.or_else(|| media_duration(len, timescale));
+        let len_bytes = if track.len_bytes > 0 { track.len_bytes } else { mdat_len };
+        let bits_per_sec = track.nominal_bits_per_sec
+            .or_else(|| duration.and_then(|d| bits_per_sec(len_bytes, d)));
+
+        let ilst = if let Some(meta) = moov.path(&[F::UDTA, F::META])? {
+            find(meta.meta_children(), F::ILST)?
+                .map(Ilst::decode)
+                .transpose()?
+        } else {
+            None
+        };
+
+        Ok(Self {
+            major_brand,
+            compatible_brands,
+            track,
+            duration,
+            bits_per_sec,
+            ilst,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::Cursor;
+    use crate::util::file::test::TempFile;
+
+    pub(crate) fn atom(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut r = (8 + data.len() as u32).to_be_bytes().to_vec();
+        r.extend_from_slice(kind);
+        r.extend_from_slice(data);
+        r
+    }
+
+    pub(crate) fn atoms(kind: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+        atom(kind, &children.concat())
+    }
+
+    pub(crate) fn audio() -> Vec<u8> {
+        (0..1000u32).map(|v| v as u8).collect()
+    }
+
+    pub(crate) fn file(ilst: &[Vec<u8>]) -> Vec<u8> {
+        file_with(ilst, false, 0)
+    }
+
+    /// Builds a file with an AAC track of 2 seconds and 1000 bytes of audio data. If `moov_first`
+    /// is set `moov` goes before `mdat` followed by a `free` atom of `free_len`.
+    pub(crate) fn file_with(ilst: &[Vec<u8>], moov_first: bool, free_len: usize) -> Vec<u8> {
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&2000u32.to_be_bytes());
+
+        let mut hdlr = vec![0; 25];
+        hdlr[8..12].copy_from_slice(b"soun");
+
+        let mut mdhd = vec![0; 24];
+        mdhd[12..16].copy_from_slice(&44100u32.to_be_bytes());
+        mdhd[16..20].copy_from_slice(&88200u32.to_be_bytes());
+
+        let mut mp4a = vec![0; 28];
+        mp4a[16..18].copy_from_slice(&2u16.to_be_bytes());
+        mp4a[18..20].copy_from_slice(&16u16.to_be_bytes());
+        mp4a[24..28].copy_from_slice(&(44100u32 << 16).to_be_bytes());
+        let esds = [0, 0, 0, 0,
+            3, 25, 0, 1, 0,
+            4, 17, 0x40, 0x15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            5, 2, 0x12, 0x10,
+            6, 1, 2];
+        mp4a.extend_from_slice(&atom(b"esds", &esds));
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend_from_slice(&atom(b"mp4a", &mp4a));
+
+        let mut stsz = vec![0; 12];
+        stsz[8..12].copy_from_slice(&2u32.to_be_bytes());
+        stsz.extend_from_slice(&400u32.to_be_bytes());
+        stsz.extend_from_slice(&600u32.to_be_bytes());
+
+        let moov = |chunk_offset: u32| {
+            let mut stco = vec![0, 0, 0, 0, 0, 0, 0, 1];
+            stco.extend_from_slice(&chunk_offset.to_be_bytes());
+
+            let stbl = atoms(b"stbl", &[atom(b"stsd", &stsd), atom(b"stsz", &stsz), atom(b"stco", &stco)]);
+            let trak = atoms(b"trak", &[atoms(b"mdia", &[
+                atom(b"mdhd", &mdhd),
+                atom(b"hdlr", &hdlr),
+                atoms(b"minf", &[stbl]),
+            ])]);
+
+            let mut moov = vec![atom(b"mvhd", &mvhd), trak];
+            if !ilst.is_empty() {
+                let mut meta = vec![0; 4];
+                meta.extend_from_slice(&atom(b"hdlr", &[0; 25]));
+                meta.extend_from_slice(&atoms(b"ilst", ilst));
+                moov.push(atoms(b"udta", &[atom(b"meta", &meta)]));
+            }
+            atoms(b"moov", &moov)
+        };
+
+        let mut r = atom(b"ftyp", b"M4A \0\0\0\0M4A mp42isom");
+        if moov_first {
+            let mdat_pos = r.len() + moov(0).len() + free_len;
+            r.extend_from_slice(&moov(mdat_pos as u32 + 8));
+            if free_len > 0 {
+                r.extend_from_slice(&atom(b"free", &vec![0; free_len - 8]));
+            }
+            r.extend_from_slice(&atom(b"mdat", &audio()));
+        } else {
+            let mdat_pos = r.len();
+            r.extend_from_slice(&atom(b"mdat", &audio()));
+            r.extend_from_slice(&moov(mdat_pos as u32 + 8));
+        }
+        r
+    }
+
+    fn write_file(name: &str, bytes: &[u8]) -> TempFile {
+        let path = TempFile::new(&format!("{}.m4a", name));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// Checks the chunk offset points at the audio data.
+    fn check_audio(buf: &[u8]) {
+        let mut moov = None;
+        let mut mdat = None;
+        for atom in Atoms::new(buf, 0) {
+            let atom = atom.unwrap();
+            match atom.kind {
+                FourCC::MOOV => moov = Some(atom),
+                FourCC::MDAT => mdat = Some(atom),
+                _ => {}
+            }
+        }
+        let (moov, mdat) = (moov.unwrap(), mdat.unwrap());
+        assert_eq!(mdat.data, &audio()[..]);
+        let stco = moov.path(&[FourCC::TRAK, FourCC::MDIA, FourCC::MINF, FourCC::STBL, FourCC::STCO])
+            .unwrap().unwrap();
+        assert_eq!(BE::read_u32(&stco.data[8..]) as u64, mdat.data_pos());
+    }
+
+    #[test]
+    fn read() {
+        let title = atoms(b"\xa9nam", &[atom(b"data", b"\0\0\0\x01\0\0\0\0title")]);
+        let mp4 = Mp4::read(Cursor::new(file(&[title]))).unwrap();
+        assert_eq!(mp4.major_brand(), FourCC(*b"M4A "));
+        assert_eq!(mp4.compatible_brands(), &[FourCC(*b"M4A "), FourCC(*b"mp42"), FourCC(*b"isom")]);
+        assert_eq!(mp4.track(), &AudioTrack {
+            codec: FourCC(*b"mp4a"),
+            channel_count: 2,
+            samples_per_sec: 44100,
+            bits_per_sample: None,
+            nominal_bits_per_sec: None,
+            esds: Some(Esds {
+                object_type: 0x40,
+                audio_object_type: Some(2),
+                avg_bits_per_sec: None,
+            }),
+            len_bytes: 1000,
+            duration: Some(Duration::from_secs(2)),
+        });
+        assert_eq!(mp4.duration(), Some(Duration::from_secs(2)));
+        assert_eq!(mp4.bits_per_sec(), Some(4000));
+        assert_eq!(mp4.tags().title(), Some("title".into()));
+
+        let mp4 = Mp4::read(Cursor::new(file(&[]))).unwrap();
+        assert!(mp4.ilst().is_none());
+
+        assert_eq!(Mp4::read(Cursor::new(&b"\0\0\0\x08free"[..])).unwrap_err().kind(),
+            io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn save() {
+        for &(moov_first, free_len) in &[(false, 0), (true, 0), (true, 1000)] {
+            let orig = file_with(&[], moov_first, free_len);
+            let path = write_file(&format!("save-{}-{}", moov_first, free_len), &orig);
+            let read = || Mp4::read(File::open(&path).unwrap()).unwrap();
+
+            let mut mp4 = read();
+            mp4.ilst_mut().get_or_insert_with(Ilst::new).set_text(FourCC::TITLE, "title");
+            mp4.save(&path, &SaveOptions { padding_len: 100 }).unwrap();
+            let buf = fs::read(&path).unwrap();
+            check_audio(&buf);
+            if free_len > 0 {
+                assert_eq!(buf.len(), orig.len());
+            }
+            let mut mp4 = read();
+            assert_eq!(mp4.tags().title(), Some("title".into()));
+
+            // Fits in the padding.
+            mp4.ilst_mut().as_mut().unwrap().set_text(FourCC::ARTIST, "artist");
+            mp4.save(&path, &SaveOptions::default()).unwrap();
+            let len = buf.len();
+            let buf = fs::read(&path).unwrap();
+            assert_eq!(buf.len(), len);
+            check_audio(&buf);
+            let mut mp4 = read();
+            assert_eq!(mp4.tags().title(), Some("title".into()));
+            assert_eq!(mp4.tags().artist(), Some("artist".into()));
+
+            mp4.ilst_mut().as_mut().unwrap().set_text(FourCC::COMMENT, "c".repeat(500));
+            mp4.save(&path, &SaveOptions { padding_len: 0 }).unwrap();
+            check_audio(&fs::read(&path).unwrap());
+            let mut mp4 = read();
+            assert_eq!(mp4.ilst().unwrap().get(FourCC::COMMENT).next().unwrap().value.len(), 500);
+            assert_eq!(mp4.tags().artist(), Some("artist".into()));
+
+            *mp4.ilst_mut() = None;
+            mp4.save(&path, &SaveOptions::default()).unwrap();
+            check_audio(&fs::read(&path).unwrap());
+            assert!(read().ilst().is_none());
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use byteorder::{ByteOrder, BE};
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::error::*;
+use crate::id3::v1::Genre;
+use crate::timestamp::Timestamp;
+use super::{begin_atom, end_atom, Atom, FourCC};
+
+impl FourCC {
+    pub const TITLE: Self = Self(*b"\xa9nam");
+    pub const ARTIST: Self = Self(*b"\xa9ART");
+    pub const ALBUM: Self = Self(*b"\xa9alb");
+    pub const ALBUM_ARTIST: Self = Self(*b"aART");
+    pub const COMMENT: Self = Self(*b"\xa9cmt");
+    pub const COMPOSER: Self = Self(*b"\xa9wrt");
+    pub const DATE: Self = Self(*b"\xa9day");
+    pub const GENRE: Self = Self(*b"\xa9gen");
+
+    /// ID3v1 genre plus one.
+    pub const GENRE_ID: Self = Self(*b"gnre");
+    pub const TRACK: Self = Self(*b"trkn");
+    pub const DISC: Self = Self(*b"disk");
+    pub const COVER: Self = Self(*b"covr");
+    pub const FREEFORM: Self = Self(*b"----");
+
+    pub(crate) const DATA: Self = Self(*b"data");
+    pub(crate) const MEAN: Self = Self(*b"mean");
+    pub(crate) const NAME: Self = Self(*b"name");
+}
+
+/// Well-known data type.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct DataKind(pub u32);
+
+impl DataKind {
+    pub const IMPLICIT: Self = Self(0);
+    pub const UTF8: Self = Self(1);
+    pub const UTF16: Self = Self(2);
+    pub const JPEG: Self = Self(13);
+    pub const PNG: Self = Self(14);
+    pub const BE_SIGNED: Self = Self(21);
+    pub const BE_UNSIGNED: Self = Self(22);
+    pub const BMP: Self = Self(27);
+}
+
+impl fmt::Debug for DataKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Self::IMPLICIT => "IMPLICIT",
+            Self::UTF8 => "UTF8",
+            Self::UTF16 => "UTF16",
+            Self::JPEG => "JPEG",
+            Self::PNG => "PNG",
+            Self::BE_SIGNED => "BE_SIGNED",
+            Self::BE_UNSIGNED => "BE_UNSIGNED",
+            Self::BMP => "BMP",
+            _ => return write!(f, "DataKind({})", self.0),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Data {
+    pub kind: DataKind,
+    pub locale: u32,
+    pub value: Vec<u8>,
+}
+
+impl Data {
+    pub fn new(kind: DataKind, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            kind,
+            locale: 0,
+            value: value.into(),
+        }
+    }
+
+    pub fn text(s: impl Into<String>) -> Self {
+        Self::new(DataKind::UTF8, s.into())
+    }
+
+    /// Returns the value of the text data.
+    pub fn as_str(&self) -> Option<Cow<'_, str>> {
+        match self.kind {
+            DataKind::UTF8 => std::str::from_utf8(&self.value).ok().map(Cow::Borrowed),
+            DataKind::UTF16 => {
+                let units: Vec<_> = self.value.chunks_exact(2).map(BE::read_u16).collect();
+                String::from_utf16(&units).ok().map(Cow::Owned)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the integer data.
+    pub fn as_int(&self) -> Option<i64> {
+        let v = &self.value;
+        match self.kind {
+            DataKind::BE_SIGNED | DataKind::IMPLICIT => Some(match v.len() {
+                1 => v[0] as i8 as i64,
+                2 => BE::read_i16(v) as i64,
+                3 => BE::read_i24(v) as i64,
+                4 => BE::read_i32(v) as i64,
+                8 => BE::read_i64(v),
+                _ => return None,
+            }),
+            DataKind::BE_UNSIGNED => Some(match v.len() {
+                1 => v[0] as i64,
+                2 => BE::read_u16(v) as i64,
+                3 => BE::read_u24(v) as i64,
+                4 => BE::read_u32(v) as i64,
+                8 => BE::read_u64(v) as i64,
+                _ => return None,
+            }),
+            _ => None,
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let start = begin_atom(buf, FourCC::DATA);
+        buf.extend_from_slice(&self.kind.0.to_be_bytes());
+        buf.extend_from_slice(&self.locale.to_be_bytes());
+        buf.extend_from_slice(&self.value);
+        end_atom(buf, start)
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < 8 {
+            return Err(Error("ilst data atom is truncated"));
+        }
+        // The high byte is the type set which is always zero.
+        Ok(Self {
+            kind: DataKind(BE::read_u32(&buf[..4]) & 0xff_ffff),
+            locale: BE::read_u32(&buf[4..8]),
+            value: buf[8..].into(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ItemKey {
+    Atom(FourCC),
+
+    /// The `----` item identified by the reverse DNS `mean` (e.g. `com.apple.iTunes`) and `name`.
+    Freeform {
+        mean: String,
+        name: String,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Item {
+    pub key: ItemKey,
+    pub data: Vec<Data>,
+}
+
+impl Item {
+    fn decode(atom: Atom) -> Result<Self> {
+        fn decode_str(buf: &[u8]) -> Result<String> {
+            let s = buf.get(4..).ok_or(Error("ilst freeform atom is truncated"))?;
+            String::from_utf8(s.into()).map_err(|_| Error("invalid ilst freeform string"))
+        }
+
+        let mut mean = None;
+        let mut name = None;
+        let mut data = Vec::new();
+        for child in atom.children() {
+            let child = child?;
+            match child.kind {
+                FourCC::DATA => data.push(Data::decode(child.data)?),
+                FourCC::MEAN => mean = Some(decode_str(child.data)?),
+                FourCC::NAME => name = Some(decode_str(child.data)?),
+                _ => {}
+            }
+        }
+        let key = if atom.kind == FourCC::FREEFORM {
+            ItemKey::Freeform {
+                mean: mean.ok_or(Error("ilst freeform item without mean"))?,
+                name: name.ok_or(Error("ilst freeform item without name"))?,
+            }
+        } else {
+            ItemKey::Atom(atom.kind)
+        };
+        Ok(Self {
+            key,
+            data,
+        })
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<()> {
+        fn encode_str(buf: &mut Vec<u8>, kind: FourCC, s: &str) -> Result<()> {
+            let start = begin_atom(buf, kind);
+            buf.extend_from_slice(&[0; 4]);
+            buf.extend_from_slice(s.as_bytes());
+            end_atom(buf, start)
+        }
+
+        let start = match &self.key {
+            ItemKey::Atom(kind) => begin_atom(buf, *kind),
+            ItemKey::Freeform { mean, name } => {
+                let start = begin_atom(buf, FourCC::FREEFORM);
+                encode_str(buf, FourCC::MEAN, mean)?;
+                encode_str(buf, FourCC::NAME, name)?;
+                start
+            }
+        };
+        for data in &self.data {
+            data.encode(buf)?;
+        }
+        end_atom(buf, start)
+    }
+}
+
+/// iTunes-style metadata item list.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Ilst {
+    items: Vec<Item>,
+}
+
+impl Ilst {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn items(&self) -> impl Iterator<Item=&Item> {
+        self.items.iter()
+    }
+
+    pub fn get(&self, kind: FourCC) -> impl Iterator<Item=&Data> {
+        self.get_by_key(move |k| k == &ItemKey::Atom(kind))
+    }
+
+    pub fn freeform<'a>(&'a self, mean: &'a str, name: &'a str) -> impl 'a + Iterator<Item=&'a Data> {
+        self.get_by_key(move |k| match k {
+            ItemKey::Freeform { mean: m, name: n } => m == mean && n == name,
+            ItemKey::Atom(_) => false,
+        })
+    }
+
+    pub fn title(&self) -> Option<Cow<'_, str>> {
+        self.get_str(FourCC::TITLE)
+    }
+
+    pub fn artist(&self) -> Option<Cow<'_, str>> {
+        self.get_str(FourCC::ARTIST)
+    }
+
+    pub fn album(&self) -> Option<Cow<'_, str>> {
+        self.get_str(FourCC::ALBUM)
+    }
+
+    pub fn album_artist(&self) -> Option<Cow<'_, str>> {
+        self.get_str(FourCC::ALBUM_ARTIST)
+    }
+
+    pub fn genre(&self) -> Option<Cow<'_, str>> {
+        self.get_str(FourCC::GENRE).or_else(|| {
+            let id = self.get(FourCC::GENRE_ID).next()?.as_int()?;
+            if !(1..=255).contains(&id) {
+                return None;
+            }
+            Genre::new(id as u8 - 1)?.description().map(Cow::Borrowed)
+        })
+    }
+
+    pub fn date(&self) -> Option<Timestamp> {
+        self.get_str(FourCC::DATE)
+            .and_then(|s| s.trim_end_matches('Z').parse().ok())
+    }
+
+    /// Track number and total track count (zero if unknown).
+    pub fn track(&self) -> Option<(u16, u16)> {
+        self.get_number_of(FourCC::TRACK)
+    }
+
+    /// Disc number and total disc count (zero if unknown).
+    pub fn disc(&self) -> Option<(u16, u16)> {
+        self.get_number_of(FourCC::DISC)
+    }
+
+    /// Cover images, the data kind tells the image format.
+    pub fn pictures(&self) -> impl Iterator<Item=&Data> {
+        self.get(FourCC::COVER)
+    }
+
+    /// Replaces the data of the item with `key` keeping its position, or appends a new item.
+    pub fn set(&mut self, key: ItemKey, data: Vec<Data>) {
+        if let Some(i) = self.items.iter().position(|v| v.key == key) {
+            self.items[i].data = data;
+            let mut i = i + 1;
+            while i < self.items.len() {
+                if self.items[i].key == key {
+                    self.items.remove(i);
+                } else {
+                    i += 1;
+                }
+            }
+        } else {
+            self.items.push(Item {
+                key,
+                data,
+            });
+        }
+    }
+
+    /// Appends `data` to the item with `key`, or appends a new item.
+    pub fn add(&mut self, key: ItemKey, data: Data) {
+        if let Some(item) = self.items.iter_mut().find(|v| v.key == key) {
+            item.data.push(data);
+        } else {
+            self.items.push(Item {
+                key,
+                data: vec![data],
+            });
+        }
+    }
+
+    /// Removes the items with `key` and returns their data.
+    pub fn remove(&mut self, key: &ItemKey) -> Vec<Data> {
+        let mut r = Vec::new();
+        let mut i = 0;
+        while i < self.items.len() {
+            if &self.items[i].key == key {
+                r.append(&mut self.items.remove(i).data);
+            } else {
+                i += 1;
+            }
+        }
+        r
+    }
+
+    pub fn set_text(&mut self, kind: FourCC, s: impl Into<String>) {
+        self.set(ItemKey::Atom(kind), vec![Data::text(s)]);
+    }
+
+    pub fn set_freeform(&mut self, mean: impl Into<String>, name: impl Into<String>,
+        s: impl Into<String>)
+    {
+        self.set(ItemKey::Freeform {
+            mean: mean.into(),
+            name: name.into(),
+        }, vec![Data::text(s)]);
+    }
+
+    /// Sets the track number and total track count (zero if unknown).
+    pub fn set_track(&mut self, number: u16, total: u16) {
+        let [n0, n1] = number.to_be_bytes();
+        let [t0, t1] = total.to_be_bytes();
+        self.set(ItemKey::Atom(FourCC::TRACK),
+            vec![Data::new(DataKind::IMPLICIT, vec![0, 0, n0, n1, t0, t1, 0, 0])]);
+    }
+
+    /// Sets the disc number and total disc count (zero if unknown).
+    pub fn set_disc(&mut self, number: u16, total: u16) {
+        let [n0, n1] = number.to_be_bytes();
+        let [t0, t1] = total.to_be_bytes();
+        self.set(ItemKey::Atom(FourCC::DISC),
+            vec![Data::new(DataKind::IMPLICIT, vec![0, 0, n0, n1, t0, t1])]);
+    }
+
+    /// Encodes the whole `ilst` atom.
+    pub(crate) fn encode(&self) -> Result<Vec<u8>> {
+        let mut r = Vec::new();
+        let start = begin_atom(&mut r, FourCC::ILST);
+        for item in &self.items {
+            item.encode(&mut r)?;
+        }
+        end_atom(&mut r, start)?;
+        Ok(r)
+    }
+
+    pub(crate) fn decode(atom: Atom) -> Result<Self> {
+        let items = atom.children()
+            .map(|v| v.and_then(Item::decode))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            items,
+        })
+    }
+
+    fn get_by_key<'a>(&'a self, mut f: impl 'a + FnMut(&ItemKey) -> bool)
+        -> impl 'a + Iterator<Item=&'a Data>
+    {
+        self.items.iter()
+            .filter(move |v| f(&v.key))
+            .flat_map(|v| v.data.iter())
+    }
+
+    fn get_str(&self, kind: FourCC) -> Option<Cow<'_, str>> {
+        self.get(kind).find_map(|v| v.as_str())
+    }
+
+    fn get_number_of(&self, kind: FourCC) -> Option<(u16, u16)> {
+        let v = &self.get(kind).next()?.value;
+        if v.len() < 4 {
+            return None;
+        }
+        let total = if v.len() >= 6 { BE::read_u16(&v[4..6]) } else { 0 };
+        Some((BE::read_u16(&v[2..4]), total))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mp4::Atoms;
+    use crate::mp4::test::{atom, atoms};
+
+    fn data(kind: u32, value: &[u8]) -> Vec<u8> {
+        let mut r = kind.to_be_bytes().to_vec();
+        r.extend_from_slice(&[0; 4]);
+        r.extend_from_slice(value);
+        atom(b"data", &r)
+    }
+
+    #[test]
+    fn decode() {
+        let buf = atoms(b"ilst", &[
+            atoms(b"\xa9nam", &[data(1, b"title")]),
+            atoms(b"\xa9ART", &[data(2, b"\0a\0r")]),
+            atoms(b"gnre", &[data(0, &[0, 18])]),
+            atoms(b"\xa9day", &[data(1, b"2004-05-03T07:00:00Z")]),
+            atoms(b"trkn", &[data(0, &[0, 0, 0, 3, 0, 12, 0, 0])]),
+            atoms(b"disk", &[data(0, &[0, 0, 0, 1, 0, 2])]),
+            atoms(b"covr", &[data(13, b"jpeg"), data(14, b"png")]),
+            atoms(b"----", &[
+                atom(b"mean", b"\0\0\0\0com.apple.iTunes"),
+                atom(b"name", b"\0\0\0\0MusicBrainz Track Id"),
+                data(1, b"id"),
+            ]),
+        ]);
+        let ilst = Ilst::decode(Atoms::new(&buf, 0).next().unwrap().unwrap()).unwrap();
+
+        assert_eq!(ilst.items().count(), 8);
+        assert_eq!(ilst.title(), Some("title".into()));
+        assert_eq!(ilst.artist(), Some("ar".into()));
+        assert_eq!(ilst.album(), None);
+        assert_eq!(ilst.genre(), Some("Rock".into()));
+        assert_eq!(ilst.date(), Some("2004-05-03T07:00:00".parse().unwrap()));
+        assert_eq!(ilst.track(), Some((3, 12)));
+        assert_eq!(ilst.disc(), Some((1, 2)));
+        assert_eq!(ilst.pictures().map(|v| v.kind).collect::<Vec<_>>(),
+            vec![DataKind::JPEG, DataKind::PNG]);
+        assert_eq!(ilst.freeform("com.apple.iTunes", "MusicBrainz Track Id")
+            .map(|v| v.as_str().unwrap()).collect::<Vec<_>>(), vec!["id"]);
+
+        let encoded = ilst.encode().unwrap();
+        assert_eq!(encoded, buf);
+        assert_eq!(Ilst::decode(Atoms::new(&encoded, 0).next().unwrap().unwrap()).unwrap(), ilst);
+    }
+
+    #[test]
+    fn edit() {
+        let mut ilst = Ilst::new();
+        ilst.set_text(FourCC::TITLE, "a");
+        ilst.set_text(FourCC::ARTIST, "b");
+        ilst.add(ItemKey::Atom(FourCC::TITLE), Data::text("c"));
+        ilst.set_freeform("com.apple.iTunes", "X", "x");
+        ilst.set_track(3, 0);
+        assert_eq!(ilst.get(FourCC::TITLE).map(|v| v.as_str().unwrap()).collect::<Vec<_>>(),
+            vec!["a", "c"]);
+        assert_eq!(ilst.track(), Some((3, 0)));
+
+        ilst.set_text(FourCC::TITLE, "d");
+        assert_eq!(ilst.title(), Some("d".into()));
+        assert_eq!(ilst.items().next().unwrap().key, ItemKey::Atom(FourCC::TITLE));
+        assert_eq!(ilst.remove(&ItemKey::Atom(FourCC::ARTIST)), vec![Data::text("b")]);
+        assert_eq!(ilst.artist(), None);
+        assert_eq!(ilst.items().count(), 3);
+    }
+}
@@ -3,16 +3,24 @@ mod vbr;
 use bit_field::BitField;
 use byteorder::{ReadBytesExt, BigEndian};
 use if_chain::if_chain;
+use std::borrow::Cow;
 use std::cmp;
 use std::fmt;
+use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::io::{self, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
 use std::time::Duration;
 
+use crate::ape::Ape;
+use crate::id3::frame::FrameId;
 use crate::id3::v1::Id3v1;
-use crate::id3::v2::Id3v2;
+use crate::id3::v2::{EncodeOptions, Id3v2};
+use crate::lyrics3::Lyrics3;
 use crate::error::*;
 use crate::util::*;
+use crate::util::file::{copy_range, rewrite_file};
 pub use vbr::*;
 use crate::tags::TagsRef;
 
@@ -248,12 +256,41 @@ impl Header {
     }
 }
 
+/// What to do with the trailing ID3v1 tag when saving.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Id3v1Action {
+    #[default]
+    Keep,
+    Strip,
+    /// Replaces the tag with the one set by `Mpeg::id3v1_mut` or strips it if there's none.
+    Update,
+}
+
+/// Options for `Mpeg::save`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SaveOptions {
+    /// ID3v2 encoding options. The padding len is used only when the file has to be rewritten,
+    /// otherwise the tag is written in place of the existing one taking all its space.
+    pub id3v2: EncodeOptions,
+    pub id3v1: Id3v1Action,
+}
+
 #[derive(Debug)]
 pub struct Mpeg {
     header: Header,
     vbr: Option<Vbr>,
     id3v1: Option<Id3v1>,
     id3v2: Option<Id3v2>,
+    ape: Option<Ape>,
+    lyrics3: Option<Lyrics3>,
+    /// Position of the leading ID3v2 tag including its padding.
+    id3v2_range: Option<Range<u64>>,
+    /// Position of the APE tag, if there's none it's the empty range before the Lyrics3 and
+    /// ID3v1 tags where the tag would be placed.
+    ape_range: Range<u64>,
+    /// Positions of the ID3v2 tags that follow the leading one (found via `SEEK` frame or
+    /// appended to the stream), they're merged into `id3v2`.
+    id3v2_next_ranges: Vec<Range<u64>>,
     duration: Duration,
     bits_per_sec: u32,
 }
@@ -264,6 +301,7 @@ impl Mpeg {
         rd.seek(SeekFrom::Start(0))?;
         let mut id3v2_done = false;
         let mut id3v2 = None;
+        let mut id3v2_range = None;
         let mut pos = 0;
         let pos_limit = cmp::min(file_len, 1024 * 1024);
         let (header, header_pos) = loop {
@@ -274,6 +312,7 @@ impl Mpeg {
             if !id3v2_done {
                 match Id3v2::read(&mut rd, Some(file_len - pos)) {
                     Ok((tag, tag_len_bytes)) => {
+                        id3v2_range = Some(pos..pos + tag_len_bytes as u64);
                         pos += tag_len_bytes as u64;
                         id3v2 = Some(tag);
                         id3v2_done = true;
@@ -307,6 +346,20 @@ impl Mpeg {
 
         let id3v1 = Id3v1::read(&mut rd).into_opt()?;
 
+        let end = file_len - id3v1.as_ref().map(|v| v.len()).unwrap_or(0) as u64;
+        let (lyrics3, end) = match Lyrics3::read_at_end(&mut rd, end).into_opt()? {
+            Some((tag, start)) => (Some(tag), start),
+            None => (None, end),
+        };
+        let (ape, ape_range) = match Ape::read_at_end(&mut rd, end).into_opt()? {
+            Some((ape, start)) => (Some(ape), start..end),
+            None => (None, end..end),
+        };
+        let end = ape_range.start;
+        let id3v2_end = id3v2_range.as_ref().map(|r| r.end).unwrap_or(0);
+        let (id3v2, stream_end, id3v2_next_ranges) = Id3v2::read_chained(&mut rd,
+            id3v2.map(|v| (v, id3v2_end)), end)?;
+
         let vbr = if header.layer == Layer::L3 {
             let pos = header_pos + Vbr::offset(&header);
             rd.seek(SeekFrom::Start(pos))?;
@@ -316,13 +369,18 @@ impl Mpeg {
         };
 
         let (duration, bits_per_sec) = Self::compute_duration_and_bitrate(
-            &header, header_pos, file_len, vbr.as_ref(), id3v1.as_ref());
+            &header, header_pos, stream_end, vbr.as_ref());
 
         Ok(Self {
             header,
             vbr,
             id3v1,
             id3v2,
+            ape,
+            lyrics3,
+            id3v2_range,
+            ape_range,
+            id3v2_next_ranges,
             duration,
             bits_per_sec,
         })
@@ -336,14 +394,129 @@ impl Mpeg {
         self.vbr.as_ref()
     }
 
+    pub fn ape(&self) -> Option<&Ape> {
+        self.ape.as_ref()
+    }
+
+    pub fn lyrics3(&self) -> Option<&Lyrics3> {
+        self.lyrics3.as_ref()
+    }
+
     pub fn tags(&self) -> TagsRef {
         TagsRef {
             id3v1: self.id3v1.as_ref(),
             id3v2: self.id3v2.as_ref(),
+            ape: self.ape.as_ref(),
             .. Default::default()
         }
     }
 
+    pub fn id3v1_mut(&mut self) -> &mut Option<Id3v1> {
+        &mut self.id3v1
+    }
+
+    pub fn id3v2_mut(&mut self) -> &mut Option<Id3v2> {
+        &mut self.id3v2
+    }
+
+    pub fn ape_mut(&mut self) -> &mut Option<Ape> {
+        &mut self.ape
+    }
+
+    /// Writes the ID3v2 tag to the file at `path`. The leading tag is rewritten in place if
+    /// the new tag fits in the existing tag and its padding, otherwise the whole file is
+    /// rewritten. The ID3v2 tags following the leading one are merged into `id3v2` on read so
+    /// they're removed and the `SEEK` frame isn't written. The APE tag is replaced with `ape`
+    /// or stripped if there's none, it's placed before the Lyrics3 and ID3v1 tags.
+    pub fn save(&self, path: impl AsRef<Path>, options: &SaveOptions) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        // The file could change since it was read so the tags are located anew.
+        let cur = Self::read(&mut file)?;
+        let file_len = file.seek(SeekFrom::End(0))?;
+        let id3v2_range = cur.id3v2_range.clone().unwrap_or(0..0);
+        let end = match options.id3v1 {
+            Id3v1Action::Keep => file_len,
+            Id3v1Action::Strip | Id3v1Action::Update =>
+                file_len - cur.id3v1.as_ref().map(|v| v.len()).unwrap_or(0) as u64,
+        };
+        let id3v1 = match (options.id3v1, &self.id3v1) {
+            (Id3v1Action::Update, Some(v)) => v.encode(),
+            _ => Vec::new(),
+        };
+        let id3v2 = self.id3v2.as_ref().map(|tag| if tag.frames().first(FrameId::SEEK).is_some() {
+            let mut tag = tag.clone();
+            tag.frames_mut().remove(FrameId::SEEK);
+            Cow::Owned(tag)
+        } else {
+            Cow::Borrowed(tag)
+        });
+
+        let ape = if self.ape != cur.ape {
+            Some(match &self.ape {
+                Some(tag) => tag.encode()?,
+                None => Vec::new(),
+            })
+        } else {
+            None
+        };
+
+        let available = id3v2_range.end - id3v2_range.start;
+        let in_place = match &id3v2 {
+            _ if !cur.id3v2_next_ranges.is_empty() || ape.is_some() => None,
+            Some(tag) => {
+                let len = tag.encode(&EncodeOptions {
+                    padding_len: 0,
+                    ..options.id3v2.clone()
+                })?.len() as u64;
+                if len <= available {
+                    Some(tag.encode(&EncodeOptions {
+                        padding_len: (available - len) as u32,
+                        ..options.id3v2.clone()
+                    })?)
+                } else {
+                    None
+                }
+            }
+            None if available == 0 => Some(Vec::new()),
+            None => None,
+        };
+
+        if let Some(bytes) = in_place {
+            debug_assert_eq!(bytes.len() as u64, available);
+            file.seek(SeekFrom::Start(id3v2_range.start))?;
+            file.write_all(&bytes)?;
+            file.set_len(end)?;
+            file.seek(SeekFrom::Start(end))?;
+            file.write_all(&id3v1)?;
+            file.sync_all()
+        } else {
+            let tag = match &id3v2 {
+                Some(tag) => tag.encode(&options.id3v2)?,
+                None => Vec::new(),
+            };
+            rewrite_file(path, &mut file, |src, dst| {
+                copy_range(src, dst, 0..id3v2_range.start)?;
+                dst.write_all(&tag)?;
+                let mut pos = id3v2_range.end;
+                for range in &cur.id3v2_next_ranges {
+                    copy_range(src, dst, pos..range.start)?;
+                    pos = range.end;
+                }
+                match &ape {
+                    Some(ape) => {
+                        copy_range(src, dst, pos..cur.ape_range.start)?;
+                        dst.write_all(ape)?;
+                        copy_range(src, dst, cur.ape_range.end..end)?;
+                    }
+                    None => copy_range(src, dst, pos..end)?,
+                }
+                dst.write_all(&id3v1)
+            })
+        }
+    }
+
     pub fn duration(&self) -> Duration {
         self.duration
     }
@@ -352,8 +525,10 @@ impl Mpeg {
         self.bits_per_sec
     }
 
-    fn compute_duration_and_bitrate(header: &Header, header_pos: u64, file_len: u64,
-        vbr: Option<&Vbr>, id3v1: Option<&Id3v1>) -> (Duration, u32)
+    /// `stream_end` is the position where the MPEG stream ends, i.e. where the trailing tags
+    /// start.
+    fn compute_duration_and_bitrate(header: &Header, header_pos: u64, stream_end: u64,
+        vbr: Option<&Vbr>) -> (Duration, u32)
     {
         if_chain! {
             if let Some(vbr) = &vbr;
@@ -385,9 +560,213 @@ impl Mpeg {
         let bits_per_sec = header.kbits_per_sec as u32 * 1000;
 
         // TODO calculate stream length as difference between the first frame start and last frame end.
-        let stream_len_bytes = file_len - header_pos - id3v1.map(|v| v.len()).unwrap_or(0) as u64;
+        let stream_len_bytes = stream_end.saturating_sub(header_pos);
 
-        let duration_millis = (stream_len_bytes * 8 + bits_per_sec as u64 - 1) / bits_per_sec as u64;
+        let duration_millis = (stream_len_bytes * 8 * 1000).div_ceil(bits_per_sec as u64);
         (Duration::from_millis(duration_millis), bits_per_sec)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use crate::id3::frame::FrameId;
+    use crate::util::file::test::TempFile;
+
+    // MPEG-1 Layer III, 128 kb/s, 44.1 kHz.
+    const FRAME_LEN: usize = 417;
+
+    fn stream() -> Vec<u8> {
+        let mut r = Vec::new();
+        for _ in 0..3 {
+            let i = r.len();
+            r.resize(i + FRAME_LEN, 0);
+            r[i..i + 4].copy_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+        }
+        r
+    }
+
+    fn write_file(name: &str, title: &str, padding_len: u32) -> (TempFile, Vec<u8>) {
+        let path = TempFile::new(&format!("{}.mp3", name));
+        let mut tag = Id3v2::new(crate::id3::Version::V2_4);
+        tag.frames_mut().set_text(FrameId::TITLE, vec![title]);
+        let mut bytes = tag.encode(&EncodeOptions {
+            padding_len,
+            ..Default::default()
+        }).unwrap();
+        bytes.extend_from_slice(&stream());
+        let mut id3v1 = vec![0; 128];
+        id3v1[..6].copy_from_slice(b"TAGabc");
+        bytes.extend_from_slice(&id3v1);
+        fs::write(&path, &bytes).unwrap();
+        (path, bytes)
+    }
+
+    fn read(path: &Path) -> Mpeg {
+        Mpeg::read(fs::File::open(path).unwrap()).unwrap()
+    }
+
+    fn set_title(mpeg: &mut Mpeg, title: &str) {
+        mpeg.id3v2_mut().as_mut().unwrap().frames_mut().set_text(FrameId::TITLE, vec![title]);
+    }
+
+    #[test]
+    fn cbr_duration() {
+        let mpeg = Mpeg::read(io::Cursor::new(stream())).unwrap();
+        assert_eq!(mpeg.duration(), Duration::from_millis(79));
+        assert_eq!(mpeg.bits_per_sec(), 128000);
+    }
+
+    #[test]
+    fn read_trailing_tags() {
+        let mut bytes = stream();
+        bytes.extend_from_slice(&crate::ape::test::tag(&[
+            crate::ape::test::item("Title", b"ape title", 0)]));
+        bytes.extend_from_slice(&crate::lyrics3::test::tag_v2(&[
+            crate::lyrics3::test::field("ETT", "lyrics3 title")]));
+        let mut id3v1 = vec![0; 128];
+        id3v1[..6].copy_from_slice(b"TAGabc");
+        bytes.extend_from_slice(&id3v1);
+
+        let mpeg = Mpeg::read(io::Cursor::new(bytes)).unwrap();
+        assert_eq!(mpeg.duration(), Duration::from_millis(79));
+        assert_eq!(mpeg.ape().unwrap().title(), Some("ape title"));
+        assert_eq!(mpeg.lyrics3().unwrap().title.as_deref(), Some("lyrics3 title"));
+        assert_eq!(mpeg.tags().title().unwrap(), "ape title");
+    }
+
+    #[test]
+    fn save_in_place() {
+        let (path, bytes) = write_file("save_in_place", "a", 100);
+        let mut mpeg = read(&path);
+        set_title(&mut mpeg, "bcd");
+        mpeg.save(&path, &Default::default()).unwrap();
+
+        let actual = fs::read(&path).unwrap();
+        assert_eq!(actual.len(), bytes.len());
+        assert_eq!(actual[actual.len() - 128 - 3 * FRAME_LEN..], bytes[bytes.len() - 128 - 3 * FRAME_LEN..]);
+        let mpeg = read(&path);
+        assert_eq!(mpeg.tags().id3v2.unwrap().title(), Some("bcd"));
+        assert_eq!(mpeg.id3v2_range, Some(0..bytes.len() as u64 - 128 - 3 * FRAME_LEN as u64));
+    }
+
+    #[test]
+    fn save_keeps_version() {
+        let path = TempFile::new("save_keeps_version.mp3");
+        let mut tag = Id3v2::new(crate::id3::Version::V2_3);
+        tag.frames_mut().set_text(FrameId::V23_YEAR, vec!["2001"]);
+        tag.frames_mut().set_text(FrameId::V23_DATE, vec!["0102"]);
+        let mut bytes = tag.encode(&Default::default()).unwrap();
+        bytes.extend_from_slice(&stream());
+        fs::write(&path, &bytes).unwrap();
+
+        let mut mpeg = read(&path);
+        set_title(&mut mpeg, "t");
+        mpeg.save(&path, &Default::default()).unwrap();
+
+        let mpeg = read(&path);
+        let tag = mpeg.tags().id3v2.unwrap();
+        assert_eq!(tag.header().version, crate::id3::Version::V2_3);
+        assert_eq!(tag.title(), Some("t"));
+        assert_eq!(tag.release_date().unwrap().to_string(), "2001-02-01");
+    }
+
+    #[test]
+    fn save_drops_appended_tag() {
+        let (path, mut bytes) = write_file("save_drops_appended_tag", "a", 100);
+        let frames = b"TIT2\0\0\0\x02\0\0\0b";
+        let mut appended = vec![b'I', b'D', b'3', 4, 0, 0b0001_0000, 0, 0, 0, frames.len() as u8];
+        appended.extend_from_slice(frames);
+        appended.extend_from_slice(&[b'3', b'D', b'I', 4, 0, 0b0001_0000, 0, 0, 0,
+            frames.len() as u8]);
+        let id3v1 = bytes.split_off(bytes.len() - 128);
+        bytes.extend_from_slice(&appended);
+        bytes.extend_from_slice(&id3v1);
+        fs::write(&path, &bytes).unwrap();
+
+        let mut mpeg = read(&path);
+        assert_eq!(mpeg.tags().id3v2.unwrap().title(), Some("b"));
+        set_title(&mut mpeg, "c");
+        mpeg.save(&path, &Default::default()).unwrap();
+
+        let actual = fs::read(&path).unwrap();
+        assert_eq!(actual[actual.len() - 128 - 3 * FRAME_LEN..actual.len() - 128], stream()[..]);
+        assert_eq!(actual[actual.len() - 128..], id3v1[..]);
+        let mpeg = read(&path);
+        assert_eq!(mpeg.tags().id3v2.unwrap().title(), Some("c"));
+        assert!(mpeg.id3v2_next_ranges.is_empty());
+    }
+
+    #[test]
+    fn save_rewrite() {
+        let (path, bytes) = write_file("save_rewrite", "a", 0);
+        let mut mpeg = read(&path);
+        let title = "b".repeat(100);
+        set_title(&mut mpeg, &title);
+        mpeg.save(&path, &SaveOptions {
+            id3v2: EncodeOptions {
+                padding_len: 10,
+                ..Default::default()
+            },
+            id3v1: Id3v1Action::Strip,
+        }).unwrap();
+
+        let actual = fs::read(&path).unwrap();
+        assert_eq!(actual.len(), bytes.len() + 99 + 10 - 128);
+        assert_eq!(actual[actual.len() - 3 * FRAME_LEN..], stream()[..]);
+        let mpeg = read(&path);
+        assert_eq!(mpeg.tags().id3v2.unwrap().title(), Some(&*title));
+        assert!(mpeg.tags().id3v1.is_none());
+
+        // Remove the tag.
+        let mut mpeg = read(&path);
+        *mpeg.id3v2_mut() = None;
+        mpeg.save(&path, &Default::default()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), stream());
+
+        let mut mpeg = read(&path);
+        *mpeg.id3v1_mut() = Some(Id3v1 {
+            title: "t".into(),
+            ..Default::default()
+        });
+        mpeg.save(&path, &SaveOptions {
+            id3v1: Id3v1Action::Update,
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(fs::read(&path).unwrap().len(), 3 * FRAME_LEN + 128);
+        assert_eq!(read(&path).tags().id3v1.unwrap().title, "t");
+    }
+
+    #[test]
+    fn save_ape() {
+        let (path, mut bytes) = write_file("save_ape", "a", 100);
+        let id3v1 = bytes.split_off(bytes.len() - 128);
+        let lyrics3 = crate::lyrics3::test::tag_v2(&[
+            crate::lyrics3::test::field("ETT", "lyrics3 title")]);
+        bytes.extend_from_slice(&lyrics3);
+        bytes.extend_from_slice(&id3v1);
+        fs::write(&path, &bytes).unwrap();
+
+        let mut mpeg = read(&path);
+        let mut ape = Ape::new();
+        ape.set_text(Ape::TITLE, "ape title");
+        *mpeg.ape_mut() = Some(ape.clone());
+        mpeg.save(&path, &Default::default()).unwrap();
+
+        let actual = fs::read(&path).unwrap();
+        assert_eq!(actual[actual.len() - 128 - lyrics3.len()..],
+            bytes[bytes.len() - 128 - lyrics3.len()..]);
+        let mut mpeg = read(&path);
+        assert_eq!(mpeg.ape(), Some(&ape));
+        assert_eq!(mpeg.lyrics3().unwrap().title.as_deref(), Some("lyrics3 title"));
+        assert_eq!(mpeg.tags().id3v2.unwrap().title(), Some("a"));
+
+        *mpeg.ape_mut() = None;
+        mpeg.save(&path, &Default::default()).unwrap();
+        let tail_len = 3 * FRAME_LEN + lyrics3.len() + 128;
+        let actual = fs::read(&path).unwrap();
+        assert_eq!(actual[actual.len() - tail_len..], bytes[bytes.len() - tail_len..]);
+        assert!(read(&path).ape().is_none());
+    }
+}
@@ -0,0 +1,294 @@
+pub mod flac;
+pub mod opus;
+pub mod speex;
+pub mod vorbis;
+
+use bit_field::BitField;
+use byteorder::{ByteOrder, LE};
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use crate::error::*;
+use crate::util::*;
+use crate::vcomment::Vcomment;
+
+pub use flac::Flac;
+pub use opus::Opus;
+pub use speex::Speex;
+pub use vorbis::Vorbis;
+
+const HEADER_LEN: usize = 27;
+const MAX_PAGE_LEN: usize = HEADER_LEN + 255 + 255 * 255;
+
+// Guards against huge allocations on corrupted streams.
+const MAX_PACKET_LEN: usize = 64 * 1024 * 1024;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut r = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut v = (i as u32) << 24;
+        let mut j = 0;
+        while j < 8 {
+            v = if v & 0x8000_0000 != 0 {
+                v << 1 ^ 0x04c1_1db7
+            } else {
+                v << 1
+            };
+            j += 1;
+        }
+        r[i] = v;
+        i += 1;
+    }
+    r
+}
+
+/// Updates Ogg CRC-32 (polynomial 0x04c11db7 with zero initial value and no reflection).
+fn crc(crc: u32, buf: &[u8]) -> u32 {
+    buf.iter().fold(crc, |crc, &b| crc << 8 ^ CRC_TABLE[((crc >> 24) as u8 ^ b) as usize])
+}
+
+struct Page {
+    continued: bool,
+    first: bool,
+    last: bool,
+    /// `None` if no packet finishes on this page.
+    granule_pos: Option<u64>,
+    serial: u32,
+    lacing: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Page {
+    fn read(rd: &mut impl Read) -> io::Result<Self> {
+        let mut hdr = [0; HEADER_LEN];
+        rd.read_exact(&mut hdr)?;
+        if &hdr[..4] != b"OggS" {
+            return Err(Error("bad Ogg page magic").into_invalid_data_err());
+        }
+        if hdr[4] != 0 {
+            return Err(Error("unsupported Ogg page version").into_invalid_data_err());
+        }
+        let flags = hdr[5];
+        let granule_pos = Some(LE::read_u64(&hdr[6..14])).filter(|&v| v != u64::MAX);
+        let serial = LE::read_u32(&hdr[14..18]);
+        let expected_crc = LE::read_u32(&hdr[22..26]);
+
+        let mut lacing = vec![0; hdr[26] as usize];
+        rd.read_exact(&mut lacing)?;
+        let mut data = vec![0; lacing.iter().map(|&v| v as usize).sum()];
+        rd.read_exact(&mut data)?;
+
+        hdr[22..26].copy_from_slice(&[0; 4]);
+        if crc(crc(crc(0, &hdr), &lacing), &data) != expected_crc {
+            return Err(Error("Ogg page CRC mismatch").into_invalid_data_err());
+        }
+
+        Ok(Self {
+            continued: flags.get_bit(0),
+            first: flags.get_bit(1),
+            last: flags.get_bit(2),
+            granule_pos,
+            serial,
+            lacing,
+            data,
+        })
+    }
+
+    fn len(&self) -> usize {
+        HEADER_LEN + self.lacing.len() + self.data.len()
+    }
+}
+
+/// Reads packets of the logical stream that starts the file, pages of other streams are skipped.
+pub(crate) struct PacketReader<R> {
+    rd: R,
+    pos: u64,
+    serial: Option<u32>,
+    page: Option<Page>,
+    lacing_idx: usize,
+    data_pos: usize,
+}
+
+impl<R: Read> PacketReader<R> {
+    pub fn new(rd: R) -> Self {
+        Self {
+            rd,
+            pos: 0,
+            serial: None,
+            page: None,
+            lacing_idx: 0,
+            data_pos: 0,
+        }
+    }
+
+    /// Serial number of the stream, known after the first packet is read.
+    pub fn serial(&self) -> Option<u32> {
+        self.serial
+    }
+
+    /// Position right after the last read page.
+    pub fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn read_packet(&mut self) -> io::Result<Vec<u8>> {
+        let mut r = Vec::new();
+        loop {
+            if self.page.as_ref().map(|p| self.lacing_idx == p.lacing.len()).unwrap_or(true) {
+                self.next_page(!r.is_empty())?;
+                continue;
+            }
+            let page = self.page.as_ref().unwrap();
+            let len = page.lacing[self.lacing_idx] as usize;
+            self.lacing_idx += 1;
+            r.extend_from_slice(&page.data[self.data_pos..self.data_pos + len]);
+            self.data_pos += len;
+            if len < 255 {
+                return Ok(r);
+            }
+            if r.len() > MAX_PACKET_LEN {
+                return Err(Error("Ogg packet is too large").into_invalid_data_err());
+            }
+        }
+    }
+
+    fn next_page(&mut self, continued: bool) -> io::Result<()> {
+        loop {
+            let page = Page::read(&mut self.rd)?;
+            self.pos += page.len() as u64;
+            if let Some(serial) = self.serial {
+                if page.serial != serial {
+                    continue;
+                }
+                if page.continued != continued {
+                    return Err(Error("Ogg packet continuation mismatch").into_invalid_data_err());
+                }
+            } else {
+                if !page.first {
+                    return Err(Error("Ogg stream doesn't start with BOS page").into_invalid_data_err());
+                }
+                self.serial = Some(page.serial);
+            }
+            self.page = Some(page);
+            self.lacing_idx = 0;
+            self.data_pos = 0;
+            return Ok(());
+        }
+    }
+}
+
+/// Returns the granule position of the last page of the stream `serial` that has it.
+pub(crate) fn read_last_granule_pos(rd: &mut (impl Read + Seek), serial: u32, file_len: u64)
+    -> io::Result<Option<u64>>
+{
+    const CHUNK_LEN: u64 = 64 * 1024;
+    const MAX_SCAN_LEN: u64 = 4 * 1024 * 1024;
+
+    let mut end = file_len;
+    while end > 0 && file_len - end < MAX_SCAN_LEN {
+        let start = end.saturating_sub(CHUNK_LEN);
+        // Include the bytes past the chunk end for the pages that start in the chunk.
+        let buf_end = cmp::min(file_len, end + MAX_PAGE_LEN as u64);
+        rd.seek(SeekFrom::Start(start))?;
+        let buf = read_vec_limited(&mut Limited::new(&mut *rd, buf_end - start),
+            (buf_end - start) as usize, "file truncated")?;
+
+        for i in (0..(end - start) as usize).rev() {
+            if &buf[i..cmp::min(i + 4, buf.len())] != b"OggS" {
+                continue;
+            }
+            match Page::read(&mut &buf[i..]).into_opt()? {
+                Some(page) if page.serial == serial && page.granule_pos.is_some() =>
+                    return Ok(page.granule_pos),
+                _ => {}
+            }
+        }
+
+        end = start;
+    }
+    Ok(None)
+}
+
+/// Decodes the comment header packet that starts with `magic`.
+pub(crate) fn decode_vcomment(packet: &[u8], magic: &[u8], framing: bool) -> io::Result<Vcomment> {
+    if !packet.starts_with(magic) {
+        return Err(Error("bad comment header").into_invalid_data_err());
+    }
+    let buf = &packet[magic.len()..];
+    Vcomment::read_limited(&mut Limited::new(buf, buf.len() as u64), framing)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// Builds a page containing the `packets`, the last packet continues on the next page
+    /// if `incomplete` is set (its len must be a multiple of 255 then).
+    pub(crate) fn page(serial: u32, seq: u32, flags: u8, granule_pos: u64, packets: &[&[u8]],
+        incomplete: bool) -> Vec<u8>
+    {
+        let mut lacing = Vec::new();
+        let mut data = Vec::new();
+        for (i, p) in packets.iter().enumerate() {
+            let mut len = p.len();
+            while len >= 255 {
+                lacing.push(255);
+                len -= 255;
+            }
+            if !(incomplete && i == packets.len() - 1) {
+                lacing.push(len as u8);
+            }
+            data.extend_from_slice(p);
+        }
+        let mut r = b"OggS".to_vec();
+        r.push(0);
+        r.push(flags);
+        r.extend_from_slice(&granule_pos.to_le_bytes());
+        r.extend_from_slice(&serial.to_le_bytes());
+        r.extend_from_slice(&seq.to_le_bytes());
+        r.extend_from_slice(&[0; 4]);
+        r.push(lacing.len() as u8);
+        r.extend_from_slice(&lacing);
+        r.extend_from_slice(&data);
+        let crc = crc(0, &r);
+        r[22..26].copy_from_slice(&crc.to_le_bytes());
+        r
+    }
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc(0, b"123456789"), 0x89a1_897f);
+    }
+
+    #[test]
+    fn read_packets() {
+        let long = vec![7; 600];
+        let mut file = page(1, 0, 0b010, 0, &[b"a"], false);
+        file.extend_from_slice(&page(2, 0, 0b010, 0, &[b"other"], false));
+        file.extend_from_slice(&page(1, 1, 0, u64::MAX, &[b"b", &long[..255]], true));
+        file.extend_from_slice(&page(1, 2, 0b101, 10, &[&long[255..], b""], false));
+
+        let mut rd = PacketReader::new(&file[..]);
+        assert_eq!(rd.read_packet().unwrap(), b"a");
+        assert_eq!(rd.serial(), Some(1));
+        assert_eq!(rd.read_packet().unwrap(), b"b");
+        assert_eq!(rd.read_packet().unwrap(), long);
+        assert_eq!(rd.read_packet().unwrap(), b"");
+        assert_eq!(rd.pos(), file.len() as u64);
+        assert_eq!(rd.read_packet().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let len = file.len() as u64;
+        assert_eq!(read_last_granule_pos(&mut io::Cursor::new(&file), 1, len).unwrap(), Some(10));
+        assert_eq!(read_last_granule_pos(&mut io::Cursor::new(&file), 2, len).unwrap(), Some(0));
+
+        let mut corrupted = file.clone();
+        corrupted[28] ^= 1;
+        assert_eq!(PacketReader::new(&corrupted[..]).read_packet().unwrap_err().kind(),
+            io::ErrorKind::InvalidData);
+    }
+}
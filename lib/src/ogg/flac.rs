@@ -0,0 +1,154 @@
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use crate::error::*;
+use crate::flac::{BlockHeader, BlockKind, Picture, StreamInfo};
+use crate::tags::TagsRef;
+use crate::util::*;
+use crate::vcomment::Vcomment;
+use super::*;
+
+/// FLAC stream encapsulated in Ogg.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Flac {
+    stream_info: StreamInfo,
+    pictures: Vec<Picture>,
+    vcomment: Option<Vcomment>,
+    duration: Option<Duration>,
+    bits_per_sec: Option<u32>,
+}
+
+impl Flac {
+    // Mapping header (9), "fLaC" and STREAMINFO block with its header.
+    const FIRST_PACKET_LEN: usize = 9 + 4 + 4 + StreamInfo::LEN;
+
+    pub fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
+        let file_len = rd.seek(SeekFrom::End(0))?;
+        rd.seek(SeekFrom::Start(0))?;
+
+        let mut packets = PacketReader::new(&mut rd);
+        let (stream_info, mut last) = Self::decode_first_packet(&packets.read_packet()?)
+            .map_err(|e| e.into_invalid_data_err())?;
+
+        let mut pictures = Vec::new();
+        let mut vcomment = None;
+        // Each of the rest header packets contains one metadata block.
+        while !last {
+            let packet = packets.read_packet()?;
+            let hdr = BlockHeader::read(&packet[..])?;
+            let data = &packet[4..];
+            if data.len() < hdr.len as usize {
+                return Err(Error("Ogg FLAC metadata block is truncated").into_invalid_data_err());
+            }
+            let data = &data[..hdr.len as usize];
+            match hdr.kind {
+                BlockKind::PICTURE => {
+                    pictures.push(Picture::read(&mut Limited::new(data, data.len() as u64))?);
+                }
+                BlockKind::VORBIS_COMMENT if vcomment.is_none() => {
+                    vcomment = Some(decode_vcomment(data, b"", false)?);
+                }
+                _ => {}
+            }
+            last = hdr.last;
+        }
+        let audio_start = packets.pos();
+        let serial = packets.serial().unwrap();
+
+        let duration = match stream_info.len_samples {
+            Some(v) => Some(v),
+            None => read_last_granule_pos(&mut rd, serial, file_len)?,
+        }.map(|v| duration(v, stream_info.samples_per_sec));
+        let bits_per_sec = duration.and_then(|d| bits_per_sec(file_len - audio_start, d));
+
+        Ok(Self {
+            stream_info,
+            pictures,
+            vcomment,
+            duration,
+            bits_per_sec,
+        })
+    }
+
+    pub fn stream_info(&self) -> &StreamInfo {
+        &self.stream_info
+    }
+
+    pub fn pictures(&self) -> impl Iterator<Item=&Picture> {
+        self.pictures.iter()
+    }
+
+    pub fn vcomment(&self) -> Option<&Vcomment> {
+        self.vcomment.as_ref()
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Average bitrate.
+    pub fn bits_per_sec(&self) -> Option<u32> {
+        self.bits_per_sec
+    }
+
+    pub fn tags(&self) -> TagsRef<'_> {
+        TagsRef {
+            vcomment: self.vcomment.as_ref(),
+            .. Default::default()
+        }
+    }
+
+    /// Returns the stream info and whether it's the last metadata block.
+    fn decode_first_packet(buf: &[u8]) -> Result<(StreamInfo, bool)> {
+        if buf.len() < Self::FIRST_PACKET_LEN || &buf[..5] != b"\x7fFLAC" || &buf[9..13] != b"fLaC" {
+            return Err(Error("bad Ogg FLAC header packet"));
+        }
+        if buf[5] != 1 {
+            return Err(Error("unsupported Ogg FLAC mapping version"));
+        }
+        // The header packet count at 7..9 may be zero (unknown), the last block flag is used instead.
+        let hdr = BlockHeader::read(&buf[13..17])
+            .map_err(|_| Error("bad Ogg FLAC header packet"))?;
+        if hdr.kind != BlockKind::STREAM_INFO || hdr.len != StreamInfo::LEN as u32 {
+            return Err(Error("Ogg FLAC stream doesn't start with METADATA_BLOCK_STREAMINFO"));
+        }
+        Ok((StreamInfo::decode(&buf[17..])?, hdr.last))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use crate::flac::test::stream_info_block;
+    use crate::ogg::test::page;
+
+    #[test]
+    fn read() {
+        let mut first = b"\x7fFLAC\x01\x00\x00\x01fLaC".to_vec();
+        first.extend_from_slice(&stream_info_block(false));
+
+        let mut vcomment = Vcomment::new("v");
+        vcomment.set("TITLE", "t").unwrap();
+        let mut block = Vec::new();
+        vcomment.write(&mut block, false).unwrap();
+        let mut comment = vec![0x84, 0, 0, block.len() as u8];
+        comment.extend_from_slice(&block);
+
+        let mut file = page(1, 0, 0b010, 0, &[&first], false);
+        file.extend_from_slice(&page(1, 1, 0, 0, &[&comment], false));
+        let audio = page(1, 2, 0b100, 44100, &[&[0; 1000]], false);
+        file.extend_from_slice(&audio);
+
+        let flac = Flac::read(Cursor::new(&file)).unwrap();
+        assert_eq!(flac.stream_info().samples_per_sec, 44100);
+        assert_eq!(flac.stream_info().channel_count, 2);
+        assert_eq!(flac.stream_info().bits_per_sample, 16);
+        assert_eq!(flac.duration(), Some(Duration::from_secs(1)));
+        assert_eq!(flac.bits_per_sec(), Some(audio.len() as u32 * 8));
+        assert_eq!(flac.tags().title(), Some("t".into()));
+        assert_eq!(flac.pictures().count(), 0);
+    }
+}
@@ -0,0 +1,215 @@
+use byteorder::{ByteOrder, LE};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use crate::error::*;
+use crate::tags::TagsRef;
+use crate::vcomment::Vcomment;
+use super::*;
+
+/// Opus always decodes at 48 kHz.
+pub const SAMPLES_PER_SEC: u32 = 48000;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChannelMapping {
+    /// Channel mapping family, 0 means mono or stereo and has no mapping table.
+    pub family: u8,
+    pub stream_count: u8,
+    pub coupled_count: u8,
+    pub mapping: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpusHead {
+    pub version: u8,
+    pub channel_count: u8,
+
+    /// Number of samples (at 48 kHz) to discard from the decoder output when starting playback.
+    pub pre_skip: u16,
+
+    /// Sample rate of the original input, informational only. `None` if unspecified.
+    pub input_samples_per_sec: Option<u32>,
+
+    /// Gain to apply to the decoder output in dB as Q7.8 fixed point number.
+    pub output_gain: i16,
+
+    pub channel_mapping: ChannelMapping,
+}
+
+impl OpusHead {
+    const MIN_LEN: usize = 19;
+
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::MIN_LEN || &buf[..8] != b"OpusHead" {
+            return Err(Error("bad OpusHead packet"));
+        }
+        let version = buf[8];
+        // Only the minor version may be changed in a compatible way.
+        if version >> 4 != 0 {
+            return Err(Error("unsupported Opus version"));
+        }
+        let channel_count = buf[9];
+        if channel_count == 0 {
+            return Err(Error("bad OpusHead channel count"));
+        }
+        let pre_skip = LE::read_u16(&buf[10..12]);
+        let input_samples_per_sec = Some(LE::read_u32(&buf[12..16])).filter(|&v| v != 0);
+        let output_gain = LE::read_i16(&buf[16..18]);
+        let family = buf[18];
+        let channel_mapping = if family == 0 {
+            ChannelMapping {
+                family,
+                stream_count: 1,
+                coupled_count: channel_count - 1,
+                mapping: Vec::new(),
+            }
+        } else {
+            let end = Self::MIN_LEN + 2 + channel_count as usize;
+            if buf.len() < end {
+                return Err(Error("OpusHead channel mapping is truncated"));
+            }
+            ChannelMapping {
+                family,
+                stream_count: buf[19],
+                coupled_count: buf[20],
+                mapping: buf[21..end].into(),
+            }
+        };
+        Ok(Self {
+            version,
+            channel_count,
+            pre_skip,
+            input_samples_per_sec,
+            output_gain,
+            channel_mapping,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Opus {
+    head: OpusHead,
+    vcomment: Vcomment,
+    duration: Option<Duration>,
+    bits_per_sec: Option<u32>,
+}
+
+impl Opus {
+    pub fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
+        let file_len = rd.seek(SeekFrom::End(0))?;
+        rd.seek(SeekFrom::Start(0))?;
+
+        let mut packets = PacketReader::new(&mut rd);
+        let head = OpusHead::decode(&packets.read_packet()?)
+            .map_err(|e| e.into_invalid_data_err())?;
+        let vcomment = decode_vcomment(&packets.read_packet()?, b"OpusTags", false)?;
+        // The audio starts on a new page.
+        let audio_start = packets.pos();
+        let serial = packets.serial().unwrap();
+
+        let duration = read_last_granule_pos(&mut rd, serial, file_len)?
+            .map(|v| duration(v.saturating_sub(head.pre_skip as u64), SAMPLES_PER_SEC));
+        let bits_per_sec = duration.and_then(|d| bits_per_sec(file_len - audio_start, d));
+
+        Ok(Self {
+            head,
+            vcomment,
+            duration,
+            bits_per_sec,
+        })
+    }
+
+    pub fn head(&self) -> &OpusHead {
+        &self.head
+    }
+
+    pub fn vcomment(&self) -> &Vcomment {
+        &self.vcomment
+    }
+
+    /// Duration excluding the pre-skip.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Average bitrate.
+    pub fn bits_per_sec(&self) -> Option<u32> {
+        self.bits_per_sec
+    }
+
+    /// Track gain (`R128_TRACK_GAIN`) in dB as Q7.8 fixed point number, it's relative to
+    /// the output gain.
+    pub fn r128_track_gain(&self) -> Option<i16> {
+        self.r128_gain("R128_TRACK_GAIN")
+    }
+
+    /// Album gain (`R128_ALBUM_GAIN`) in dB as Q7.8 fixed point number, it's relative to
+    /// the output gain.
+    pub fn r128_album_gain(&self) -> Option<i16> {
+        self.r128_gain("R128_ALBUM_GAIN")
+    }
+
+    pub fn tags(&self) -> TagsRef<'_> {
+        TagsRef {
+            vcomment: Some(&self.vcomment),
+            .. Default::default()
+        }
+    }
+
+    fn r128_gain(&self, key: &str) -> Option<i16> {
+        self.vcomment.get(key).next().and_then(|v| v.trim().parse().ok())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use crate::ogg::test::page;
+
+    #[test]
+    fn read() {
+        let mut head = b"OpusHead".to_vec();
+        head.extend_from_slice(&[1, 2]);
+        head.extend_from_slice(&312u16.to_le_bytes());
+        head.extend_from_slice(&44100u32.to_le_bytes());
+        head.extend_from_slice(&(-256i16).to_le_bytes());
+        head.push(0);
+
+        let mut vcomment = Vcomment::new("v");
+        vcomment.set("TITLE", "t").unwrap();
+        vcomment.set("R128_TRACK_GAIN", "-1280").unwrap();
+        let mut tags = b"OpusTags".to_vec();
+        vcomment.write(&mut tags, false).unwrap();
+
+        let mut file = page(1, 0, 0b010, 0, &[&head], false);
+        file.extend_from_slice(&page(1, 1, 0, 0, &[&tags], false));
+        let audio = page(1, 2, 0b100, 96000 + 312, &[&[0; 1000]], false);
+        file.extend_from_slice(&audio);
+
+        let opus = Opus::read(Cursor::new(&file)).unwrap();
+        assert_eq!(opus.head(), &OpusHead {
+            version: 1,
+            channel_count: 2,
+            pre_skip: 312,
+            input_samples_per_sec: Some(44100),
+            output_gain: -256,
+            channel_mapping: ChannelMapping {
+                family: 0,
+                stream_count: 1,
+                coupled_count: 1,
+                mapping: vec![],
+            },
+        });
+        assert_eq!(opus.duration(), Some(Duration::from_secs(2)));
+        assert_eq!(opus.bits_per_sec(), Some(audio.len() as u32 * 4));
+        assert_eq!(opus.r128_track_gain(), Some(-1280));
+        assert_eq!(opus.r128_album_gain(), None);
+        assert_eq!(opus.tags().title(), Some("t".into()));
+
+        assert_eq!(Opus::read(Cursor::new(&file[..10])).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,184 @@
+use byteorder::{ByteOrder, LE};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use crate::error::*;
+use crate::tags::TagsRef;
+use crate::vcomment::Vcomment;
+use super::*;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    Narrowband,
+    Wideband,
+    UltraWideband,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Header {
+    /// Version string of the encoder.
+    pub version: String,
+    pub samples_per_sec: u32,
+    pub mode: Mode,
+    pub channel_count: u8,
+
+    /// Nominal bitrate, `None` if unknown.
+    pub bits_per_sec: Option<u32>,
+
+    /// Number of samples per frame.
+    pub frame_len_samples: u32,
+    pub vbr: bool,
+    pub frames_per_packet: u32,
+
+    /// Number of extra header packets following the comment header.
+    pub extra_header_count: u32,
+}
+
+impl Header {
+    const LEN: usize = 80;
+
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::LEN || &buf[..8] != b"Speex   " {
+            return Err(Error("bad Speex header"));
+        }
+        let version = String::from_utf8_lossy(&buf[8..28])
+            .trim_end_matches('\0')
+            .into();
+        let i32_at = |i: usize| LE::read_i32(&buf[i..i + 4]);
+        if (i32_at(32) as usize) < Self::LEN {
+            return Err(Error("bad Speex header len"));
+        }
+        let samples_per_sec = i32_at(36);
+        if samples_per_sec <= 0 {
+            return Err(Error("invalid sample rate"));
+        }
+        let mode = match i32_at(40) {
+            0 => Mode::Narrowband,
+            1 => Mode::Wideband,
+            2 => Mode::UltraWideband,
+            _ => return Err(Error("bad Speex mode")),
+        };
+        let channel_count = i32_at(48);
+        if !(1..=2).contains(&channel_count) {
+            return Err(Error("bad Speex channel count"));
+        }
+        let bits_per_sec = Some(i32_at(52)).filter(|&v| v > 0).map(|v| v as u32);
+        Ok(Self {
+            version,
+            samples_per_sec: samples_per_sec as u32,
+            mode,
+            channel_count: channel_count as u8,
+            bits_per_sec,
+            frame_len_samples: i32_at(56).max(0) as u32,
+            vbr: i32_at(60) != 0,
+            frames_per_packet: i32_at(64).max(0) as u32,
+            extra_header_count: i32_at(68).max(0) as u32,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Speex {
+    header: Header,
+    vcomment: Vcomment,
+    duration: Option<Duration>,
+    bits_per_sec: Option<u32>,
+}
+
+impl Speex {
+    pub fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
+        let file_len = rd.seek(SeekFrom::End(0))?;
+        rd.seek(SeekFrom::Start(0))?;
+
+        let mut packets = PacketReader::new(&mut rd);
+        let header = Header::decode(&packets.read_packet()?)
+            .map_err(|e| e.into_invalid_data_err())?;
+        let vcomment = decode_vcomment(&packets.read_packet()?, b"", false)?;
+        for _ in 0..header.extra_header_count {
+            packets.read_packet()?;
+        }
+        // The audio starts on a new page.
+        let audio_start = packets.pos();
+        let serial = packets.serial().unwrap();
+
+        let duration = read_last_granule_pos(&mut rd, serial, file_len)?
+            .map(|v| duration(v, header.samples_per_sec));
+        let bits_per_sec = header.bits_per_sec
+            .or_else(|| duration.and_then(|d| bits_per_sec(file_len - audio_start, d)));
+
+        Ok(Self {
+            header,
+            vcomment,
+            duration,
+            bits_per_sec,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn vcomment(&self) -> &Vcomment {
+        &self.vcomment
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Nominal bitrate if set, otherwise the average bitrate.
+    pub fn bits_per_sec(&self) -> Option<u32> {
+        self.bits_per_sec
+    }
+
+    pub fn tags(&self) -> TagsRef<'_> {
+        TagsRef {
+            vcomment: Some(&self.vcomment),
+            .. Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use crate::ogg::test::page;
+
+    #[test]
+    fn read() {
+        let mut header = b"Speex   speex-1.2.0".to_vec();
+        header.resize(28, 0);
+        for &v in &[1, 80, 16000, 1, 4, 1, -1, 320, 1, 1, 0, 0, 0] {
+            header.extend_from_slice(&(v as i32).to_le_bytes());
+        }
+
+        let mut vcomment = Vcomment::new("v");
+        vcomment.set("TITLE", "t").unwrap();
+        let mut comment = Vec::new();
+        vcomment.write(&mut comment, false).unwrap();
+
+        let mut file = page(1, 0, 0b010, 0, &[&header], false);
+        file.extend_from_slice(&page(1, 1, 0, 0, &[&comment], false));
+        let audio = page(1, 2, 0b100, 32000, &[&[0; 1000]], false);
+        file.extend_from_slice(&audio);
+
+        let speex = Speex::read(Cursor::new(&file)).unwrap();
+        assert_eq!(speex.header(), &Header {
+            version: "speex-1.2.0".into(),
+            samples_per_sec: 16000,
+            mode: Mode::Wideband,
+            channel_count: 1,
+            bits_per_sec: None,
+            frame_len_samples: 320,
+            vbr: true,
+            frames_per_packet: 1,
+            extra_header_count: 0,
+        });
+        assert_eq!(speex.duration(), Some(Duration::from_secs(2)));
+        assert_eq!(speex.bits_per_sec(), Some(audio.len() as u32 * 4));
+        assert_eq!(speex.tags().title(), Some("t".into()));
+    }
+}
@@ -0,0 +1,161 @@
+use byteorder::{ByteOrder, LE};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use crate::error::*;
+use crate::tags::TagsRef;
+use crate::vcomment::Vcomment;
+use super::*;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IdentHeader {
+    pub channel_count: u8,
+    pub samples_per_sec: u32,
+
+    /// Bitrate hints. These are `None` if unset.
+    pub max_bits_per_sec: Option<u32>,
+    pub nominal_bits_per_sec: Option<u32>,
+    pub min_bits_per_sec: Option<u32>,
+}
+
+impl IdentHeader {
+    const LEN: usize = 30;
+
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::LEN || &buf[..7] != b"\x01vorbis" {
+            return Err(Error("bad Vorbis identification header"));
+        }
+        if LE::read_u32(&buf[7..11]) != 0 {
+            return Err(Error("unsupported Vorbis version"));
+        }
+        let channel_count = buf[11];
+        let samples_per_sec = LE::read_u32(&buf[12..16]);
+        if channel_count == 0 || samples_per_sec == 0 || !buf[29].get_bit(0) {
+            return Err(Error("bad Vorbis identification header"));
+        }
+        let bits_per_sec = |i: usize| Some(LE::read_i32(&buf[i..i + 4]))
+            .filter(|&v| v > 0)
+            .map(|v| v as u32);
+        Ok(Self {
+            channel_count,
+            samples_per_sec,
+            max_bits_per_sec: bits_per_sec(16),
+            nominal_bits_per_sec: bits_per_sec(20),
+            min_bits_per_sec: bits_per_sec(24),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Vorbis {
+    ident_header: IdentHeader,
+    vcomment: Vcomment,
+    duration: Option<Duration>,
+    bits_per_sec: Option<u32>,
+}
+
+impl Vorbis {
+    pub fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
+        let file_len = rd.seek(SeekFrom::End(0))?;
+        rd.seek(SeekFrom::Start(0))?;
+
+        let mut packets = PacketReader::new(&mut rd);
+        let ident_header = IdentHeader::decode(&packets.read_packet()?)
+            .map_err(|e| e.into_invalid_data_err())?;
+        let vcomment = decode_vcomment(&packets.read_packet()?, b"\x03vorbis", true)?;
+        // Setup header, the audio starts on a new page.
+        packets.read_packet()?;
+        let audio_start = packets.pos();
+        let serial = packets.serial().unwrap();
+
+        let duration = read_last_granule_pos(&mut rd, serial, file_len)?
+            .map(|v| duration(v, ident_header.samples_per_sec));
+        let bits_per_sec = ident_header.nominal_bits_per_sec
+            .or_else(|| duration.and_then(|d| bits_per_sec(file_len - audio_start, d)));
+
+        Ok(Self {
+            ident_header,
+            vcomment,
+            duration,
+            bits_per_sec,
+        })
+    }
+
+    pub fn ident_header(&self) -> &IdentHeader {
+        &self.ident_header
+    }
+
+    pub fn vcomment(&self) -> &Vcomment {
+        &self.vcomment
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Nominal bitrate if set, otherwise the average bitrate.
+    pub fn bits_per_sec(&self) -> Option<u32> {
+        self.bits_per_sec
+    }
+
+    pub fn tags(&self) -> TagsRef<'_> {
+        TagsRef {
+            vcomment: Some(&self.vcomment),
+            .. Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use crate::ogg::test::page;
+
+    fn ident_header(nominal_bits_per_sec: i32) -> Vec<u8> {
+        let mut r = b"\x01vorbis".to_vec();
+        r.extend_from_slice(&0u32.to_le_bytes());
+        r.push(2);
+        r.extend_from_slice(&44100u32.to_le_bytes());
+        r.extend_from_slice(&0i32.to_le_bytes());
+        r.extend_from_slice(&nominal_bits_per_sec.to_le_bytes());
+        r.extend_from_slice(&0i32.to_le_bytes());
+        r.push(0xb8);
+        r.push(1);
+        r
+    }
+
+    #[test]
+    fn read() {
+        let mut vcomment = Vcomment::new("v");
+        vcomment.set("TITLE", "t").unwrap();
+        let mut comment = b"\x03vorbis".to_vec();
+        vcomment.write(&mut comment, true).unwrap();
+
+        for &nominal_bits_per_sec in &[128000, 0] {
+            let mut file = page(1, 0, 0b010, 0, &[&ident_header(nominal_bits_per_sec)], false);
+            file.extend_from_slice(&page(1, 1, 0, 0, &[&comment, b"\x05vorbis"], false));
+            file.extend_from_slice(&page(1, 2, 0, 44100, &[&[0; 1000]], false));
+            let audio = page(1, 3, 0b100, 88200, &[&[0; 1000]], false);
+            file.extend_from_slice(&audio);
+
+            let vorbis = Vorbis::read(Cursor::new(&file)).unwrap();
+            assert_eq!(vorbis.ident_header(), &IdentHeader {
+                channel_count: 2,
+                samples_per_sec: 44100,
+                max_bits_per_sec: None,
+                nominal_bits_per_sec: Some(nominal_bits_per_sec as u32).filter(|&v| v > 0),
+                min_bits_per_sec: None,
+            });
+            assert_eq!(vorbis.tags().title(), Some("t".into()));
+            assert_eq!(vorbis.duration(), Some(Duration::from_secs(2)));
+            if nominal_bits_per_sec == 0 {
+                assert_eq!(vorbis.bits_per_sec(), Some(audio.len() as u32 * 8));
+            } else {
+                assert_eq!(vorbis.bits_per_sec(), Some(128000));
+            }
+        }
+    }
+}
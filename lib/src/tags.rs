@@ -1,40 +1,61 @@
 use std::borrow::Cow;
 
+use crate::aiff::Aiff;
+use crate::ape::Ape;
 use crate::id3::v1::Id3v1;
 use crate::id3::v2::Id3v2;
+use crate::mp4::Ilst;
 use crate::timestamp::Timestamp;
 use crate::vcomment::Vcomment;
+use crate::wav;
 
 #[derive(Debug, Default)]
 pub struct TagsRef<'a> {
     pub id3v1: Option<&'a Id3v1>,
     pub id3v2: Option<&'a Id3v2>,
     pub vcomment: Option<&'a Vcomment>,
+    pub ilst: Option<&'a Ilst>,
+    pub riff_info: Option<&'a wav::Info>,
+    /// AIFF text chunks.
+    pub aiff: Option<&'a Aiff>,
+    pub ape: Option<&'a Ape>,
 }
 
 impl<'a> TagsRef<'a> {
     pub fn title(&self) -> Option<Cow<str>> {
         self.choose(
-            |v| Some(v.best_title()),
+            |v| Some(v.best_title().into()),
+            |v| v.title().map(|v| v.into()),
+            |v| v.title().map(|v| v.into()),
             |v| v.title(),
-            |v| v.title(),
-        ).map(|v| v.into())
+            |v| v.title().map(|v| v.into()),
+            |v| v.name().map(|v| v.into()),
+            |v| v.title().map(|v| v.into()),
+        )
     }
 
     pub fn artist(&self) -> Option<Cow<str>> {
         self.choose(
-            |v| Some(v.best_artist()),
-            |v| v.artist(),
+            |v| Some(v.best_artist().into()),
+            |v| v.artist().map(|v| v.into()),
+            |v| v.artist().map(|v| v.into()),
             |v| v.artist(),
-        ).map(|v| v.into())
+            |v| v.artist().map(|v| v.into()),
+            |v| v.author().map(|v| v.into()),
+            |v| v.artist().map(|v| v.into()),
+        )
     }
 
     pub fn album(&self) -> Option<Cow<str>> {
         self.choose(
-            |v| Some(v.best_album()),
-            |v| v.album(),
+            |v| Some(v.best_album().into()),
+            |v| v.album().map(|v| v.into()),
+            |v| v.album().map(|v| v.into()),
             |v| v.album(),
-        ).map(|v| v.into())
+            |v| v.album().map(|v| v.into()),
+            |_| None,
+            |v| v.album().map(|v| v.into()),
+        )
     }
 
     pub fn genre(&self) -> Option<Cow<str>> {
@@ -54,6 +75,10 @@ impl<'a> TagsRef<'a> {
             },
             |v| v.genre().map(|v| v.into()),
             |v| v.genre().map(|v| v.into()),
+            |v| v.genre(),
+            |v| v.genre().map(|v| v.into()),
+            |_| None,
+            |v| v.genre().map(|v| v.into()),
         )
     }
 
@@ -62,22 +87,43 @@ impl<'a> TagsRef<'a> {
             |v| v.date(),
             |v| v.release_date(),
             |v| v.date(),
+            |v| v.date(),
+            |v| v.date(),
+            |_| None,
+            |v| v.date(),
         )
     }
 
-    fn choose<T, TId3v1, TId3v2, TVComment>(&self,
+    #[allow(clippy::too_many_arguments)]
+    fn choose<T, TId3v1, TId3v2, TVComment, TIlst, TRiffInfo, TAiff, TApe>(&self,
         id3v1: TId3v1,
         id3v2: TId3v2,
         vcomment: TVComment,
+        ilst: TIlst,
+        riff_info: TRiffInfo,
+        aiff: TAiff,
+        ape: TApe,
     ) -> Option<T>
         where TId3v1: FnOnce(&'a Id3v1) -> Option<T>,
               TId3v2: FnOnce(&'a Id3v2) -> Option<T>,
               TVComment: FnOnce(&'a Vcomment) -> Option<T>,
+              TIlst: FnOnce(&'a Ilst) -> Option<T>,
+              TRiffInfo: FnOnce(&'a wav::Info) -> Option<T>,
+              TAiff: FnOnce(&'a Aiff) -> Option<T>,
+              TApe: FnOnce(&'a Ape) -> Option<T>,
     {
         if let Some(v) = self.vcomment {
             vcomment(v)
+        } else if let Some(v) = self.ilst {
+            ilst(v)
         } else if let Some(v) = self.id3v2 {
             id3v2(v)
+        } else if let Some(v) = self.ape {
+            ape(v)
+        } else if let Some(v) = self.riff_info {
+            riff_info(v)
+        } else if let Some(v) = self.aiff {
+            aiff(v)
         } else if let Some(v) = self.id3v1 {
             id3v1(v)
         } else {
@@ -1,9 +1,12 @@
 pub mod bit_stream;
+pub mod chunk;
+pub mod file;
 pub mod limited;
 
 use std::fmt;
 use std::io::prelude::*;
 use std::io::{Error, ErrorKind, Result};
+use std::time::Duration;
 
 pub use crate::util::limited::Limited;
 pub use crate::util::bit_stream::BitReader;
@@ -49,6 +52,20 @@ impl<T> IoResultExt<T> for std::result::Result<T, Error> {
     }
 }
 
+/// Converts the len played at the `per_sec` rate (e.g. samples and samples per second)
+/// to duration.
+pub fn duration(len: u64, per_sec: u32) -> Duration {
+    Duration::from_nanos((len as u128 * 1_000_000_000 / per_sec as u128) as u64)
+}
+
+/// Computes the average bitrate of the stream of `len_bytes` playing for `duration`.
+pub fn bits_per_sec(len_bytes: u64, duration: Duration) -> Option<u32> {
+    if duration.as_nanos() == 0 {
+        return None;
+    }
+    Some((len_bytes as u128 * 8 * 1_000_000_000 / duration.as_nanos()) as u32)
+}
+
 pub fn display_to_debug(d: impl fmt::Display) -> impl fmt::Debug {
     struct Wrap<T>(T);
 
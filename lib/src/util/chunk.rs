@@ -0,0 +1,68 @@
+use byteorder::ByteOrder;
+use encoding::{Encoding, DecoderTrap};
+use encoding::all::ISO_8859_1;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use crate::error::*;
+use crate::fourcc::FourCC;
+use super::{read_vec_limited, Limited};
+
+/// Max len of the chunk data read into memory, larger chunks are considered corrupted.
+const MAX_LEN: u64 = 64 * 1024 * 1024;
+
+/// Chunk of RIFF (WAVE) or IFF (AIFF) file.
+pub struct Chunk {
+    pub id: FourCC,
+    /// Data len as stored in the header, it may exceed the file.
+    pub len: u64,
+    /// Position of the data.
+    pub pos: u64,
+    /// Len of the data available before the end passed to `read`.
+    pub avail: u64,
+}
+
+impl Chunk {
+    /// Reads the header of the chunk at `pos` leaving `rd` at the data start. Returns `None`
+    /// if there's no room for the header before `end`.
+    pub fn read<B: ByteOrder>(rd: &mut (impl Read + Seek), pos: u64, end: u64)
+        -> io::Result<Option<Self>>
+    {
+        if end.saturating_sub(pos) < 8 {
+            return Ok(None);
+        }
+        rd.seek(SeekFrom::Start(pos))?;
+        let mut hdr = [0; 8];
+        rd.read_exact(&mut hdr)?;
+        Ok(Some(Self {
+            id: FourCC([hdr[0], hdr[1], hdr[2], hdr[3]]),
+            len: B::read_u32(&hdr[4..8]) as u64,
+            pos: pos + 8,
+            avail: end - pos - 8,
+        }))
+    }
+
+    /// Reads the data, `rd` must be positioned at the data start.
+    pub fn read_data(&self, rd: &mut impl Read) -> io::Result<Vec<u8>> {
+        if self.len > MAX_LEN {
+            return Err(Error("chunk is too large").into_invalid_data_err());
+        }
+        read_vec_limited(&mut Limited::new(rd, self.avail), self.len as usize, "chunk is truncated")
+    }
+
+    /// Position of the next chunk, odd len chunks are followed by a pad byte.
+    pub fn next_pos(&self) -> u64 {
+        self.pos.saturating_add(self.len).saturating_add(self.len & 1)
+    }
+}
+
+/// Decodes null-padded string, ASCII is expected but some writers use UTF-8 or ISO-8859-1.
+pub fn decode_str(buf: &[u8]) -> String {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    let buf = &buf[..len];
+    match std::str::from_utf8(buf) {
+        Ok(s) => s.into(),
+        Err(_) => ISO_8859_1.decode(buf, DecoderTrap::Replace).unwrap(),
+    }
+}
@@ -0,0 +1,89 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, BufWriter, Result, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use super::unexpected_eof_err;
+
+/// Rewrites the file at `path` by writing the new contents with `f` to a temporary file
+/// in the same directory and renaming it over the original. `f` receives the original file
+/// as the source.
+pub fn rewrite_file(path: &Path, src: &mut File,
+    f: impl FnOnce(&mut File, &mut BufWriter<File>) -> Result<()>) -> Result<()>
+{
+    let tmp_path = tmp_path(path)?;
+    let r = (|| {
+        let mut dst = BufWriter::new(OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?);
+        f(src, &mut dst)?;
+        let dst = dst.into_inner().map_err(|e| e.into_error())?;
+        dst.set_permissions(src.metadata()?.permissions())?;
+        dst.sync_all()
+    })();
+    if let Err(e) = r {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    fs::rename(&tmp_path, path)
+}
+
+/// Copies the `range` of `src` to `dst`.
+pub fn copy_range(src: &mut (impl Read + Seek), dst: &mut impl Write, range: Range<u64>)
+    -> Result<()>
+{
+    let len = range.end - range.start;
+    src.seek(SeekFrom::Start(range.start))?;
+    if io::copy(&mut src.take(len), dst)? != len {
+        return Err(unexpected_eof_err("file truncated"));
+    }
+    Ok(())
+}
+
+fn tmp_path(path: &Path) -> Result<PathBuf> {
+    let file_name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(".tagen-tmp");
+    Ok(path.with_file_name(tmp_name))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::ops::Deref;
+
+    /// File in the temp dir which is removed on drop.
+    pub struct TempFile(PathBuf);
+
+    impl TempFile {
+        /// The `name` is prefixed with the process ID to not clash with the concurrent runs.
+        pub fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("tagen-{}-{}", std::process::id(), name)))
+        }
+    }
+
+    impl AsRef<Path> for TempFile {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Deref for TempFile {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use bit_field::BitField;
-use byteorder::{LE, ReadBytesExt};
+use byteorder::{LE, ReadBytesExt, WriteBytesExt};
+use std::convert::TryFrom;
 use std::io;
 use std::io::prelude::*;
 
@@ -14,10 +15,26 @@ pub struct Vcomment {
 }
 
 impl Vcomment {
+    pub fn new(vendor: impl Into<String>) -> Self {
+        Self {
+            vendor: vendor.into(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if `key` consists of ASCII chars 0x20 through 0x7D excluding `=`.
+    pub fn is_valid_key(key: &str) -> bool {
+        key.bytes().all(|c| (0x20..=0x7d).contains(&c) && c != b'=')
+    }
+
     pub fn vendor(&self) -> &str {
         &self.vendor
     }
 
+    pub fn set_vendor(&mut self, vendor: impl Into<String>) {
+        self.vendor = vendor.into();
+    }
+
     pub fn entries(&self) -> impl Iterator<Item=(&str, &str)> {
         self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
     }
@@ -28,6 +45,41 @@ impl Vcomment {
             .map(|(_, v)| v)
     }
 
+    /// Replaces all values of the `key` with the `value`.
+    pub fn set(&mut self, key: &str, value: impl Into<String>) -> Result<()> {
+        Self::check_key(key)?;
+        let pos = self.entries.iter().position(|(k, _)| k.eq_ignore_ascii_case(key));
+        self.remove(key);
+        let entry = (key.into(), value.into());
+        if let Some(pos) = pos {
+            self.entries.insert(pos, entry);
+        } else {
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+
+    /// Adds the `value` to the values of the `key`.
+    pub fn add(&mut self, key: &str, value: impl Into<String>) -> Result<()> {
+        Self::check_key(key)?;
+        self.entries.push((key.into(), value.into()));
+        Ok(())
+    }
+
+    /// Removes all values of the `key` and returns them.
+    pub fn remove(&mut self, key: &str) -> Vec<String> {
+        let mut r = Vec::new();
+        let mut i = 0;
+        while i < self.entries.len() {
+            if self.entries[i].0.eq_ignore_ascii_case(key) {
+                r.push(self.entries.remove(i).1);
+            } else {
+                i += 1;
+            }
+        }
+        r
+    }
+
     pub fn title(&self) -> Option<&str> {
         self.get("TITLE").next()
     }
@@ -71,7 +123,7 @@ impl Vcomment {
             entries.push((key, value));
         }
 
-        if framing && !rd.read_u8()?.get_bit(7) {
+        if framing && !rd.read_u8()?.get_bit(0) {
             return Err(Error("framing bit is not set").into_invalid_data_err());
         }
 
@@ -80,4 +132,70 @@ impl Vcomment {
             entries,
         })
     }
+
+    /// Writes the comment packet. `framing` tells whether to write the framing bit as required
+    /// by Vorbis streams, FLAC omits it.
+    pub fn write(&self, wr: &mut impl Write, framing: bool) -> io::Result<()> {
+        fn write_len(wr: &mut impl Write, len: usize) -> io::Result<()> {
+            let len = u32::try_from(len)
+                .map_err(|_| Error("vcomment is too large").into_io_err(io::ErrorKind::InvalidInput))?;
+            wr.write_u32::<LE>(len)
+        }
+
+        write_len(wr, self.vendor.len())?;
+        wr.write_all(self.vendor.as_bytes())?;
+
+        write_len(wr, self.entries.len())?;
+        for (k, v) in &self.entries {
+            Self::check_key(k).map_err(|e| e.into_io_err(io::ErrorKind::InvalidInput))?;
+            write_len(wr, k.len() + 1 + v.len())?;
+            wr.write_all(k.as_bytes())?;
+            wr.write_all(b"=")?;
+            wr.write_all(v.as_bytes())?;
+        }
+
+        if framing {
+            wr.write_u8(1)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_key(key: &str) -> Result<()> {
+        if Self::is_valid_key(key) {
+            Ok(())
+        } else {
+            Err(Error("invalid vcomment key"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn edit_and_write() {
+        let mut vc = Vcomment::new("vendor");
+        vc.add("ARTIST", "a").unwrap();
+        vc.add("TITLE", "t").unwrap();
+        vc.add("artist", "b").unwrap();
+        assert!(vc.add("A=B", "c").is_err());
+        assert!(vc.set("\u{e9}", "c").is_err());
+        assert_eq!(vc.get("Artist").collect::<Vec<_>>(), vec!["a", "b"]);
+
+        vc.set("Artist", "\u{263a}").unwrap();
+        assert_eq!(vc.entries().collect::<Vec<_>>(), vec![("Artist", "\u{263a}"), ("TITLE", "t")]);
+        assert_eq!(vc.remove("title"), vec!["t"]);
+        vc.set_vendor("v");
+
+        for &framing in &[false, true] {
+            let mut buf = Vec::new();
+            vc.write(&mut buf, framing).unwrap();
+            let len = buf.len() as u64;
+            let mut rd = Limited::new(&buf[..], len);
+            assert_eq!(Vcomment::read_limited(&mut rd, framing).unwrap(), vc);
+            assert_eq!(rd.max_available(), 0);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,525 @@
+use byteorder::{ByteOrder, LE};
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use crate::error::*;
+use crate::fourcc::FourCC;
+use crate::id3::v2::Id3v2;
+use crate::tags::TagsRef;
+use crate::timestamp::Timestamp;
+use crate::util::*;
+use crate::util::chunk::{Chunk, decode_str};
+
+const RIFF: FourCC = FourCC(*b"RIFF");
+const RF64: FourCC = FourCC(*b"RF64");
+const BW64: FourCC = FourCC(*b"BW64");
+const WAVE: FourCC = FourCC(*b"WAVE");
+const DS64: FourCC = FourCC(*b"ds64");
+const FMT: FourCC = FourCC(*b"fmt ");
+const FACT: FourCC = FourCC(*b"fact");
+const DATA: FourCC = FourCC(*b"data");
+const LIST: FourCC = FourCC(*b"LIST");
+const INFO: FourCC = FourCC(*b"INFO");
+const ID3: FourCC = FourCC(*b"id3 ");
+const ID3_UPPER: FourCC = FourCC(*b"ID3 ");
+const BEXT: FourCC = FourCC(*b"bext");
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct FormatTag(pub u16);
+
+impl FormatTag {
+    pub const PCM: Self = Self(0x0001);
+    pub const ADPCM: Self = Self(0x0002);
+    pub const IEEE_FLOAT: Self = Self(0x0003);
+    pub const ALAW: Self = Self(0x0006);
+    pub const MULAW: Self = Self(0x0007);
+    pub const MPEG_LAYER3: Self = Self(0x0055);
+    pub const EXTENSIBLE: Self = Self(0xfffe);
+}
+
+impl fmt::Debug for FormatTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Self::PCM => "PCM",
+            Self::ADPCM => "ADPCM",
+            Self::IEEE_FLOAT => "IEEE_FLOAT",
+            Self::ALAW => "ALAW",
+            Self::MULAW => "MULAW",
+            Self::MPEG_LAYER3 => "MPEG_LAYER3",
+            Self::EXTENSIBLE => "EXTENSIBLE",
+            _ => return write!(f, "FormatTag({:#06x})", self.0),
+        })
+    }
+}
+
+/// The `fmt ` chunk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WaveFormat {
+    pub format_tag: FormatTag,
+    pub channel_count: u16,
+    pub samples_per_sec: u32,
+    pub bytes_per_sec: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+
+    /// These are set for `FormatTag::EXTENSIBLE` only.
+    pub valid_bits_per_sample: Option<u16>,
+    pub channel_mask: Option<u32>,
+    pub sub_format: Option<FormatTag>,
+}
+
+impl WaveFormat {
+    /// Format tag with `EXTENSIBLE` resolved to the sub format.
+    pub fn kind(&self) -> FormatTag {
+        self.sub_format.unwrap_or(self.format_tag)
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < 16 {
+            return Err(Error("fmt chunk is truncated"));
+        }
+        let format_tag = FormatTag(LE::read_u16(&buf[0..2]));
+        let channel_count = LE::read_u16(&buf[2..4]);
+        let samples_per_sec = LE::read_u32(&buf[4..8]);
+        if channel_count == 0 || samples_per_sec == 0 {
+            return Err(Error("bad fmt chunk"));
+        }
+        let (valid_bits_per_sample, channel_mask, sub_format) = if format_tag == FormatTag::EXTENSIBLE {
+            if buf.len() < 40 || LE::read_u16(&buf[16..18]) < 22 {
+                return Err(Error("WAVE_FORMAT_EXTENSIBLE fmt chunk is truncated"));
+            }
+            (Some(LE::read_u16(&buf[18..20])),
+                Some(LE::read_u32(&buf[20..24])),
+                // The rest of the GUID is fixed.
+                Some(FormatTag(LE::read_u16(&buf[24..26]))))
+        } else {
+            (None, None, None)
+        };
+        Ok(Self {
+            format_tag,
+            channel_count,
+            samples_per_sec,
+            bytes_per_sec: LE::read_u32(&buf[8..12]),
+            block_align: LE::read_u16(&buf[12..14]),
+            bits_per_sample: LE::read_u16(&buf[14..16]),
+            valid_bits_per_sample,
+            channel_mask,
+            sub_format,
+        })
+    }
+}
+
+/// The `LIST` chunk of `INFO` type.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Info {
+    entries: Vec<(FourCC, String)>,
+}
+
+impl Info {
+    pub const TITLE: FourCC = FourCC(*b"INAM");
+    pub const ARTIST: FourCC = FourCC(*b"IART");
+    pub const ALBUM: FourCC = FourCC(*b"IPRD");
+    pub const GENRE: FourCC = FourCC(*b"IGNR");
+    pub const DATE: FourCC = FourCC(*b"ICRD");
+    pub const COMMENT: FourCC = FourCC(*b"ICMT");
+    pub const COPYRIGHT: FourCC = FourCC(*b"ICOP");
+    pub const SOFTWARE: FourCC = FourCC(*b"ISFT");
+    pub const TRACK: FourCC = FourCC(*b"ITRK");
+
+    pub fn entries(&self) -> impl Iterator<Item=(FourCC, &str)> {
+        self.entries.iter().map(|(k, v)| (*k, v.as_str()))
+    }
+
+    pub fn get(&self, id: FourCC) -> Option<&str> {
+        self.entries.iter().find(|(k, _)| *k == id).map(|(_, v)| v.as_str())
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.get(Self::TITLE)
+    }
+
+    pub fn artist(&self) -> Option<&str> {
+        self.get(Self::ARTIST)
+    }
+
+    pub fn album(&self) -> Option<&str> {
+        self.get(Self::ALBUM)
+    }
+
+    pub fn genre(&self) -> Option<&str> {
+        self.get(Self::GENRE)
+    }
+
+    pub fn date(&self) -> Option<Timestamp> {
+        self.get(Self::DATE).and_then(|s| s.parse().ok())
+    }
+
+    /// Decodes the `LIST` chunk data after the `INFO` list type.
+    fn decode(mut buf: &[u8]) -> Result<Self> {
+        let mut entries = Vec::new();
+        while buf.len() >= 8 {
+            let id = FourCC([buf[0], buf[1], buf[2], buf[3]]);
+            let len = LE::read_u32(&buf[4..8]) as usize;
+            let value = buf.get(8..8 + len).ok_or(Error("INFO entry is truncated"))?;
+            entries.push((id, decode_str(value)));
+            buf = buf.get(8 + len + (len & 1)..).unwrap_or(&[]);
+        }
+        Ok(Self {
+            entries,
+        })
+    }
+}
+
+/// Broadcast Wave Format `bext` chunk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Bext {
+    pub description: String,
+    pub originator: String,
+    pub originator_reference: String,
+
+    /// In `yyyy:mm:dd` format.
+    pub origination_date: String,
+
+    /// In `hh:mm:ss` format.
+    pub origination_time: String,
+
+    /// Position of the first sample in samples since midnight.
+    pub time_reference: u64,
+    pub version: u16,
+
+    /// Loudness in hundredths of LUFS, LU or dBTP. These are set since version 2.
+    pub loudness_value: Option<i16>,
+    pub loudness_range: Option<i16>,
+    pub max_true_peak_level: Option<i16>,
+    pub max_momentary_loudness: Option<i16>,
+    pub max_short_term_loudness: Option<i16>,
+
+    pub coding_history: String,
+}
+
+impl Bext {
+    const MIN_LEN: usize = 602;
+
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::MIN_LEN {
+            return Err(Error("bext chunk is truncated"));
+        }
+        let version = LE::read_u16(&buf[346..348]);
+        let loudness = |i: usize| Some(LE::read_i16(&buf[i..i + 2])).filter(|_| version >= 2);
+        Ok(Self {
+            description: decode_str(&buf[..256]),
+            originator: decode_str(&buf[256..288]),
+            originator_reference: decode_str(&buf[288..320]),
+            origination_date: decode_str(&buf[320..330]),
+            origination_time: decode_str(&buf[330..338]),
+            time_reference: LE::read_u64(&buf[338..346]),
+            version,
+            loudness_value: loudness(412),
+            loudness_range: loudness(414),
+            max_true_peak_level: loudness(416),
+            max_momentary_loudness: loudness(418),
+            max_short_term_loudness: loudness(420),
+            coding_history: decode_str(&buf[602..]),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Wav {
+    format: WaveFormat,
+    data_len: u64,
+    duration: Option<Duration>,
+    info: Option<Info>,
+    id3v2: Option<Id3v2>,
+    bext: Option<Bext>,
+}
+
+impl Wav {
+    pub fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
+        let file_len = rd.seek(SeekFrom::End(0))?;
+        rd.seek(SeekFrom::Start(0))?;
+
+        let mut hdr = [0; 12];
+        rd.read_exact(&mut hdr)?;
+        let kind = FourCC([hdr[0], hdr[1], hdr[2], hdr[3]]);
+        if ![RIFF, RF64, BW64].contains(&kind) || hdr[8..12] != WAVE.0 {
+            return Err(Error("not a WAVE file").into_invalid_data_err());
+        }
+
+        // Sizes of the chunks that don't fit in 32 bits for RF64.
+        let mut ds64 = Vec::new();
+        let mut format = None;
+        let mut fact_len_samples = None;
+        let mut data_len = None;
+        let mut info = None;
+        let mut id3v2 = None;
+        let mut bext = None;
+
+        let mut pos = 12;
+        while let Some(mut chunk) = Chunk::read::<LE>(&mut rd, pos, file_len)? {
+            if chunk.len == u32::MAX as u64 {
+                if let Some(&(_, v)) = ds64.iter().find(|&&(k, _)| k == chunk.id) {
+                    chunk.len = v;
+                }
+            }
+
+            // Only `fmt ` and `data` are required, the optional chunks that can't be decoded
+            // are ignored.
+            match chunk.id {
+                DS64 if pos == 12 && kind != RIFF => {
+                    ds64 = Self::decode_ds64(&chunk.read_data(&mut rd)?)
+                        .map_err(|e| e.into_invalid_data_err())?;
+                }
+                FMT if format.is_none() => {
+                    format = Some(WaveFormat::decode(&chunk.read_data(&mut rd)?)
+                        .map_err(|e| e.into_invalid_data_err())?);
+                }
+                FACT if fact_len_samples.is_none() => {
+                    fact_len_samples = chunk.read_data(&mut rd).into_opt()?
+                        .filter(|buf| buf.len() >= 4)
+                        .map(|buf| LE::read_u32(&buf) as u64);
+                }
+                // Streamed files may have the data len unset or exceeding the file.
+                DATA if data_len.is_none() => data_len = Some(cmp::min(chunk.len, chunk.avail)),
+                LIST if info.is_none() => {
+                    info = chunk.read_data(&mut rd).into_opt()?
+                        .filter(|buf| buf.len() >= 4 && buf[..4] == INFO.0)
+                        .and_then(|buf| Info::decode(&buf[4..]).ok());
+                }
+                ID3 | ID3_UPPER if id3v2.is_none() => {
+                    id3v2 = Id3v2::read(&mut rd, Some(cmp::min(chunk.len, chunk.avail)))
+                        .into_opt()?
+                        .map(|(v, _)| v);
+                }
+                BEXT if bext.is_none() => {
+                    bext = chunk.read_data(&mut rd).into_opt()?
+                        .and_then(|buf| Bext::decode(&buf).ok());
+                }
+                _ => {}
+            }
+
+            pos = chunk.next_pos();
+        }
+
+        let format = format.ok_or_else(|| Error("missing fmt chunk").into_invalid_data_err())?;
+        let data_len = data_len.ok_or_else(|| Error("missing data chunk").into_invalid_data_err())?;
+
+        let len_samples = match format.kind() {
+            FormatTag::PCM | FormatTag::IEEE_FLOAT if format.block_align > 0 =>
+                Some(data_len / format.block_align as u64),
+            _ => fact_len_samples,
+        };
+        let duration = if let Some(len_samples) = len_samples {
+            Some(duration(len_samples, format.samples_per_sec))
+        } else if format.bytes_per_sec > 0 {
+            Some(duration(data_len, format.bytes_per_sec))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            format,
+            data_len,
+            duration,
+            info,
+            id3v2,
+            bext,
+        })
+    }
+
+    pub fn format(&self) -> &WaveFormat {
+        &self.format
+    }
+
+    /// Len of the audio data in bytes.
+    pub fn data_len(&self) -> u64 {
+        self.data_len
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    pub fn bits_per_sec(&self) -> u32 {
+        self.format.bytes_per_sec.saturating_mul(8)
+    }
+
+    pub fn info(&self) -> Option<&Info> {
+        self.info.as_ref()
+    }
+
+    pub fn id3v2(&self) -> Option<&Id3v2> {
+        self.id3v2.as_ref()
+    }
+
+    pub fn bext(&self) -> Option<&Bext> {
+        self.bext.as_ref()
+    }
+
+    pub fn tags(&self) -> TagsRef<'_> {
+        TagsRef {
+            id3v2: self.id3v2.as_ref(),
+            riff_info: self.info.as_ref(),
+            .. Default::default()
+        }
+    }
+
+    /// Decodes the chunk sizes from `ds64` chunk: the `data` size and the table entries.
+    fn decode_ds64(buf: &[u8]) -> Result<Vec<(FourCC, u64)>> {
+        if buf.len() < 28 {
+            return Err(Error("ds64 chunk is truncated"));
+        }
+        let mut r = vec![(DATA, LE::read_u64(&buf[8..16]))];
+        let count = LE::read_u32(&buf[24..28]) as usize;
+        let table = buf[28..].chunks_exact(12).take(count);
+        if table.len() < count {
+            return Err(Error("ds64 chunk is truncated"));
+        }
+        for entry in table {
+            r.push((FourCC([entry[0], entry[1], entry[2], entry[3]]), LE::read_u64(&entry[4..])));
+        }
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut r = id.to_vec();
+        r.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        r.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            r.push(0);
+        }
+        r
+    }
+
+    fn file(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let data = chunks.concat();
+        let mut r = kind.to_vec();
+        let len = if kind == b"RIFF" { 4 + data.len() as u32 } else { u32::MAX };
+        r.extend_from_slice(&len.to_le_bytes());
+        r.extend_from_slice(b"WAVE");
+        r.extend_from_slice(&data);
+        r
+    }
+
+    fn fmt_extensible() -> Vec<u8> {
+        let mut r = Vec::new();
+        for &v in &[0xfffe, 2] {
+            r.extend_from_slice(&(v as u16).to_le_bytes());
+        }
+        r.extend_from_slice(&48000u32.to_le_bytes());
+        r.extend_from_slice(&(48000u32 * 6).to_le_bytes());
+        for &v in &[6, 24, 22, 24] {
+            r.extend_from_slice(&(v as u16).to_le_bytes());
+        }
+        r.extend_from_slice(&3u32.to_le_bytes());
+        r.extend_from_slice(b"\x01\x00\x00\x00\x00\x00\x10\x00\x80\x00\x00\xaa\x00\x38\x9b\x71");
+        r
+    }
+
+    #[test]
+    fn read() {
+        let mut info = b"INFO".to_vec();
+        info.extend_from_slice(&chunk(b"INAM", b"title\0"));
+        info.extend_from_slice(&chunk(b"IART", b"art\xe9\0"));
+        info.extend_from_slice(&chunk(b"ICRD", b"2004-05-03\0"));
+
+        let mut bext = vec![0; 602];
+        bext[..4].copy_from_slice(b"desc");
+        bext[256..259].copy_from_slice(b"org");
+        bext[320..330].copy_from_slice(b"2020:01:02");
+        bext[338..346].copy_from_slice(&96000u64.to_le_bytes());
+        bext[346..348].copy_from_slice(&2u16.to_le_bytes());
+        bext[412..414].copy_from_slice(&(-2300i16).to_le_bytes());
+        bext.extend_from_slice(b"A=PCM\r\n");
+
+        let mut id3 = Vec::new();
+        let mut tag = Id3v2::new(crate::id3::Version::V2_4);
+        tag.frames_mut().set_text(crate::id3::frame::FrameId::TITLE, vec!["id3 title"]);
+        id3.extend_from_slice(&tag.encode(&Default::default()).unwrap());
+
+        let chunks = vec![
+            chunk(b"fmt ", &fmt_extensible()),
+            chunk(b"bext", &bext),
+            chunk(b"LIST", &info),
+            chunk(b"data", &[0; 48000 * 6]),
+            chunk(b"id3 ", &id3),
+        ];
+        let wav = Wav::read(Cursor::new(file(b"RIFF", &chunks))).unwrap();
+        assert_eq!(wav.format(), &WaveFormat {
+            format_tag: FormatTag::EXTENSIBLE,
+            channel_count: 2,
+            samples_per_sec: 48000,
+            bytes_per_sec: 48000 * 6,
+            block_align: 6,
+            bits_per_sample: 24,
+            valid_bits_per_sample: Some(24),
+            channel_mask: Some(3),
+            sub_format: Some(FormatTag::PCM),
+        });
+        assert_eq!(wav.format().kind(), FormatTag::PCM);
+        assert_eq!(wav.data_len(), 48000 * 6);
+        assert_eq!(wav.duration(), Some(Duration::from_secs(1)));
+        assert_eq!(wav.bits_per_sec(), 48000 * 6 * 8);
+
+        let info = wav.info().unwrap();
+        assert_eq!(info.title(), Some("title"));
+        assert_eq!(info.artist(), Some("art\u{e9}"));
+        assert_eq!(info.date(), Some("2004-05-03".parse().unwrap()));
+
+        let bext = wav.bext().unwrap();
+        assert_eq!(bext.description, "desc");
+        assert_eq!(bext.originator, "org");
+        assert_eq!(bext.origination_date, "2020:01:02");
+        assert_eq!(bext.time_reference, 96000);
+        assert_eq!(bext.loudness_value, Some(-2300));
+        assert_eq!(bext.loudness_range, Some(0));
+        assert_eq!(bext.coding_history, "A=PCM\r\n");
+
+        assert_eq!(wav.tags().title(), Some("id3 title".into()));
+    }
+
+    #[test]
+    fn read_bad_optional_chunks() {
+        let mut info = b"INFO".to_vec();
+        info.extend_from_slice(b"INAM\x10\0\0\0title");
+        let chunks = vec![
+            chunk(b"fmt ", &fmt_extensible()),
+            chunk(b"fact", &[1]),
+            chunk(b"bext", &[0; 10]),
+            chunk(b"LIST", &info),
+            chunk(b"data", &[0; 6]),
+        ];
+        let wav = Wav::read(Cursor::new(file(b"RIFF", &chunks))).unwrap();
+        assert_eq!(wav.data_len(), 6);
+        assert!(wav.info().is_none());
+        assert!(wav.bext().is_none());
+    }
+
+    #[test]
+    fn read_rf64() {
+        let mut ds64 = vec![0; 28];
+        ds64[8..16].copy_from_slice(&(48000u64 * 6 * 2).to_le_bytes());
+        let mut data = b"data".to_vec();
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&[0; 48000 * 6]);
+
+        let bytes = file(b"RF64", &[chunk(b"ds64", &ds64), chunk(b"fmt ", &fmt_extensible()), data]);
+        let wav = Wav::read(Cursor::new(&bytes)).unwrap();
+        // The file is truncated so the data len is limited by the file len.
+        assert_eq!(wav.data_len(), 48000 * 6);
+        assert_eq!(wav.duration(), Some(Duration::from_secs(1)));
+
+        assert_eq!(Wav::read(Cursor::new(&b"RIFF\0\0\0\0AVI "[..])).unwrap_err().kind(),
+            io::ErrorKind::InvalidData);
+    }
+}
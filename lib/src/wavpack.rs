@@ -0,0 +1,302 @@
+use bit_field::BitField;
+use byteorder::{ByteOrder, LE};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use crate::ape::Ape;
+use crate::error::*;
+use crate::id3::v1::Id3v1;
+use crate::tags::TagsRef;
+use crate::util::*;
+
+const MAGIC: &[u8] = b"wvpk";
+const HEADER_LEN: usize = 32;
+
+/// Larger blocks are considered corrupted.
+const MAX_BLOCK_LEN: u64 = 16 * 1024 * 1024;
+
+const SAMPLE_RATES: [u32; 15] = [
+    6000, 8000, 9600, 11025, 12000, 16000, 22050, 24000,
+    32000, 44100, 48000, 64000, 88200, 96000, 192000];
+
+// Metadata sub-block IDs.
+const ID_CHANNEL_INFO: u8 = 0x0d;
+const ID_CONFIG_BLOCK: u8 = 0x25;
+const ID_SAMPLE_RATE: u8 = 0x27;
+
+// Encoder config flag stored in `ID_CONFIG_BLOCK`.
+const CONFIG_CREATE_WVC: u32 = 0x80000;
+
+/// Header of a WavPack block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Header {
+    pub version: u16,
+    /// Total samples in the file, `None` if unknown.
+    pub len_samples: Option<u64>,
+    pub block_index: u64,
+    pub block_samples: u32,
+    pub flags: u32,
+}
+
+impl Header {
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if !buf.starts_with(MAGIC) {
+            return Err(Error("bad WavPack block magic"));
+        }
+        let version = LE::read_u16(&buf[8..10]);
+        if !(0x402..=0x410).contains(&version) {
+            return Err(Error("unsupported WavPack version"));
+        }
+        let block_index_hi = buf[10] as u64;
+        let len_samples_hi = buf[11] as u64;
+        let len_samples = LE::read_u32(&buf[12..16]);
+        let len_samples = if len_samples == u32::MAX {
+            None
+        } else {
+            // The high byte is offset by one to keep 0xffffffff meaning unknown.
+            Some((len_samples as u64 + (len_samples_hi << 32)).saturating_sub(len_samples_hi))
+        };
+        Ok(Self {
+            version,
+            len_samples,
+            block_index: LE::read_u32(&buf[16..20]) as u64 | block_index_hi << 32,
+            block_samples: LE::read_u32(&buf[20..24]),
+            flags: LE::read_u32(&buf[24..28]),
+        })
+    }
+
+    pub fn bytes_per_sample(&self) -> u32 {
+        self.flags.get_bits(0..2) + 1
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.flags.get_bit(2)
+    }
+
+    pub fn is_hybrid(&self) -> bool {
+        self.flags.get_bit(3)
+    }
+
+    pub fn is_float(&self) -> bool {
+        self.flags.get_bit(7)
+    }
+
+    pub fn is_dsd(&self) -> bool {
+        self.flags.get_bit(31)
+    }
+
+    /// Left shift applied to the samples when unpacking.
+    fn shift(&self) -> u32 {
+        self.flags.get_bits(13..18)
+    }
+
+    /// `None` if the rate is stored in a metadata sub-block.
+    fn samples_per_sec(&self) -> Option<u32> {
+        SAMPLE_RATES.get(self.flags.get_bits(23..27) as usize).copied()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WavPack {
+    header: Header,
+    samples_per_sec: u32,
+    channel_count: u32,
+    correction_file: bool,
+    duration: Option<Duration>,
+    bits_per_sec: Option<u32>,
+    ape: Option<Ape>,
+    id3v1: Option<Id3v1>,
+}
+
+impl WavPack {
+    pub fn read(mut rd: impl Read + Seek) -> io::Result<Self> {
+        rd.seek(SeekFrom::Start(0))?;
+        let mut buf = [0; HEADER_LEN];
+        rd.read_exact(&mut buf)?;
+        let header = Header::decode(&buf).map_err(|e| e.into_invalid_data_err())?;
+
+        let len = (LE::read_u32(&buf[4..8]) as u64 + 8).saturating_sub(HEADER_LEN as u64);
+        if len > MAX_BLOCK_LEN {
+            return Err(Error("WavPack block is too large").into_invalid_data_err());
+        }
+        let buf = read_vec_limited(&mut Limited::new(&mut rd, len), len as usize,
+            "WavPack block is truncated")?;
+
+        let mut samples_per_sec = header.samples_per_sec();
+        let mut channel_count = if header.is_mono() { 1 } else { 2 };
+        let mut config = 0;
+        let mut buf = &buf[..];
+        while buf.len() >= 2 {
+            let id = buf[0];
+            let (len, hdr_len) = if id.get_bit(7) {
+                if buf.len() < 4 {
+                    break;
+                }
+                ((buf[1] as usize | (buf[2] as usize) << 8 | (buf[3] as usize) << 16) * 2, 4)
+            } else {
+                (buf[1] as usize * 2, 2)
+            };
+            let data = match buf.get(hdr_len..hdr_len + len) {
+                Some(v) => v,
+                None => return Err(Error("WavPack metadata is truncated").into_invalid_data_err()),
+            };
+            buf = &buf[hdr_len + len..];
+            let data = if id.get_bit(6) && len > 0 { &data[..len - 1] } else { data };
+
+            match id & 0x3f {
+                ID_CHANNEL_INFO if !data.is_empty() => {
+                    channel_count = if data.len() >= 6 {
+                        (data[0] as u32 | (data[2] as u32 & 0xf) << 8) + 1
+                    } else {
+                        data[0] as u32
+                    };
+                }
+                ID_SAMPLE_RATE if data.len() >= 3 => {
+                    let mut rate = data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16;
+                    if data.len() >= 4 {
+                        rate |= (data[3] as u32 & 0x7f) << 24;
+                    }
+                    samples_per_sec = Some(rate);
+                }
+                ID_CONFIG_BLOCK if data.len() >= 3 => {
+                    config = (data[0] as u32) << 8 | (data[1] as u32) << 16 | (data[2] as u32) << 24;
+                }
+                _ => {}
+            }
+        }
+        let samples_per_sec = samples_per_sec.filter(|&v| v > 0)
+            .ok_or_else(|| Error("unknown WavPack sample rate").into_invalid_data_err())?;
+        if channel_count == 0 {
+            return Err(Error("bad WavPack channel count").into_invalid_data_err());
+        }
+
+        let (ape, id3v1, audio_end) = Ape::read_trailing(&mut rd)?;
+
+        let duration = header.len_samples.map(|v| duration(v, samples_per_sec));
+        let bits_per_sec = duration.and_then(|d| bits_per_sec(audio_end, d));
+
+        Ok(Self {
+            correction_file: header.is_hybrid() && config & CONFIG_CREATE_WVC != 0,
+            header,
+            samples_per_sec,
+            channel_count,
+            duration,
+            bits_per_sec,
+            ape,
+            id3v1,
+        })
+    }
+
+    /// Header of the first block.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn samples_per_sec(&self) -> u32 {
+        self.samples_per_sec
+    }
+
+    pub fn channel_count(&self) -> u32 {
+        self.channel_count
+    }
+
+    pub fn bits_per_sample(&self) -> u32 {
+        if self.header.is_float() {
+            32
+        } else {
+            (self.header.bytes_per_sample() * 8).saturating_sub(self.header.shift())
+        }
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        !self.header.is_hybrid()
+    }
+
+    /// Whether the encoder created a correction (.wvc) file that restores the lossless
+    /// stream of the hybrid file.
+    pub fn has_correction_file(&self) -> bool {
+        self.correction_file
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    pub fn bits_per_sec(&self) -> Option<u32> {
+        self.bits_per_sec
+    }
+
+    pub fn ape(&self) -> Option<&Ape> {
+        self.ape.as_ref()
+    }
+
+    pub fn id3v1(&self) -> Option<&Id3v1> {
+        self.id3v1.as_ref()
+    }
+
+    pub fn tags(&self) -> TagsRef<'_> {
+        TagsRef {
+            id3v1: self.id3v1.as_ref(),
+            ape: self.ape.as_ref(),
+            .. Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn block(len_samples: u32, flags: u32, metadata: &[u8]) -> Vec<u8> {
+        let mut r = MAGIC.to_vec();
+        r.extend_from_slice(&(HEADER_LEN as u32 - 8 + metadata.len() as u32).to_le_bytes());
+        r.extend_from_slice(&0x410u16.to_le_bytes());
+        r.extend_from_slice(&[0, 0]);
+        r.extend_from_slice(&len_samples.to_le_bytes());
+        r.extend_from_slice(&0u32.to_le_bytes());
+        r.extend_from_slice(&len_samples.to_le_bytes());
+        r.extend_from_slice(&flags.to_le_bytes());
+        r.extend_from_slice(&[0; 4]);
+        r.extend_from_slice(metadata);
+        r
+    }
+
+    #[test]
+    fn read() {
+        // 16-bit stereo 44.1 kHz lossless.
+        let mut bytes = block(88200, 1 | 9 << 23 | 0b11 << 11, &[0x0a, 2, 0, 0, 0, 0]);
+        bytes.resize(44100, 0);
+        bytes.extend_from_slice(&crate::ape::test::tag(&[
+            crate::ape::test::item("Title", b"title", 0)]));
+        let wv = WavPack::read(Cursor::new(&bytes)).unwrap();
+        assert_eq!(wv.samples_per_sec(), 44100);
+        assert_eq!(wv.channel_count(), 2);
+        assert_eq!(wv.bits_per_sample(), 16);
+        assert!(wv.is_lossless());
+        assert!(!wv.has_correction_file());
+        assert_eq!(wv.duration(), Some(Duration::from_secs(2)));
+        assert_eq!(wv.bits_per_sec(), Some(44100 * 4));
+        assert_eq!(wv.tags().title().unwrap(), "title");
+
+        // 24-bit 6 channel 50 kHz hybrid with correction file.
+        let metadata = [
+            0x0d, 1, 6, 0,
+            0x27 | 0x40, 2, 0x50, 0xc3, 0, 0,
+            0x25 | 0x40, 2, 0, 0x08, 0, 0,
+        ];
+        let bytes = block(u32::MAX, 2 | 1 << 3 | 15 << 23, &metadata);
+        let wv = WavPack::read(Cursor::new(&bytes)).unwrap();
+        assert_eq!(wv.samples_per_sec(), 50000);
+        assert_eq!(wv.channel_count(), 6);
+        assert_eq!(wv.bits_per_sample(), 24);
+        assert!(!wv.is_lossless());
+        assert!(wv.has_correction_file());
+        assert_eq!(wv.duration(), None);
+
+        assert_eq!(WavPack::read(Cursor::new(&b"RIFF"[..])).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof);
+    }
+}